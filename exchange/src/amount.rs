@@ -0,0 +1,239 @@
+//! Fixed-point amounts of an asset.
+
+use std::num::NonZeroU64;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A non-zero amount in the smallest unit of an asset, e.g. satoshis for bitcoin.
+///
+/// Amounts carry no decimals themselves, those are a property of the asset (see
+/// [`Asset::decimals`](crate::Asset::decimals)) and only matter when an amount is parsed from or
+/// formatted as a decimal string like `"0.0015"`. Amounts never exceed [`Amount::MAX`] so they
+/// always fit the ledger's `BIGINT` columns.
+///
+/// Amounts are serialized as their integer value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u64", into = "u64")]
+pub struct Amount(NonZeroU64);
+
+/// Error returned when an amount can not be parsed or is out of range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum AmountError {
+    /// the string is not a decimal number.
+    #[error("amounts must be decimal numbers like \"0.0015\"")]
+    Invalid,
+    /// the number has more decimal places than the asset.
+    #[error("amounts can have at most {0} decimal places")]
+    TooManyDecimals(u32),
+    /// the amount is zero.
+    #[error("amounts must not be zero")]
+    Zero,
+    /// the amount is larger than [`Amount::MAX`].
+    #[error("amounts must be at most {}", Amount::MAX)]
+    TooLarge,
+}
+
+impl Amount {
+    /// the smallest amount, one unit.
+    pub const MIN: Amount = Amount(NonZeroU64::MIN);
+
+    /// the largest amount, the largest value of a `BIGINT`.
+    pub const MAX: Amount = match NonZeroU64::new(i64::MAX as u64) {
+        Some(max) => Amount(max),
+        None => unreachable!(),
+    };
+
+    /// create an amount of `units`, `None` if it is zero or larger than [`Amount::MAX`].
+    #[inline]
+    pub const fn new(units: u64) -> Option<Self> {
+        match NonZeroU64::new(units) {
+            Some(units) if units.get() <= Self::MAX.get() => Some(Self(units)),
+            _ => None,
+        }
+    }
+
+    /// the amount in the smallest unit of its asset.
+    #[inline]
+    pub const fn get(self) -> u64 {
+        self.0.get()
+    }
+
+    /// add `units` to the amount, `None` if the sum is larger than [`Amount::MAX`].
+    #[inline]
+    pub fn checked_add(self, units: u64) -> Option<Self> {
+        Self::new(self.get().checked_add(units)?)
+    }
+
+    /// parse a decimal string like `"0.0015"` of an asset with `decimals` decimal places.
+    pub fn parse_decimal(s: &str, decimals: u32) -> Result<Self, AmountError> {
+        Self::try_from(parse_decimal(s, decimals)?)
+    }
+
+    /// format the amount as a decimal string of an asset with `decimals` decimal places.
+    pub fn to_decimal(self, decimals: u32) -> String {
+        format_decimal(self.get(), decimals)
+    }
+}
+
+impl TryFrom<u64> for Amount {
+    type Error = AmountError;
+
+    fn try_from(units: u64) -> Result<Self, Self::Error> {
+        match units {
+            0 => Err(AmountError::Zero),
+            units => Self::new(units).ok_or(AmountError::TooLarge),
+        }
+    }
+}
+
+impl From<Amount> for u64 {
+    fn from(amount: Amount) -> Self {
+        amount.get()
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A decimal number as it is sent to the API, e.g. `"0.0015"`.
+///
+/// It is converted to an [`Amount`] once the asset and so the number of decimal places is known.
+/// Whole numbers may also be sent as JSON numbers, fractions have to be strings so they are not
+/// rounded by a float on the way.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "DecimalRepr", into = "String")]
+pub struct Decimal(String);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DecimalRepr {
+    String(String),
+    Integer(u64),
+}
+
+impl TryFrom<DecimalRepr> for Decimal {
+    type Error = AmountError;
+
+    fn try_from(repr: DecimalRepr) -> Result<Self, Self::Error> {
+        let s = match repr {
+            DecimalRepr::String(s) => s,
+            DecimalRepr::Integer(n) => n.to_string(),
+        };
+
+        // the syntax is checked up-front, the number of decimal places only once the asset is known.
+        split_decimal(&s).ok_or(AmountError::Invalid)?;
+        Ok(Decimal(s))
+    }
+}
+
+impl From<Decimal> for String {
+    fn from(decimal: Decimal) -> Self {
+        decimal.0
+    }
+}
+
+impl Decimal {
+    /// the amount of an asset with `decimals` decimal places.
+    pub fn to_amount(&self, decimals: u32) -> Result<Amount, AmountError> {
+        Amount::parse_decimal(&self.0, decimals)
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// the value of `quantity` at `price` in the quote asset, rounded down.
+///
+/// prices are paid for one whole base asset with `base_decimals` decimal places, values too large
+/// for the ledger are capped at [`Amount::MAX`].
+pub fn notional(price: Amount, quantity: u64, base_decimals: u32) -> u64 {
+    scaled_notional(price, quantity, base_decimals, false)
+}
+
+/// the value of `quantity` at `price` in the quote asset, rounded up.
+pub fn notional_ceil(price: Amount, quantity: u64, base_decimals: u32) -> u64 {
+    scaled_notional(price, quantity, base_decimals, true)
+}
+
+/// how much of the base asset `quote` buys at `price`, rounded down.
+pub fn quantity_for(price: Amount, quote: u64, base_decimals: u32) -> u64 {
+    let scale = 10u128.pow(base_decimals);
+    let quantity = u128::from(quote) * scale / u128::from(price.get());
+
+    quantity.min(u128::from(Amount::MAX.get())) as u64
+}
+
+fn scaled_notional(price: Amount, quantity: u64, base_decimals: u32, round_up: bool) -> u64 {
+    let scale = 10u128.pow(base_decimals);
+    let value = u128::from(price.get()) * u128::from(quantity);
+
+    let notional = if round_up {
+        value.div_ceil(scale)
+    } else {
+        value / scale
+    };
+
+    notional.min(u128::from(Amount::MAX.get())) as u64
+}
+
+/// parse a decimal string like `"0.0015"` into the smallest unit of an asset with `decimals`
+/// decimal places, zero is allowed.
+pub fn parse_decimal(s: &str, decimals: u32) -> Result<u64, AmountError> {
+    let (whole, fraction) = split_decimal(s).ok_or(AmountError::Invalid)?;
+
+    // trailing zeros do not add precision, "1.50" is fine for an asset with a single decimal.
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > decimals as usize {
+        return Err(AmountError::TooManyDecimals(decimals));
+    }
+
+    let scale = 10u64.checked_pow(decimals).ok_or(AmountError::TooLarge)?;
+    let fraction_scale = 10u64.pow(decimals - fraction.len() as u32);
+
+    let parse = |part: &str| match part {
+        "" => Ok(0),
+        part => part.parse::<u64>().map_err(|_| AmountError::TooLarge),
+    };
+
+    let (whole, fraction) = (parse(whole)?, parse(fraction)? * fraction_scale);
+
+    whole
+        .checked_mul(scale)
+        .and_then(|whole| whole.checked_add(fraction))
+        .filter(|&units| units <= Amount::MAX.get())
+        .ok_or(AmountError::TooLarge)
+}
+
+/// split a decimal string into its whole and fractional digits, `None` if it is not one.
+fn split_decimal(s: &str) -> Option<(&str, &str)> {
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    let valid =
+        !(whole.is_empty() && fraction.is_empty()) && is_digits(whole) && is_digits(fraction);
+
+    valid.then_some((whole, fraction))
+}
+
+/// format `units` of the smallest unit of an asset with `decimals` decimal places as a decimal
+/// string, trailing zeros are kept so every amount of an asset has the same number of decimals.
+pub fn format_decimal(units: u64, decimals: u32) -> String {
+    let scale = 10u64.pow(decimals);
+
+    match decimals {
+        0 => units.to_string(),
+        _ => format!(
+            "{}.{:0width$}",
+            units / scale,
+            units % scale,
+            width = decimals as usize
+        ),
+    }
+}
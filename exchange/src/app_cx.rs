@@ -8,13 +8,13 @@
 //! example, instead of calling `te_tx.send(TradingEngineCmd::PlaceOrder { .. })`
 //! you would call `app.place_order(..)`.
 //!
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::IpAddr;
-use std::num::NonZeroU64;
+use std::num::{NonZeroU32, NonZeroU64};
 use std::path::Path;
 use std::str::FromStr as _;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use argon2::password_hash::PasswordHashString;
 use argon2::{Argon2, PasswordHasher, PasswordVerifier as _};
@@ -29,15 +29,21 @@ use thiserror::Error;
 use tokio::sync::oneshot;
 use uuid::Uuid;
 
-use crate::asset::{internal_asset_list, AssetKey};
+use crate::amount::{AmountError, Decimal};
+use crate::asset::{MarketListing, MarketStatus, Symbol};
 use crate::bitcoin::BitcoinRpcClient;
+use crate::ledger;
+use crate::market_data::MarketData;
 use crate::password::Password;
 use crate::trading::{
-    CancelOrder, OrderSide, OrderUuid, PlaceOrder, PlaceOrderResult, TeResponse as Response,
-    TradeCmd, TradingEngineCmd, TradingEngineError, TradingEngineTx,
+    AmendOrder, AmendOrderResult, BookOrders, CancelAll, CancelOrder, CancelOrderResult,
+    DepthSnapshot, EngineEventRx, EngineEventTx, MarketRuleError, MarketRules, OcoGroupUuid,
+    OpenOrder, OrderSide, OrderType, OrderUuid, PlaceOco, PlaceOcoResult, PlaceOrder,
+    PlaceOrderResult, TeResponse as Response, TimeInForce, TradeCmd, TradingEngineCmd,
+    TradingEngineError, TradingEngineTx,
 };
 use crate::web::TradeAddOrder;
-use crate::{Asset, Configuration};
+use crate::{Amount, Asset, Configuration, Market};
 
 mod defer_guard;
 pub use defer_guard::{defer, DeferGuard};
@@ -48,6 +54,9 @@ pub use reserve_ok::ReserveOk;
 struct Inner {
     te_state: Atomic<TradingEngineState>,
     jinja: crate::jinja::Jinja,
+    assets: RwLock<BTreeMap<Symbol, Asset>>,
+    markets: RwLock<BTreeMap<Market, MarketListing>>,
+    market_data: MarketData,
 }
 
 #[derive(Debug, Error)]
@@ -73,6 +82,50 @@ pub enum PlaceOrderError {
     TradingEngineUnresponsive,
     #[error("insufficient funds")]
     InsufficientFunds,
+    #[error("good-til-date orders require an expire_at in the future")]
+    InvalidExpireAt,
+    #[error("stop orders require a stop_price and other orders must not have one")]
+    InvalidStopPrice,
+    #[error("post-only orders must be good-til-canceled or good-til-date limit orders")]
+    InvalidPostOnly,
+    #[error("iceberg orders must be good-til-canceled or good-til-date limit orders displaying less than their quantity")]
+    InvalidDisplayQuantity,
+    #[error("quote amounts are only allowed on immediate-or-cancel market buys")]
+    InvalidQuoteAmount,
+    #[error(
+        "protection prices and slippage limits are only allowed on market and stop-market orders"
+    )]
+    InvalidProtection,
+    #[error("market {0} is not traded")]
+    UnknownMarket(Market),
+    #[error("{0}")]
+    InvalidAmount(#[from] AmountError),
+    #[error("{0}")]
+    MarketRule(#[from] MarketRuleError),
+}
+
+#[derive(Debug, Error)]
+pub enum ListMarketError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+    #[error("market {0} is already listed")]
+    AlreadyListed(Market),
+    #[error("a market needs two different assets")]
+    SameAsset,
+    #[error("the maximum quantity is below the minimum quantity")]
+    InvalidRules,
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum ListAssetError {
+    #[error("asset {0} is already listed")]
+    AlreadyListed(Symbol),
+    #[error("assets can have at most {} decimal places", Asset::MAX_DECIMALS)]
+    TooManyDecimals,
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
 }
 
 #[derive(Debug, Error)]
@@ -89,6 +142,14 @@ pub enum CancelOrderError {
     TradingEngineUnresponsive,
 }
 
+#[derive(Debug, Error)]
+pub enum AmendOrderError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+    #[error("{0}")]
+    InvalidAmount(#[from] AmountError),
+}
+
 #[derive(Debug, Error)]
 pub enum CreateUserError {
     #[error("password hash error")]
@@ -133,6 +194,37 @@ pub struct UserDetails {
     portfolio: UserPortfolio,
 }
 
+/// A row of the `trades` table.
+#[derive(Debug, Clone)]
+pub struct TradeRecord {
+    pub id: i64,
+    pub market: Market,
+    pub price: Amount,
+    pub quantity: u64,
+    pub taker_side: OrderSide,
+    pub maker_order_uuid: OrderUuid,
+    pub maker_user_uuid: Uuid,
+    pub taker_order_uuid: OrderUuid,
+    pub taker_user_uuid: Uuid,
+    pub maker_fee: u64,
+    pub taker_fee: u64,
+    /// the unix timestamp (in seconds) of the trade.
+    pub timestamp: i64,
+}
+
+/// Which trades of a user to list, newest first.
+#[derive(Debug, Clone, Copy)]
+pub struct TradeFilter {
+    pub market: Option<Market>,
+    /// the earliest unix timestamp (in seconds).
+    pub start: Option<i64>,
+    /// the unix timestamp (in seconds) trades must be before.
+    pub end: Option<i64>,
+    /// the id trades must be below, the last id of the previous page.
+    pub before: Option<i64>,
+    pub limit: i64,
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[repr(u64)]
 enum TradingEngineState {
@@ -151,6 +243,8 @@ pub struct UserAccount {}
 pub struct AppCx {
     /// a mpsc sender to the trading engine supervisor.
     te_tx: TradingEngineTx,
+    /// a broadcast sender of the trading engine's execution reports.
+    te_events: EngineEventTx,
     /// a client for the bitcoin core rpc.
     pub(crate) bitcoind_rpc: BitcoinRpcClient,
     /// a pool of connections to the database.
@@ -159,8 +253,6 @@ pub struct AppCx {
     inner_ro: Arc<Inner>,
     /// The service configuration
     config: Configuration,
-    /// The list of active assets
-    pub(crate) assets: &'static [(AssetKey, Asset)],
 }
 
 impl std::fmt::Debug for Inner {
@@ -168,6 +260,9 @@ impl std::fmt::Debug for Inner {
         f.debug_struct("Inner")
             .field("te_state", &self.te_state)
             .field("jinja", &"")
+            .field("assets", &self.assets)
+            .field("markets", &self.markets)
+            .field("market_data", &self.market_data)
             .finish()
     }
 }
@@ -175,20 +270,36 @@ impl std::fmt::Debug for Inner {
 impl AppCx {
     pub fn new(
         te_tx: TradingEngineTx,
+        te_events: EngineEventTx,
         btc_rpc: BitcoinRpcClient,
         db: sqlx::PgPool,
         jinja: crate::jinja::Jinja,
         config: Configuration,
+        assets: Vec<Asset>,
+        markets: Vec<MarketListing>,
     ) -> Self {
         Self {
             te_tx,
+            te_events,
             bitcoind_rpc: btc_rpc,
             db,
             inner_ro: Arc::new(Inner {
                 te_state: Atomic::new(TradingEngineState::Running),
                 jinja,
+                assets: RwLock::new(
+                    assets
+                        .into_iter()
+                        .map(|asset| (asset.symbol(), asset))
+                        .collect(),
+                ),
+                markets: RwLock::new(
+                    markets
+                        .into_iter()
+                        .map(|listing| (listing.market, listing))
+                        .collect(),
+                ),
+                market_data: MarketData::new(config.market_data_channel_capacity),
             }),
-            assets: internal_asset_list(),
             config,
         }
     }
@@ -205,6 +316,62 @@ impl AppCx {
         self.db.clone()
     }
 
+    /// subscribe to the execution reports of the trading engine, see [`crate::trading::engine_event`].
+    pub fn subscribe_engine_events(&self) -> EngineEventRx {
+        self.te_events.subscribe()
+    }
+
+    /// ask the trading engine for the depth of every orderbook, `None` if it does not answer.
+    pub async fn depth_snapshot(&self) -> Option<DepthSnapshot> {
+        let (tx, rx) = oneshot::channel();
+
+        if let Err(err) = self.te_tx.send(TradingEngineCmd::Depth(tx)).await {
+            tracing::warn!(?err, "failed to send depth command to trading engine");
+            return None;
+        }
+
+        rx.await.ok()
+    }
+
+    /// ask the trading engine for the orders of the best `levels` price levels of a market,
+    /// `None` if the market is not traded or the engine does not answer.
+    pub async fn book(&self, market: Market, levels: usize) -> Option<BookOrders> {
+        let (tx, rx) = oneshot::channel();
+
+        if let Err(err) = self
+            .te_tx
+            .send(TradingEngineCmd::Book((market, levels, tx)))
+            .await
+        {
+            tracing::warn!(?err, "failed to send book command to trading engine");
+            return None;
+        }
+
+        rx.await.ok().flatten()
+    }
+
+    /// ask the trading engine for the resting orders of a user, `None` if the engine does not
+    /// answer.
+    pub async fn open_orders(&self, user_uuid: Uuid) -> Option<Vec<OpenOrder>> {
+        let (tx, rx) = oneshot::channel();
+
+        if let Err(err) = self
+            .te_tx
+            .send(TradingEngineCmd::OpenOrders((user_uuid, tx)))
+            .await
+        {
+            tracing::warn!(?err, "failed to send open orders command to trading engine");
+            return None;
+        }
+
+        rx.await.ok()
+    }
+
+    /// the public market data, kept up to date by [`crate::market_data::run`].
+    pub fn market_data(&self) -> &MarketData {
+        &self.inner_ro.market_data
+    }
+
     pub fn trading_engine_state(&self) -> TradingEngineState {
         self.inner_ro.te_state.load(Ordering::Relaxed)
     }
@@ -212,6 +379,37 @@ impl AppCx {
     pub fn set_trading_engine_state(&self, state: TradingEngineState) {
         self.inner_ro.te_state.store(state, Ordering::SeqCst)
     }
+
+    /// the listed asset with the symbol, e.g. `btc` or `BTC`.
+    pub fn asset(&self, symbol: &str) -> Option<Asset> {
+        let symbol = Symbol::new(symbol)?;
+        let assets = self.inner_ro.assets.read().unwrap();
+        assets.get(&symbol).copied()
+    }
+
+    /// the market with the id, e.g. `BTC-USD`, if both of its assets are listed. the market does
+    /// not have to be listed itself, see [`Market::parse`].
+    pub fn market(&self, id: &str) -> Option<Market> {
+        Market::parse(id, |symbol| self.asset(symbol))
+    }
+
+    /// every listed market, including halted ones.
+    pub fn markets(&self) -> Vec<MarketListing> {
+        let markets = self.inner_ro.markets.read().unwrap();
+        markets.values().copied().collect()
+    }
+
+    /// the prices and quantities `market` accepts, if it is listed.
+    pub fn market_rules(&self, market: Market) -> Option<MarketRules> {
+        let markets = self.inner_ro.markets.read().unwrap();
+        markets.get(&market).map(|listing| listing.rules)
+    }
+
+    /// whether orders are accepted for `market`.
+    pub fn is_market_traded(&self, market: Market) -> bool {
+        let markets = self.inner_ro.markets.read().unwrap();
+        matches!(markets.get(&market), Some(listing) if listing.status == MarketStatus::Active)
+    }
 }
 
 impl AppCx {
@@ -372,7 +570,7 @@ impl AppCx {
                 .fetch_all(&mut *db)
                 .await?
                 .into_iter()
-                .map(|rec| (rec.txid.clone(), rec))
+                .filter_map(|rec| Some((rec.txid.clone()?, rec)))
                 .collect::<HashMap<_, _>>();
 
             let txs = cx
@@ -447,18 +645,88 @@ impl AppCx {
         Ok(details)
     }
 
+    /// the trades the user was the maker or the taker of.
+    pub async fn user_trades(
+        &self,
+        user_id: Uuid,
+        filter: TradeFilter,
+    ) -> Result<Vec<TradeRecord>, sqlx::Error> {
+        let recs = sqlx::query!(
+            r#"
+            SELECT t.id, t.market, m.base, b.decimals AS base_decimals, m.quote,
+                q.decimals AS quote_decimals, t.price, t.quantity, t.taker_side,
+                t.maker_order_uuid, t.maker_user_uuid, t.taker_order_uuid, t.taker_user_uuid,
+                t.maker_fee, t.taker_fee, t.created_at
+            FROM trades t
+            JOIN markets m ON m.symbol = t.market
+            JOIN assets b ON b.symbol = m.base
+            JOIN assets q ON q.symbol = m.quote
+            WHERE (t.maker_user_uuid = $1 OR t.taker_user_uuid = $1)
+                AND ($2::VARCHAR IS NULL OR t.market = $2)
+                AND ($3::BIGINT IS NULL OR t.created_at >= to_timestamp($3))
+                AND ($4::BIGINT IS NULL OR t.created_at < to_timestamp($4))
+                AND ($5::BIGINT IS NULL OR t.id < $5)
+            ORDER BY t.id DESC
+            LIMIT $6
+            "#,
+            user_id,
+            filter.market.map(|market| market.to_string()),
+            filter.start,
+            filter.end,
+            filter.before,
+            filter.limit,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        recs.into_iter()
+            .filter_map(|rec| {
+                let Some(market) =
+                    Market::from_row(&rec.base, rec.base_decimals, &rec.quote, rec.quote_decimals)
+                else {
+                    tracing::warn!(id = rec.id, market = rec.market, "trade of unknown market");
+                    return None;
+                };
+
+                Some(Ok(TradeRecord {
+                    id: rec.id,
+                    market,
+                    price: Amount::new(rec.price as u64)?,
+                    quantity: rec.quantity as u64,
+                    taker_side: match rec.taker_side.as_str() {
+                        "buy" => OrderSide::Buy,
+                        "sell" => OrderSide::Sell,
+                        taker_side => {
+                            return Some(Err(sqlx::Error::Decode(
+                                format!("unknown taker side {taker_side:?} of trade {}", rec.id)
+                                    .into(),
+                            )))
+                        }
+                    },
+                    maker_order_uuid: OrderUuid(rec.maker_order_uuid),
+                    maker_user_uuid: rec.maker_user_uuid,
+                    taker_order_uuid: OrderUuid(rec.taker_order_uuid),
+                    taker_user_uuid: rec.taker_user_uuid,
+                    maker_fee: rec.maker_fee as u64,
+                    taker_fee: rec.taker_fee as u64,
+                    timestamp: rec.created_at.unix_timestamp(),
+                }))
+            })
+            .collect()
+    }
+
     pub async fn reserve_by_asset(
         &self,
         user_uuid: Uuid,
-        quantity: std::num::NonZeroU32,
+        amount: NonZeroU64,
         currency: &str,
     ) -> Result<ReserveOk, ReserveError> {
-        let balance = self
-            .calculate_balance_from_accounting(user_uuid, currency)
-            .await?;
+        // the balance stays locked until the reservation is committed, so it is not spent twice.
+        let mut tx = self.db.begin().await?;
+        let balance = ledger::lock_balance(&mut tx, user_uuid, currency).await?;
 
-        let balance = match balance {
-            Some(i) if i.get() >= quantity.get() as u64 => i,
+        let balance = match NonZeroU64::new(balance) {
+            Some(i) if i.get() >= amount.get() => i,
             _ => return Err(ReserveError::InsufficientFunds),
         };
 
@@ -467,18 +735,20 @@ impl AppCx {
             r#"
             INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
                 (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = $3),
-                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $2),
+                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $2 AND currency = $3),
                 $3,
                 $1,
                 'reserve asset'
             ) RETURNING id
             "#,
-            quantity.get() as i64,
+            amount.get() as i64,
             user_uuid.to_string(),
             currency,
-        ).fetch_one(&self.db).await?;
+        ).fetch_one(&mut *tx).await?;
 
-        tracing::trace!(id = ?rec.id, %user_uuid, "reserved USD fiat from user account");
+        tx.commit().await?;
+
+        tracing::trace!(id = ?rec.id, %user_uuid, %currency, "reserved funds from user account");
 
         let new_balance = self
             .calculate_balance_from_accounting(user_uuid, currency)
@@ -494,16 +764,13 @@ impl AppCx {
         })
     }
 
-    pub async fn place_order(
+    /// validate an order and reserve the funds it holds.
+    async fn reserve_order(
         &self,
-        asset: Asset,
+        market: Market,
         user_uuid: uuid::Uuid,
         trade_add_order: TradeAddOrder,
-    ) -> Result<(Response<PlaceOrderResult>, ReserveOk), PlaceOrderError> {
-        if !matches!(self.trading_engine_state(), TradingEngineState::Running) {
-            return Err(PlaceOrderError::TradingEngineUnresponsive);
-        }
-
+    ) -> Result<(PlaceOrder, ReserveOk), PlaceOrderError> {
         let TradeAddOrder {
             side,
             order_type,
@@ -511,37 +778,127 @@ impl AppCx {
             quantity,
             price,
             time_in_force,
+            expire_at,
+            stop_price,
+            post_only,
+            display_quantity,
+            quote_amount,
+            protection_price,
+            max_slippage_bps,
         } = trade_add_order;
 
-        let reserve = match side {
-            OrderSide::Buy => self.reserve_by_asset(user_uuid, quantity, "USD").await?,
-            OrderSide::Sell => {
-                self.reserve_by_asset(
-                    user_uuid,
-                    quantity,
-                    match asset {
-                        Asset::Bitcoin => "BTC",
-                        Asset::Ether => "ETH",
-                    },
-                )
-                .await?
+        // prices are amounts of the quote asset, quantities amounts of the base asset.
+        let (base, quote) = (market.base.decimals(), market.quote.decimals());
+        let price = price.to_amount(quote)?;
+        let quantity = quantity.to_amount(base)?;
+        let stop_price = stop_price.map(|p| p.to_amount(quote)).transpose()?;
+        let display_quantity = display_quantity.map(|q| q.to_amount(base)).transpose()?;
+        let quote_amount = quote_amount.map(|q| q.to_amount(quote)).transpose()?;
+        let protection_price = protection_price.map(|p| p.to_amount(quote)).transpose()?;
+
+        let expire_at = match (time_in_force, expire_at) {
+            (TimeInForce::GoodTilDate, Some(t)) if t > chrono::Utc::now().timestamp() as u64 => {
+                Some(t)
             }
+            (TimeInForce::GoodTilDate, _) => return Err(PlaceOrderError::InvalidExpireAt),
+            (_, Some(_)) => return Err(PlaceOrderError::InvalidExpireAt),
+            (_, None) => None,
         };
 
-        tracing::trace!(?reserve.previous_balance, ?reserve.new_balance, "marked funds as reserved");
+        if order_type.is_stop() != stop_price.is_some() {
+            return Err(PlaceOrderError::InvalidStopPrice);
+        }
+
+        let resting = matches!(order_type, OrderType::Limit | OrderType::StopLimit)
+            && matches!(
+                time_in_force,
+                TimeInForce::GoodTilCanceled | TimeInForce::GoodTilDate
+            );
+        if post_only.is_some() && !resting {
+            return Err(PlaceOrderError::InvalidPostOnly);
+        }
+
+        if let Some(display_quantity) = display_quantity {
+            if !resting || display_quantity >= quantity {
+                return Err(PlaceOrderError::InvalidDisplayQuantity);
+            }
+        }
+
+        let market_order = matches!(order_type, OrderType::Market | OrderType::StopMarket);
+        if (protection_price.is_some() || max_slippage_bps.is_some()) && !market_order {
+            return Err(PlaceOrderError::InvalidProtection);
+        }
+
+        let market_buy_ioc = matches!(
+            (order_type, side, time_in_force),
+            (
+                OrderType::Market,
+                OrderSide::Buy,
+                TimeInForce::ImmediateOrCancel
+            )
+        );
+        if quote_amount.is_some() && !market_buy_ioc {
+            return Err(PlaceOrderError::InvalidQuoteAmount);
+        }
 
-        let (place_order_tx, wait_response) = oneshot::channel();
         let place_order = PlaceOrder::new(
-            asset,
+            market,
             user_uuid,
+            OrderUuid::new_v4(),
             price,
             quantity,
             order_type,
             stp,
             time_in_force,
             side,
+            expire_at,
+            stop_price,
+            post_only,
+            display_quantity,
+            quote_amount,
+            protection_price,
+            max_slippage_bps,
         );
 
+        // the engine checks the rules again, they are checked here so nothing is reserved for
+        // orders it would reject.
+        let Some(rules) = self.market_rules(market) else {
+            return Err(PlaceOrderError::UnknownMarket(market));
+        };
+        rules.check_order(&place_order)?;
+
+        // a buy by quote amount reserves exactly what it may spend.
+        let amount = match quote_amount {
+            Some(quote_amount) => quote_amount.get(),
+            None => ledger::reserve_amount(market, side, order_type, price, quantity),
+        };
+        let Some(amount) = NonZeroU64::new(amount) else {
+            return Err(PlaceOrderError::InsufficientFunds);
+        };
+
+        let currency = ledger::reserve_currency(market, side);
+        let reserve = self.reserve_by_asset(user_uuid, amount, &currency).await?;
+
+        tracing::trace!(?reserve.previous_balance, ?reserve.new_balance, "marked funds as reserved");
+
+        Ok((place_order, reserve))
+    }
+
+    pub async fn place_order(
+        &self,
+        market: Market,
+        user_uuid: uuid::Uuid,
+        trade_add_order: TradeAddOrder,
+    ) -> Result<(Response<PlaceOrderResult>, ReserveOk), PlaceOrderError> {
+        if !matches!(self.trading_engine_state(), TradingEngineState::Running) {
+            return Err(PlaceOrderError::TradingEngineUnresponsive);
+        }
+
+        let (place_order, reserve) = self
+            .reserve_order(market, user_uuid, trade_add_order)
+            .await?;
+
+        let (place_order_tx, wait_response) = oneshot::channel();
         let cmd = TradeCmd::PlaceOrder((place_order, place_order_tx));
 
         match self.te_tx.send(TradingEngineCmd::Trade(cmd)).await {
@@ -556,11 +913,53 @@ impl AppCx {
         }
     }
 
+    /// place two linked orders, funds are reserved for both of them until one is cancelled.
+    pub async fn place_oco(
+        &self,
+        market: Market,
+        user_uuid: uuid::Uuid,
+        [first, second]: [TradeAddOrder; 2],
+    ) -> Result<(Response<PlaceOcoResult>, [ReserveOk; 2]), PlaceOrderError> {
+        if !matches!(self.trading_engine_state(), TradingEngineState::Running) {
+            return Err(PlaceOrderError::TradingEngineUnresponsive);
+        }
+
+        let (first, first_reserve) = self.reserve_order(market, user_uuid, first).await?;
+        let (second, second_reserve) = match self.reserve_order(market, user_uuid, second).await {
+            Ok(r) => r,
+            Err(err) => {
+                if let Err(err) = first_reserve.revert(&self.db).await {
+                    tracing::error!(?err, "failed to revert reserve");
+                }
+                return Err(err);
+            }
+        };
+        let reserves = [first_reserve, second_reserve];
+
+        let (place_oco_tx, wait_response) = oneshot::channel();
+        let place_oco = PlaceOco::new(OcoGroupUuid::new_v4(), [first, second]);
+
+        let cmd = TradeCmd::PlaceOco((place_oco, place_oco_tx));
+
+        match self.te_tx.send(TradingEngineCmd::Trade(cmd)).await {
+            Ok(()) => Ok((Response(wait_response), reserves)),
+            Err(err) => {
+                tracing::warn!(?err, "failed to send place oco command to trading engine");
+                for reserve in reserves {
+                    if let Err(err) = reserve.revert(&self.db).await {
+                        tracing::error!(?err, "failed to revert reserve");
+                    }
+                }
+                Err(PlaceOrderError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
     pub async fn cancel_order(
         &self,
         user_uuid: Uuid,
         order_uuid: Uuid,
-    ) -> Result<Response<()>, CancelOrderError> {
+    ) -> Result<Response<CancelOrderResult>, CancelOrderError> {
         // Running and ReduceOnly are the only states where we can cancel orders.
         if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
             return Err(CancelOrderError::TradingEngineUnresponsive);
@@ -583,6 +982,186 @@ impl AppCx {
         }
     }
 
+    /// cancel every order of the user, optionally only those of `market` or `side`.
+    pub async fn cancel_all(
+        &self,
+        user_uuid: Uuid,
+        market: Option<Market>,
+        side: Option<OrderSide>,
+    ) -> Result<Response<Vec<CancelOrderResult>>, CancelOrderError> {
+        // Running and ReduceOnly are the only states where we can cancel orders.
+        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
+            return Err(CancelOrderError::TradingEngineUnresponsive);
+        }
+
+        let (cancel_all_tx, wait_response) = oneshot::channel();
+        let cancel_all = CancelAll::new(user_uuid, market, side);
+
+        let cmd = TradeCmd::CancelAll((cancel_all, cancel_all_tx));
+
+        match self.te_tx.send(TradingEngineCmd::Trade(cmd)).await {
+            Ok(()) => Ok(Response(wait_response)),
+            Err(err) => {
+                tracing::warn!(?err, "failed to send cancel all command to trading engine");
+                Err(CancelOrderError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
+    pub async fn amend_order(
+        &self,
+        user_uuid: Uuid,
+        market: Market,
+        order_uuid: Uuid,
+        price: Decimal,
+        quantity: Decimal,
+    ) -> Result<Response<AmendOrderResult>, AmendOrderError> {
+        // amendments may increase an order's exposure so they are only accepted while running.
+        if !matches!(self.trading_engine_state(), TradingEngineState::Running) {
+            return Err(AmendOrderError::TradingEngineUnresponsive);
+        }
+
+        let price = price.to_amount(market.quote.decimals())?;
+        let quantity = quantity.to_amount(market.base.decimals())?;
+
+        let (amend_order_tx, wait_response) = oneshot::channel();
+        let amend_order =
+            AmendOrder::new(user_uuid, market, OrderUuid(order_uuid), price, quantity);
+
+        let cmd = TradeCmd::AmendOrder((amend_order, amend_order_tx));
+
+        match self.te_tx.send(TradingEngineCmd::Trade(cmd)).await {
+            Ok(()) => Ok(Response(wait_response)),
+            Err(err) => {
+                tracing::warn!(?err, "failed to send amend order command to trading engine");
+                Err(AmendOrderError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
+    /// list a new asset, recording it in the `assets` table together with the exchange's account
+    /// that holds the reserved funds of the asset. markets of the asset can be listed afterwards.
+    pub async fn list_asset(&self, symbol: Symbol, decimals: u8) -> Result<Asset, ListAssetError> {
+        if decimals > Asset::MAX_DECIMALS {
+            return Err(ListAssetError::TooManyDecimals);
+        }
+
+        let mut tx = self.db.begin().await?;
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO assets (symbol, decimals)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            symbol.as_str(),
+            i16::from(decimals),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if inserted.rows_affected() == 0 {
+            return Err(ListAssetError::AlreadyListed(symbol));
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO accounts (currency, source_type, source_id)
+            VALUES ($1, 'fiat', 'exchange')
+            ON CONFLICT DO NOTHING
+            "#,
+            symbol.as_str(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        let asset = Asset::new(symbol, decimals);
+        let mut assets = self.inner_ro.assets.write().unwrap();
+        assets.insert(symbol, asset);
+
+        Ok(asset)
+    }
+
+    /// list a new market, recording it in the `markets` table and then creating its orderbook
+    /// in the running trading engine.
+    pub async fn list_market(
+        &self,
+        market: Market,
+        rules: MarketRules,
+    ) -> Result<MarketListing, ListMarketError> {
+        if market.base == market.quote {
+            return Err(ListMarketError::SameAsset);
+        }
+
+        if matches!(rules.max_quantity, Some(max_quantity) if max_quantity < rules.min_quantity) {
+            return Err(ListMarketError::InvalidRules);
+        }
+
+        // the row is only committed once the engine was sent the market, a concurrent listing of
+        // the same market waits for the outcome.
+        let mut tx = self.db.begin().await?;
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO markets (symbol, base, quote,
+                tick_size, lot_size, min_quantity, max_quantity, min_notional)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT DO NOTHING
+            "#,
+            market.to_string(),
+            market.base.to_string(),
+            market.quote.to_string(),
+            rules.tick_size.get() as i64,
+            rules.lot_size.get() as i64,
+            rules.min_quantity.get() as i64,
+            rules
+                .max_quantity
+                .map(|max_quantity| max_quantity.get() as i64),
+            rules.min_notional as i64,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if inserted.rows_affected() == 0 {
+            return Err(ListMarketError::AlreadyListed(market));
+        }
+
+        // the engine processes commands in order, so orders accepted after the market is added
+        // to the registry below always find its orderbook.
+        let cmd = TradingEngineCmd::ListMarket((market, rules));
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(?err, "failed to send list market command to trading engine");
+            return Err(ListMarketError::TradingEngineUnresponsive);
+        }
+
+        // orders are only accepted once the market is in the registry, so an orderbook left
+        // without its row when the commit fails is never traded.
+        tx.commit().await?;
+
+        let listing = MarketListing {
+            market,
+            status: MarketStatus::Active,
+            rules,
+        };
+
+        let mut markets = self.inner_ro.markets.write().unwrap();
+        markets.insert(market, listing);
+
+        Ok(listing)
+    }
+
+    /// whether the user is an administrator.
+    pub async fn is_admin(&self, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"SELECT role as "role: String" FROM users WHERE id = $1"#,
+            user_id
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(matches!(rec, Some(rec) if rec.role == "admin"))
+    }
+
     pub async fn create_user(
         &self,
         name: &str,
@@ -698,6 +1277,7 @@ impl AppCx {
 
 #[cfg(test)]
 mod test {
+    use crate::asset::fixtures;
     use crate::jinja::make_jinja_env;
     use crate::spawn_trading_engine::spawn_trading_engine;
 
@@ -705,16 +1285,20 @@ mod test {
 
     async fn make_app_cx_fixture(db: sqlx::PgPool) -> AppCx {
         let config = Configuration::load_from_toml("");
-        let (te_tx, te_handle) = spawn_trading_engine(&config, db.clone())
-            .init_from_db(db.clone())
-            .await
-            .unwrap();
+        let te = spawn_trading_engine(&config, db.clone());
+        let te_events = te.events.clone();
+        let (te_tx, te_handle) = te.init_from_db(db.clone()).await.unwrap();
+        let assets = crate::asset::fetch_assets(&db).await.unwrap();
+        let markets = crate::asset::fetch_markets(&db).await.unwrap();
         AppCx::new(
             te_tx,
+            te_events,
             BitcoinRpcClient::new_mock(),
             db,
             make_jinja_env(&config),
             config,
+            assets,
+            markets,
         )
     }
 
@@ -744,6 +1328,348 @@ mod test {
         }
     }
 
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_list_market_at_runtime(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture(db.clone()).await;
+        let btc_eth = Market::new(fixtures::BTC, fixtures::ETH);
+
+        assert!(app_cx.is_market_traded(Market::new(fixtures::BTC, fixtures::USD)));
+        assert!(!app_cx.is_market_traded(btc_eth));
+
+        let listing = app_cx
+            .list_market(btc_eth, MarketRules::default())
+            .await
+            .unwrap();
+        assert_eq!(listing.status, MarketStatus::Active);
+        assert!(app_cx.is_market_traded(btc_eth));
+
+        assert!(matches!(
+            app_cx.list_market(btc_eth, MarketRules::default()).await,
+            Err(ListMarketError::AlreadyListed(market)) if market == btc_eth
+        ));
+
+        // the running engine accepts orders for the new market.
+        let (tx, rx) = oneshot::channel();
+        let place_order = PlaceOrder::new(
+            btc_eth,
+            Uuid::new_v4(),
+            OrderUuid::new_v4(),
+            Amount::new(10).unwrap(),
+            Amount::new(1).unwrap(),
+            OrderType::Limit,
+            Default::default(),
+            TimeInForce::GoodTilCanceled,
+            OrderSide::Buy,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        app_cx
+            .te_tx
+            .send(TradingEngineCmd::Trade(TradeCmd::PlaceOrder((
+                place_order,
+                tx,
+            ))))
+            .await
+            .unwrap();
+        let placed = rx.await.unwrap().unwrap();
+        assert_eq!(placed.market, btc_eth);
+
+        // and the listing is loaded again after a restart.
+        let markets = crate::asset::fetch_markets(&db).await.unwrap();
+        assert!(markets.contains(&listing));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_list_asset_at_runtime(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture(db.clone()).await;
+        let sol = Symbol::new("SOL").unwrap();
+
+        assert!(app_cx.asset("sol").is_none());
+        assert!(app_cx.market("SOL-USD").is_none());
+
+        let asset = app_cx.list_asset(sol, 9).await.unwrap();
+        assert_eq!(app_cx.asset("sol"), Some(asset));
+
+        assert!(matches!(
+            app_cx.list_asset(sol, 9).await,
+            Err(ListAssetError::AlreadyListed(symbol)) if symbol == sol
+        ));
+        assert!(matches!(
+            app_cx.list_asset(Symbol::new("XYZ").unwrap(), 19).await,
+            Err(ListAssetError::TooManyDecimals)
+        ));
+
+        // the exchange has an account to hold the reserved funds of the new asset.
+        let accounts = sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM accounts
+            WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = 'SOL'"
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(accounts, Some(1));
+
+        // markets of the new asset can be listed.
+        let sol_usd = app_cx.market("SOL-USD").unwrap();
+        app_cx
+            .list_market(sol_usd, MarketRules::default())
+            .await
+            .unwrap();
+        assert!(app_cx.is_market_traded(sol_usd));
+
+        // and the asset is loaded again after a restart.
+        let assets = crate::asset::fetch_assets(&db).await.unwrap();
+        assert!(assets.contains(&asset));
+    }
+
+    async fn place_limit_order(
+        app_cx: &AppCx,
+        market: Market,
+        side: OrderSide,
+        price: u64,
+        quantity: u64,
+    ) -> PlaceOrderResult {
+        let (tx, rx) = oneshot::channel();
+        let place_order = PlaceOrder::new(
+            market,
+            Uuid::new_v4(),
+            OrderUuid::new_v4(),
+            Amount::new(price).unwrap(),
+            Amount::new(quantity).unwrap(),
+            OrderType::Limit,
+            Default::default(),
+            TimeInForce::GoodTilCanceled,
+            side,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        app_cx
+            .te_tx
+            .send(TradingEngineCmd::Trade(TradeCmd::PlaceOrder((
+                place_order,
+                tx,
+            ))))
+            .await
+            .unwrap();
+        rx.await.unwrap().unwrap()
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_market_data_feed(db: sqlx::PgPool) {
+        use crate::market_data::{self, MarketDataBody, MarketDataMsg};
+
+        async fn next(
+            rx: &mut tokio::sync::broadcast::Receiver<MarketDataMsg>,
+            market: Market,
+        ) -> MarketDataMsg {
+            loop {
+                let msg = rx.recv().await.unwrap();
+                if msg.market == market {
+                    return msg;
+                }
+            }
+        }
+
+        let app_cx = make_app_cx_fixture(db.clone()).await;
+        let btc_usd = Market::new(fixtures::BTC, fixtures::USD);
+        const BTC: u64 = 100_000_000;
+
+        let (snapshot, mut rx) = app_cx.market_data().subscribe(btc_usd);
+        assert_eq!(snapshot.seq, 0);
+        tokio::spawn(market_data::run(app_cx.clone()));
+
+        // the feed starts over from the trading engine's orderbook.
+        let msg = next(&mut rx, btc_usd).await;
+        assert_eq!(msg.seq, 1);
+        assert!(matches!(msg.body, MarketDataBody::Snapshot { .. }));
+
+        place_limit_order(&app_cx, btc_usd, OrderSide::Sell, 1_000_000, BTC).await;
+        let msg = next(&mut rx, btc_usd).await;
+        assert_eq!(msg.seq, 2);
+        assert!(matches!(
+            msg.body,
+            MarketDataBody::L2Update { side: OrderSide::Sell, ref price, ref quantity }
+                if price == "10000.00" && quantity == "1.00000000"
+        ));
+
+        place_limit_order(&app_cx, btc_usd, OrderSide::Buy, 1_000_000, 4 * BTC / 10).await;
+        let msg = next(&mut rx, btc_usd).await;
+        assert_eq!(msg.seq, 3);
+        assert!(matches!(
+            msg.body,
+            MarketDataBody::Trade { side: OrderSide::Buy, ref quantity, .. } if quantity == "0.40000000"
+        ));
+        assert_eq!(next(&mut rx, btc_usd).await.seq, 4);
+
+        let snapshot = app_cx.market_data().snapshot(btc_usd);
+        assert_eq!(snapshot.seq, 4);
+        match snapshot.body {
+            MarketDataBody::Snapshot { bids, asks } => {
+                assert!(bids.is_empty());
+                assert_eq!(asks, [["10000.00".to_owned(), "0.60000000".to_owned()]]);
+            }
+            body => panic!("expected a snapshot, got {body:?}"),
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_ticker(db: sqlx::PgPool) {
+        use crate::market_data;
+
+        let app_cx = make_app_cx_fixture(db.clone()).await;
+        let btc_usd = Market::new(fixtures::BTC, fixtures::USD);
+        const BTC: u64 = 100_000_000;
+
+        let mut rx = app_cx.market_data().subscribe_tickers();
+        tokio::spawn(market_data::run(app_cx.clone()));
+
+        place_limit_order(&app_cx, btc_usd, OrderSide::Sell, 1_000_000, BTC / 2).await;
+        place_limit_order(&app_cx, btc_usd, OrderSide::Sell, 1_050_000, BTC / 2).await;
+        place_limit_order(&app_cx, btc_usd, OrderSide::Buy, 1_000_000, BTC / 4).await;
+        place_limit_order(&app_cx, btc_usd, OrderSide::Buy, 1_050_000, BTC / 2).await;
+
+        let mut ticker = rx.recv().await.unwrap();
+        while ticker.market != btc_usd || ticker.volume != "0.75000000" {
+            ticker = rx.recv().await.unwrap();
+        }
+
+        assert_eq!(ticker.last.as_deref(), Some("10500.00"));
+        assert_eq!(ticker.high.as_deref(), Some("10500.00"));
+        assert_eq!(ticker.low.as_deref(), Some("10000.00"));
+        assert_eq!(ticker.change_percent.as_deref(), Some("5.00"));
+
+        // the trade is published before the ask it took is updated.
+        let ticker = app_cx.market_data().ticker(btc_usd);
+        assert_eq!(ticker.bid, None);
+        assert_eq!(ticker.ask.as_deref(), Some("10500.00"));
+        assert_eq!(ticker.volume, "0.75000000");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_candles(db: sqlx::PgPool) {
+        use crate::candles::{self, Candle, CandleInterval};
+
+        let app_cx = make_app_cx_fixture(db.clone()).await;
+        let btc_usd = Market::new(fixtures::BTC, fixtures::USD);
+        const BTC: u64 = 100_000_000;
+
+        tokio::spawn(candles::run(app_cx.clone()));
+
+        place_limit_order(&app_cx, btc_usd, OrderSide::Sell, 1_000_000, BTC / 2).await;
+        place_limit_order(&app_cx, btc_usd, OrderSide::Sell, 1_100_000, BTC / 2).await;
+        place_limit_order(&app_cx, btc_usd, OrderSide::Buy, 1_100_000, BTC).await;
+
+        // the candles are stored in the background.
+        let mut fetched = vec![];
+        for _ in 0..100 {
+            fetched =
+                candles::fetch_candles(&db, btc_usd, CandleInterval::OneHour, 0, i64::MAX, 10)
+                    .await
+                    .unwrap();
+            if fetched.iter().map(|candle| candle.trades).sum::<u64>() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let [candle] = fetched[..] else {
+            panic!("expected one candle, got {fetched:?}");
+        };
+        assert_eq!(candle.open_time % 3600, 0);
+        assert_eq!(
+            candle,
+            Candle {
+                open_time: candle.open_time,
+                open: 1_000_000,
+                high: 1_100_000,
+                low: 1_000_000,
+                close: 1_100_000,
+                volume: BTC,
+                trades: 2,
+            }
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_user_trades(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture(db.clone()).await;
+        let btc_usd = Market::new(fixtures::BTC, fixtures::USD);
+        const BTC: u64 = 100_000_000;
+
+        let maker = place_limit_order(&app_cx, btc_usd, OrderSide::Sell, 1_000_000, BTC).await;
+        let first = place_limit_order(&app_cx, btc_usd, OrderSide::Buy, 1_000_000, BTC / 4).await;
+        let second = place_limit_order(&app_cx, btc_usd, OrderSide::Buy, 1_100_000, BTC / 2).await;
+
+        let filter = TradeFilter {
+            market: Some(btc_usd),
+            start: None,
+            end: None,
+            before: None,
+            limit: 10,
+        };
+
+        let trades = app_cx.user_trades(maker.user_uuid, filter).await.unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].taker_order_uuid, second.order_uuid);
+        assert_eq!(trades[0].maker_order_uuid, maker.order_uuid);
+        assert_eq!(trades[0].price.get(), 1_000_000);
+        assert_eq!(trades[0].quantity, BTC / 2);
+        assert_eq!(trades[0].taker_side, OrderSide::Buy);
+        assert_eq!(trades[1].taker_order_uuid, first.order_uuid);
+
+        // the next page starts after the last trade of the previous one.
+        let page = TradeFilter {
+            before: Some(trades[0].id),
+            limit: 1,
+            ..filter
+        };
+        let trades = app_cx.user_trades(maker.user_uuid, page).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].taker_user_uuid, first.user_uuid);
+
+        let trades = app_cx.user_trades(first.user_uuid, filter).await.unwrap();
+        assert_eq!(trades.len(), 1);
+
+        // trades are stamped with fractional seconds, so a trade at `time` is in [time, time + 1).
+        let trades = app_cx.user_trades(maker.user_uuid, filter).await.unwrap();
+        let (newest, oldest) = (trades[0].timestamp, trades[1].timestamp);
+        let window = |start, end| TradeFilter {
+            start,
+            end,
+            ..filter
+        };
+        for (start, end, len) in [
+            (Some(oldest), None, 2),
+            (Some(newest + 1), None, 0),
+            (None, Some(newest + 1), 2),
+            (None, Some(oldest), 0),
+            (Some(oldest), Some(newest + 1), 2),
+        ] {
+            let trades = app_cx
+                .user_trades(maker.user_uuid, window(start, end))
+                .await
+                .unwrap();
+            assert_eq!(trades.len(), len, "start {start:?} end {end:?}");
+        }
+
+        let other = TradeFilter {
+            market: Some(Market::new(fixtures::ETH, fixtures::USD)),
+            ..filter
+        };
+        let trades = app_cx.user_trades(first.user_uuid, other).await.unwrap();
+        assert!(trades.is_empty());
+    }
+
     #[sqlx::test(migrations = "../migrations")]
     async fn test_calculate_balances(db: sqlx::PgPool) {
         let app_cx = make_app_cx_fixture(db.clone()).await;
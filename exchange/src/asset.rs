@@ -4,84 +4,308 @@ use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
-/// useful as a key in a map-like structure for when there are multiple ways to key an asset
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum AssetKey {
-    /// reference by a static string (e.g. "btc" or "eth")
-    Static(&'static str),
-    /// reference by the asset itself
-    ByValue(Asset),
+use crate::amount::{self, Amount};
+use crate::trading::MarketRules;
+
+/// The ticker symbol of an asset, e.g. `BTC`, the key of its row in the `assets` table.
+///
+/// Symbols are kept inline so that assets and markets stay `Copy`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol {
+    bytes: [u8; Symbol::MAX_LEN],
+    len: u8,
+}
+
+impl Symbol {
+    /// the longest symbol the `assets` table accepts.
+    pub const MAX_LEN: usize = 16;
+
+    /// parse a symbol of 3 to [`Symbol::MAX_LEN`] ASCII letters, lowercase letters are uppercased.
+    pub const fn new(symbol: &str) -> Option<Self> {
+        let src = symbol.as_bytes();
+        if src.len() < 3 || src.len() > Self::MAX_LEN {
+            return None;
+        }
+
+        let mut bytes = [0; Self::MAX_LEN];
+        let mut i = 0;
+        while i < src.len() {
+            let b = src[i].to_ascii_uppercase();
+            if !b.is_ascii_uppercase() {
+                return None;
+            }
+            bytes[i] = b;
+            i += 1;
+        }
+
+        Some(Self {
+            bytes,
+            len: src.len() as u8,
+        })
+    }
+
+    /// the symbol as a string, e.g. `BTC`.
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len as usize]).expect("symbols are ASCII letters")
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-impl From<Asset> for AssetKey {
-    fn from(asset: Asset) -> Self {
-        AssetKey::ByValue(asset)
+impl Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
     }
 }
 
-impl From<&'static str> for AssetKey {
-    fn from(asset: &'static str) -> Self {
-        AssetKey::Static(asset)
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let symbol = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        Symbol::new(&symbol)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid symbol {symbol:?}")))
     }
 }
 
-/// An asset that can be traded on the exchange
+/// An asset that can be traded on the exchange, a row of the `assets` table.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
-pub enum Asset {
-    /// Bitcoin
-    Bitcoin,
-    /// Ethereum
-    Ether,
+pub struct Asset {
+    symbol: Symbol,
+    decimals: u8,
 }
 
-impl FromStr for Asset {
-    type Err = ();
+impl Asset {
+    /// the most decimal places the `assets` table accepts, amounts of whole units still fit the
+    /// ledger's `BIGINT` columns.
+    pub const MAX_DECIMALS: u8 = 18;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "btc" | "BTC" => Ok(Self::Bitcoin),
-            "etc" | "ETH" => Ok(Self::Ether),
-            _ => Err(()),
-        }
+    /// create a new asset
+    pub const fn new(symbol: Symbol, decimals: u8) -> Self {
+        Self { symbol, decimals }
+    }
+
+    /// the ticker symbol of the asset, e.g. `BTC`.
+    pub const fn symbol(self) -> Symbol {
+        self.symbol
+    }
+
+    /// the number of decimal places of the asset, amounts are kept in units of its smallest
+    /// fraction, e.g. satoshis for bitcoin.
+    pub const fn decimals(self) -> u32 {
+        self.decimals as u32
     }
 }
 
 impl std::fmt::Display for Asset {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Asset::Bitcoin => write!(f, "BTC"),
-            Asset::Ether => write!(f, "ETH"),
+        self.symbol.fmt(f)
+    }
+}
+
+/// A market where the `base` asset is traded for the `quote` asset, e.g. BTC-USD.
+///
+/// Quantities are amounts of the base asset, prices are amounts of the quote asset paid for one
+/// whole base asset, e.g. US cents per bitcoin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct Market {
+    /// the asset being bought and sold
+    pub base: Asset,
+    /// the asset the base asset is priced in
+    pub quote: Asset,
+}
+
+impl Market {
+    /// create a new market
+    pub const fn new(base: Asset, quote: Asset) -> Self {
+        Self { base, quote }
+    }
+
+    /// the value of `quantity` at `price` in the quote asset, rounded down.
+    ///
+    /// this is what the buyer pays the seller in a trade, the remainder of the reservation is
+    /// left to the exchange.
+    pub fn notional(&self, price: Amount, quantity: u64) -> u64 {
+        amount::notional(price, quantity, self.base.decimals())
+    }
+
+    /// the value of `quantity` at `price` in the quote asset, rounded up.
+    ///
+    /// this is what a buy reserves so that it can pay for every fill.
+    pub fn notional_ceil(&self, price: Amount, quantity: u64) -> u64 {
+        amount::notional_ceil(price, quantity, self.base.decimals())
+    }
+
+    /// parse a market id like `BTC-USD`, looking its assets up with `asset`. a lone asset like
+    /// `btc` is quoted in USD.
+    pub fn parse(s: &str, mut asset: impl FnMut(&str) -> Option<Asset>) -> Option<Self> {
+        match s.split_once('-') {
+            Some((base, quote)) => Some(Market::new(asset(base)?, asset(quote)?)),
+            None => Some(Market::new(asset(s)?, asset("USD")?)),
         }
     }
+
+    /// the market of a row naming its assets and their decimals, `None` if a row is invalid.
+    pub(crate) fn from_row(
+        base: &str,
+        base_decimals: i16,
+        quote: &str,
+        quote_decimals: i16,
+    ) -> Option<Self> {
+        let asset = |symbol, decimals| {
+            Some(Asset::new(
+                Symbol::new(symbol)?,
+                u8::try_from(decimals).ok()?,
+            ))
+        };
+
+        Some(Market::new(
+            asset(base, base_decimals)?,
+            asset(quote, quote_decimals)?,
+        ))
+    }
+}
+
+impl std::fmt::Display for Market {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.base, self.quote)
+    }
 }
 
-/// Helper for the asset list
-pub trait ContainsAsset {
-    /// check if an asset-key is present in the list
-    fn contains_asset(&self, key: &AssetKey) -> bool;
+/// Whether orders are accepted for a market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketStatus {
+    /// orders are accepted
+    Active,
+    /// orders are rejected, the orderbook is kept as it is
+    Halted,
 }
 
-impl ContainsAsset for [(AssetKey, Asset)] {
-    fn contains_asset(&self, key: &AssetKey) -> bool {
-        for (k, v) in self {
-            if k == key {
-                return true;
-            }
+impl FromStr for MarketStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(Self::Active),
+            "halted" => Ok(Self::Halted),
+            _ => Err(()),
         }
-        return false;
     }
 }
 
-pub(crate) fn internal_asset_list() -> &'static [(AssetKey, Asset)] {
-    use {Asset as A, AssetKey as K};
+/// A row of the `markets` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MarketListing {
+    /// the market
+    pub market: Market,
+    /// whether orders are accepted for the market
+    pub status: MarketStatus,
+    /// the prices and quantities the market accepts
+    #[serde(flatten)]
+    pub rules: MarketRules,
+}
+
+/// fetch every asset from the `assets` table, invalid rows are skipped.
+pub async fn fetch_assets(db: &sqlx::PgPool) -> Result<Vec<Asset>, sqlx::Error> {
+    let rows = sqlx::query!("SELECT symbol, decimals FROM assets ORDER BY symbol")
+        .fetch_all(db)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|rec| {
+            let asset = (|| {
+                Some(Asset::new(
+                    Symbol::new(&rec.symbol)?,
+                    u8::try_from(rec.decimals).ok()?,
+                ))
+            })();
+
+            if asset.is_none() {
+                tracing::error!(symbol = rec.symbol, "skipping unreadable asset");
+            }
+
+            asset
+        })
+        .collect())
+}
+
+/// fetch every market from the `markets` table with its assets, invalid rows are skipped.
+pub async fn fetch_markets(db: &sqlx::PgPool) -> Result<Vec<MarketListing>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT m.symbol, m.base, b.decimals AS base_decimals, m.quote,
+            q.decimals AS quote_decimals, m.status as "status: String",
+            m.tick_size, m.lot_size, m.min_quantity, m.max_quantity, m.min_notional
+        FROM markets m
+        JOIN assets b ON b.symbol = m.base
+        JOIN assets q ON q.symbol = m.quote
+        ORDER BY m.symbol"#
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|rec| {
+            let listing = (|| {
+                Some(MarketListing {
+                    market: Market::from_row(
+                        &rec.base,
+                        rec.base_decimals,
+                        &rec.quote,
+                        rec.quote_decimals,
+                    )?,
+                    status: rec.status.parse().ok()?,
+                    rules: MarketRules {
+                        tick_size: u64::try_from(rec.tick_size).ok()?.try_into().ok()?,
+                        lot_size: u64::try_from(rec.lot_size).ok()?.try_into().ok()?,
+                        min_quantity: u64::try_from(rec.min_quantity).ok()?.try_into().ok()?,
+                        max_quantity: match rec.max_quantity {
+                            Some(max_quantity) => {
+                                Some(u64::try_from(max_quantity).ok()?.try_into().ok()?)
+                            }
+                            None => None,
+                        },
+                        min_notional: u64::try_from(rec.min_notional).ok()?,
+                    },
+                })
+            })();
+
+            if listing.is_none() {
+                tracing::error!(symbol = rec.symbol, "skipping unreadable market");
+            }
+
+            listing
+        })
+        .collect())
+}
+
+#[cfg(test)]
+pub(crate) mod fixtures {
+    //! the assets the migrations list.
+
+    use super::{Asset, Symbol};
+
+    const fn asset(symbol: &str, decimals: u8) -> Asset {
+        match Symbol::new(symbol) {
+            Some(symbol) => Asset::new(symbol, decimals),
+            None => panic!("invalid symbol"),
+        }
+    }
 
-    [
-        (K::ByValue(A::Bitcoin), A::Bitcoin),
-        (K::Static("btc"), A::Bitcoin),
-        (K::Static("BTC"), A::Bitcoin),
-        (K::ByValue(A::Ether), A::Ether),
-        (K::Static("eth"), A::Ether),
-        (K::Static("ETH"), A::Ether),
-    ]
-    .as_slice()
+    /// Bitcoin, kept in satoshis.
+    pub(crate) const BTC: Asset = asset("BTC", 8);
+    /// Ether, kept in gwei.
+    pub(crate) const ETH: Asset = asset("ETH", 9);
+    /// US Dollar, kept in cents.
+    pub(crate) const USD: Asset = asset("USD", 2);
 }
@@ -0,0 +1,176 @@
+//! Replay the trading event log offline and print a digest of the resulting orderbooks.
+//!
+//! The log is read from the `trading_event_source` table, or from a file with one logged command
+//! (the `jstr` column) per line, e.g. exported with
+//! `\copy (SELECT jstr FROM trading_event_source ORDER BY id) TO 'events.jsonl'` in `psql`.
+//! The markets are read from the database if one is given, otherwise every market accepts any
+//! price and quantity. Stored snapshots are compared with the replay at the event they were
+//! taken after.
+//!
+//! Exits with a non-zero status if the replay diverges from the log.
+
+use std::error::Error;
+use std::io::BufRead as _;
+use std::path::PathBuf;
+
+use clap::Parser;
+use futures::StreamExt as _;
+
+use exchange::trading::{AssetsSnapshot, OrderSide, Replay, ReplayReport, TradeCmdPayload};
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// read the event log from this file instead of the database, one logged command per line.
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// the database to read the event log, markets and snapshots from.
+    #[arg(long, env = "DATABASE_URL")]
+    database_url: Option<String>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let _ = dotenv::dotenv();
+    let args = Args::parse();
+
+    let report = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed building the Runtime")
+        .block_on(replay(args))?;
+
+    print_report(&report);
+
+    if !report.divergences.is_empty() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+async fn replay(args: Args) -> Result<ReplayReport, Box<dyn Error>> {
+    let db = match &args.database_url {
+        Some(url) => Some(sqlx::PgPool::connect(url).await?),
+        None => None,
+    };
+
+    let mut replay = match &db {
+        Some(db) => Replay::new(
+            exchange::asset::fetch_markets(db)
+                .await?
+                .into_iter()
+                .map(|listing| (listing.market, listing.rules)),
+        ),
+        None => Replay::with_any_market(),
+    };
+
+    if let Some(path) = args.file {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+
+        for (index, line) in file.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let event_id = index as i64 + 1;
+            let payload: TradeCmdPayload =
+                serde_json::from_str(&line).map_err(|err| format!("line {event_id}: {err}"))?;
+            replay.apply(event_id, payload);
+        }
+
+        return Ok(replay.finish());
+    }
+
+    let Some(db) = db else {
+        return Err("either --file or --database-url (or DATABASE_URL) is required".into());
+    };
+
+    let mut snapshots = sqlx::query!(
+        "SELECT last_event_id, snapshot FROM trading_snapshots ORDER BY last_event_id"
+    )
+    .fetch_all(&db)
+    .await?
+    .into_iter()
+    .map(|rec| (rec.last_event_id, rec.snapshot))
+    .peekable();
+
+    // a snapshot is checked once every event up to the one it was taken after is applied.
+    let mut check_snapshots = |replay: &mut Replay, next_event_id: i64| {
+        while let Some((last_event_id, snapshot)) =
+            snapshots.next_if(|&(last_event_id, _)| last_event_id < next_event_id)
+        {
+            match AssetsSnapshot::decode(&snapshot) {
+                Ok(snapshot) => replay.check_snapshot(&snapshot),
+                Err(err) => eprintln!("skipping snapshot after event {last_event_id}: {err}"),
+            }
+        }
+    };
+
+    let mut events =
+        sqlx::query!("SELECT id, jstr FROM trading_event_source ORDER BY id").fetch(&db);
+
+    while let Some(row) = events.next().await {
+        let row = row?;
+        check_snapshots(&mut replay, row.id);
+
+        let payload: TradeCmdPayload =
+            serde_json::from_value(row.jstr).map_err(|err| format!("event {}: {err}", row.id))?;
+        replay.apply(row.id, payload);
+    }
+
+    check_snapshots(&mut replay, i64::MAX);
+
+    Ok(replay.finish())
+}
+
+fn print_report(report: &ReplayReport) {
+    println!(
+        "replayed {} events up to event {}, {} rejected",
+        report.events, report.last_event_id, report.rejected
+    );
+
+    for (market, book) in &report.assets.books {
+        let (base, quote) = (market.base.decimals(), market.quote.decimals());
+        let orderbook = book.orderbook();
+
+        let side = |side| {
+            let orders: Vec<_> = orderbook.iter_rel(side).map(|(_, order)| order).collect();
+            let quantity: u64 = orders
+                .iter()
+                .map(|order| order.total_quantity().get())
+                .sum();
+
+            match orders.first() {
+                Some(best) => format!(
+                    "{} orders for {} (best {})",
+                    orders.len(),
+                    exchange::amount::format_decimal(quantity, base),
+                    best.price().to_decimal(quote)
+                ),
+                None => "empty".to_string(),
+            }
+        };
+
+        let last_price = book
+            .last_price()
+            .map(|price| price.to_decimal(quote))
+            .unwrap_or_else(|| "-".to_string());
+
+        println!(
+            "{market}: bids {}, asks {}, last price {last_price}",
+            side(OrderSide::Buy),
+            side(OrderSide::Sell)
+        );
+    }
+
+    println!("digest {}", report.digest);
+
+    if report.divergences.is_empty() {
+        println!("no divergences");
+    } else {
+        println!("{} divergences:", report.divergences.len());
+        for divergence in &report.divergences {
+            println!("  {divergence}");
+        }
+    }
+}
@@ -0,0 +1,454 @@
+//! OHLCV candles of the trades of every market.
+//!
+//! The rows of the `trades` table are aggregated into a candle of every [`CandleInterval`] and
+//! stored in the `candles` table, periods without trades have no candle. The `candle_cursor`
+//! table holds the last aggregated trade, moved in the same transaction as the candles, so trades
+//! settled while the aggregator lagged behind or was not running are aggregated when it catches
+//! up. Execution reports only wake the aggregator up.
+//!
+//! Trades are aggregated in the order of their ids, which is the order they are committed in as
+//! the trading engine settles one command at a time. To rebuild the candles, e.g. after changing
+//! how they are aggregated, delete them and set `candle_cursor.last_trade_id` to 0 in one
+//! transaction.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+use crate::app_cx::AppCx;
+use crate::trading::EngineEventKind;
+use crate::{Amount, Market};
+
+/// the most trades aggregated before their candles are stored.
+const MAX_BATCH_TRADES: i64 = 1024;
+
+/// The length of the period of a candle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum CandleInterval {
+    /// one minute
+    #[serde(rename = "1m")]
+    OneMinute,
+    /// five minutes
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    /// one hour
+    #[serde(rename = "1h")]
+    OneHour,
+    /// one day, starting at midnight UTC
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl CandleInterval {
+    /// every interval, shortest first.
+    pub const ALL: [CandleInterval; 4] = [
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinutes,
+        CandleInterval::OneHour,
+        CandleInterval::OneDay,
+    ];
+
+    /// the length of the interval in seconds.
+    pub const fn secs(self) -> i64 {
+        match self {
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinutes => 5 * 60,
+            CandleInterval::OneHour => 60 * 60,
+            CandleInterval::OneDay => 24 * 60 * 60,
+        }
+    }
+
+    /// the name of the interval in the `candles` table.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            CandleInterval::OneMinute => "1m",
+            CandleInterval::FiveMinutes => "5m",
+            CandleInterval::OneHour => "1h",
+            CandleInterval::OneDay => "1d",
+        }
+    }
+
+    /// the start of the period the unix timestamp (in seconds) `at` falls in.
+    pub const fn open_time(self, at: i64) -> i64 {
+        at - at.rem_euclid(self.secs())
+    }
+}
+
+/// The trades of a market in one period.
+///
+/// Prices are in the smallest unit of the quote asset, the volume in the smallest unit of the
+/// base asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candle {
+    /// the unix timestamp (in seconds) the period starts at.
+    pub open_time: i64,
+    /// the price of the first trade.
+    pub open: u64,
+    /// the highest price.
+    pub high: u64,
+    /// the lowest price.
+    pub low: u64,
+    /// the price of the last trade.
+    pub close: u64,
+    /// the traded quantity.
+    pub volume: u64,
+    /// the number of trades.
+    pub trades: u64,
+}
+
+impl Candle {
+    /// a candle of a single trade.
+    fn new(open_time: i64, price: Amount, quantity: u64) -> Self {
+        Self {
+            open_time,
+            open: price.get(),
+            high: price.get(),
+            low: price.get(),
+            close: price.get(),
+            volume: quantity,
+            trades: 1,
+        }
+    }
+
+    /// add a later trade to the candle.
+    fn add(&mut self, price: Amount, quantity: u64) {
+        self.high = self.high.max(price.get());
+        self.low = self.low.min(price.get());
+        self.close = price.get();
+        self.volume = self.volume.saturating_add(quantity);
+        self.trades += 1;
+    }
+}
+
+/// candles of a batch of trades, by market, interval and period.
+#[derive(Debug, Default)]
+struct CandleBatch(BTreeMap<(Market, CandleInterval, i64), Candle>);
+
+impl CandleBatch {
+    /// add a trade at the unix timestamp (in seconds) `at`, later than the trades added before.
+    fn add(&mut self, market: Market, at: i64, price: Amount, quantity: u64) {
+        for interval in CandleInterval::ALL {
+            let open_time = interval.open_time(at);
+            self.0
+                .entry((market, interval, open_time))
+                .and_modify(|candle| candle.add(price, quantity))
+                .or_insert_with(|| Candle::new(open_time, price, quantity));
+        }
+    }
+}
+
+/// merge the candles of a batch into the `candles` table.
+async fn store_candles(tx: &mut PgConnection, batch: &CandleBatch) -> Result<(), sqlx::Error> {
+    for (&(market, interval, _), candle) in &batch.0 {
+        sqlx::query!(
+            r#"
+            INSERT INTO candles (market, period, open_time, open, high, low, close, volume, trades)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (market, period, open_time) DO UPDATE SET
+                high = GREATEST(candles.high, EXCLUDED.high),
+                low = LEAST(candles.low, EXCLUDED.low),
+                close = EXCLUDED.close,
+                volume = candles.volume + EXCLUDED.volume,
+                trades = candles.trades + EXCLUDED.trades
+            "#,
+            market.to_string(),
+            interval.as_str(),
+            candle.open_time,
+            candle.open as i64,
+            candle.high as i64,
+            candle.low as i64,
+            candle.close as i64,
+            candle.volume as i64,
+            candle.trades as i64,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// aggregate the trades after the cursor into the candles and move the cursor past them, returns
+/// whether there may be more trades to aggregate.
+async fn aggregate_trades(db: &sqlx::PgPool) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let cursor = sqlx::query_scalar!("SELECT last_trade_id FROM candle_cursor FOR UPDATE")
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let trades = sqlx::query!(
+        r#"
+        SELECT t.id, t.market, m.base, b.decimals AS base_decimals, m.quote,
+            q.decimals AS quote_decimals, t.price, t.quantity, t.created_at
+        FROM trades t
+        JOIN markets m ON m.symbol = t.market
+        JOIN assets b ON b.symbol = m.base
+        JOIN assets q ON q.symbol = m.quote
+        WHERE t.id > $1
+        ORDER BY t.id
+        LIMIT $2
+        "#,
+        cursor,
+        MAX_BATCH_TRADES,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let Some(last_trade_id) = trades.last().map(|trade| trade.id) else {
+        return Ok(false);
+    };
+    let full = trades.len() as i64 == MAX_BATCH_TRADES;
+
+    let mut batch = CandleBatch::default();
+    for trade in trades {
+        let (Some(market), Some(price)) = (
+            Market::from_row(
+                &trade.base,
+                trade.base_decimals,
+                &trade.quote,
+                trade.quote_decimals,
+            ),
+            Amount::new(trade.price as u64),
+        ) else {
+            tracing::warn!(
+                id = trade.id,
+                market = trade.market,
+                "trade of unknown market"
+            );
+            continue;
+        };
+
+        let at = trade.created_at.unix_timestamp();
+        batch.add(market, at, price, trade.quantity as u64);
+    }
+
+    store_candles(&mut tx, &batch).await?;
+
+    sqlx::query!("UPDATE candle_cursor SET last_trade_id = $1", last_trade_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(full)
+}
+
+/// the newest candles of a market whose period starts in `start..end` (unix timestamps in
+/// seconds), at most `limit` of them, oldest first.
+pub async fn fetch_candles(
+    db: &sqlx::PgPool,
+    market: Market,
+    interval: CandleInterval,
+    start: i64,
+    end: i64,
+    limit: i64,
+) -> Result<Vec<Candle>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT open_time, open, high, low, close, volume, trades
+        FROM candles
+        WHERE market = $1 AND period = $2 AND open_time >= $3 AND open_time < $4
+        ORDER BY open_time DESC
+        LIMIT $5
+        "#,
+        market.to_string(),
+        interval.as_str(),
+        start,
+        end,
+        limit,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .rev()
+        .map(|rec| Candle {
+            open_time: rec.open_time,
+            open: rec.open as u64,
+            high: rec.high as u64,
+            low: rec.low as u64,
+            close: rec.close as u64,
+            volume: rec.volume as u64,
+            trades: rec.trades as u64,
+        })
+        .collect())
+}
+
+/// aggregate the trades settled by the trading engine into the candles of `app`'s database.
+pub async fn run(app: AppCx) {
+    let db = app.db();
+    let mut events = app.subscribe_engine_events();
+
+    loop {
+        match aggregate_trades(&db).await {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(err) => tracing::error!(?err, "failed to store candles"),
+        }
+
+        // wait for a fill, any trades reported while lagging behind are still in the trades table.
+        let mut filled = false;
+        while !filled {
+            match events.recv().await {
+                Ok(event) => filled = matches!(event.kind, EngineEventKind::Filled(_)),
+                Err(RecvError::Lagged(_)) => filled = true,
+                Err(RecvError::Closed) => return,
+            }
+        }
+
+        // the trades of the execution reports in the queue are aggregated in the same batch.
+        loop {
+            match events.try_recv() {
+                Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::asset::fixtures;
+
+    const BTC_USD: Market = Market::new(fixtures::BTC, fixtures::USD);
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn nz(n: u64) -> Amount {
+        Amount::new(n).unwrap()
+    }
+
+    #[test]
+    fn test_open_time() {
+        assert_eq!(CandleInterval::OneMinute.open_time(0), 0);
+        assert_eq!(CandleInterval::OneMinute.open_time(59), 0);
+        assert_eq!(CandleInterval::OneMinute.open_time(60), 60);
+        assert_eq!(CandleInterval::FiveMinutes.open_time(599), 300);
+        assert_eq!(CandleInterval::OneHour.open_time(7_199), 3_600);
+        assert_eq!(CandleInterval::OneDay.open_time(DAY - 1), 0);
+        assert_eq!(CandleInterval::OneDay.open_time(DAY), DAY);
+
+        // periods before the epoch start at or before the timestamp, too.
+        assert_eq!(CandleInterval::OneMinute.open_time(-1), -60);
+        assert_eq!(CandleInterval::OneMinute.open_time(-60), -60);
+        assert_eq!(CandleInterval::OneMinute.open_time(-61), -120);
+        assert_eq!(CandleInterval::OneDay.open_time(-1), -DAY);
+    }
+
+    #[test]
+    fn test_batch_buckets_trades() {
+        let mut batch = CandleBatch::default();
+        batch.add(BTC_USD, DAY - 61, nz(10), 1);
+        batch.add(BTC_USD, DAY - 30, nz(12), 2);
+        batch.add(BTC_USD, DAY - 1, nz(9), 3);
+        // the first trade of the next day.
+        batch.add(BTC_USD, DAY, nz(11), 4);
+
+        let candle = |interval, open_time| batch.0[&(BTC_USD, interval, open_time)];
+
+        assert_eq!(
+            candle(CandleInterval::OneMinute, DAY - 120),
+            Candle {
+                open_time: DAY - 120,
+                open: 10,
+                high: 10,
+                low: 10,
+                close: 10,
+                volume: 1,
+                trades: 1,
+            }
+        );
+        assert_eq!(
+            candle(CandleInterval::OneMinute, DAY - 60),
+            Candle {
+                open_time: DAY - 60,
+                open: 12,
+                high: 12,
+                low: 9,
+                close: 9,
+                volume: 5,
+                trades: 2,
+            }
+        );
+
+        // the first three trades are in the last period of every interval of the first day.
+        for interval in CandleInterval::ALL.into_iter().skip(1) {
+            let open_time = interval.open_time(DAY - 1);
+            assert_eq!(
+                candle(interval, open_time),
+                Candle {
+                    open_time,
+                    open: 10,
+                    high: 12,
+                    low: 9,
+                    close: 9,
+                    volume: 6,
+                    trades: 3,
+                }
+            );
+        }
+
+        for interval in CandleInterval::ALL {
+            assert_eq!(candle(interval, DAY), Candle::new(DAY, nz(11), 4));
+        }
+        assert_eq!(batch.0.len(), 2 + 3 + 4);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_aggregate_trades(db: sqlx::PgPool) {
+        let trade = |at: i64, price: i64, quantity: i64| {
+            let db = db.clone();
+            async move {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO trades (market, price, quantity, taker_side, maker_order_uuid,
+                        maker_user_uuid, taker_order_uuid, taker_user_uuid, created_at)
+                    VALUES ($1, $2, $3, 'buy', $4, $4, $4, $4, to_timestamp($5::BIGINT))
+                    "#,
+                    BTC_USD.to_string(),
+                    price,
+                    quantity,
+                    Uuid::new_v4(),
+                    at,
+                )
+                .execute(&db)
+                .await
+                .unwrap();
+            }
+        };
+        let hour = |db: sqlx::PgPool| async move {
+            fetch_candles(&db, BTC_USD, CandleInterval::OneHour, 0, i64::MAX, 10)
+                .await
+                .unwrap()
+        };
+
+        trade(DAY + 10, 10, 1).await;
+        trade(DAY + 20, 12, 2).await;
+        assert!(!aggregate_trades(&db).await.unwrap());
+
+        // a later batch only adds the trades after the cursor, and merges into the same candle.
+        trade(DAY + 30, 8, 3).await;
+        trade(DAY + 40, 11, 4).await;
+        assert!(!aggregate_trades(&db).await.unwrap());
+        assert!(!aggregate_trades(&db).await.unwrap());
+
+        assert_eq!(
+            hour(db.clone()).await,
+            [Candle {
+                open_time: DAY,
+                open: 10,
+                high: 12,
+                low: 8,
+                close: 11,
+                volume: 10,
+                trades: 4,
+            }]
+        );
+    }
+}
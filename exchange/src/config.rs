@@ -68,6 +68,21 @@ const fn default_te_channel_capacity() -> usize {
     1024
 }
 
+/// The default number of execution reports a lagging subscriber can fall behind by.
+const fn default_te_event_channel_capacity() -> usize {
+    4096
+}
+
+/// The default number of market data messages a lagging subscriber can fall behind by.
+const fn default_market_data_channel_capacity() -> usize {
+    4096
+}
+
+/// The default number of logged events between snapshots of the trading engine.
+const fn default_te_snapshot_interval() -> u64 {
+    10_000
+}
+
 /// The string key used to check the environment variable for the bitcoin rpc url.
 pub const BITCOIN_RPC_URL: &str = "BITCOIN_RPC_URL";
 
@@ -140,6 +155,15 @@ pub struct Configuration {
     /// Configure the message channel capacity of the trading engine
     #[serde(default = "default_te_channel_capacity")]
     pub te_channel_capacity: usize,
+    /// Configure how many execution reports of the trading engine are buffered for subscribers
+    #[serde(default = "default_te_event_channel_capacity")]
+    pub te_event_channel_capacity: usize,
+    /// Configure how many market data messages are buffered for subscribers
+    #[serde(default = "default_market_data_channel_capacity")]
+    pub market_data_channel_capacity: usize,
+    /// Snapshot the trading engine every this many logged events, 0 disables snapshots
+    #[serde(default = "default_te_snapshot_interval")]
+    pub te_snapshot_interval: u64,
     /// Mnemonic for the exchange Ether wallet
     pub eth_wallet_mnemonic: Option<String>,
     #[serde(default = "bitcoin_rpc_url")]
@@ -0,0 +1,494 @@
+//! Postings to the double-entry accounting journal (`account_tx_journal`) made on behalf of the trading engine.
+//!
+//! Funds for an order are reserved up-front by moving them from the user's account into the
+//! exchange's account for that currency (see [`crate::app_cx::AppCx::reserve_by_asset`]). When an
+//! order trades, the reserved funds are paid out of the exchange's accounts to the counterparty.
+//!
+//! Every function here takes a connection that is expected to be inside the same transaction as
+//! the `trading_event_source` write so the journal never drifts from the engine's event log.
+//! Every match is recorded in the `trades` table in the same way.
+//!
+//! Trades pay the notional rounded down (see [`Market::notional`]) while buys hold it rounded up,
+//! the rounding remainder of a fill is left in the exchange's account.
+
+use sqlx::PgConnection;
+
+use crate::trading::{
+    AmendOrderResult, CancelOrderResult, MakerFill, Order, OrderSide, OrderType, OrderUuid,
+    PlaceOcoResult, PlaceOrderResult, TriggerStopsResult,
+};
+use crate::{Amount, Market};
+
+/// Extra headroom (in percent) reserved for market buys, the execution price is not known up-front.
+pub const MARKET_BUY_RESERVE_BUFFER_PCT: u64 = 5;
+
+/// `transaction_type` of a journal row paying out a trade.
+const TRADE_SETTLE: &str = "trade settle";
+
+/// `transaction_type` of a journal row returning reserved funds that are no longer needed.
+const RELEASE_RESERVE: &str = "release reserve asset";
+
+/// `transaction_type` of a journal row reserving funds for an order.
+const RESERVE: &str = "reserve asset";
+
+/// the currency that is reserved when placing an order, the market's quote asset for buys and its base asset for sells.
+pub fn reserve_currency(market: Market, side: OrderSide) -> String {
+    match side {
+        OrderSide::Buy => market.quote.to_string(),
+        OrderSide::Sell => market.base.to_string(),
+    }
+}
+
+/// the amount of [`reserve_currency`] that is reserved when placing an order.
+///
+/// * buys reserve the notional (price × quantity, rounded up) plus [`MARKET_BUY_RESERVE_BUFFER_PCT`] for (stop-)market orders.
+/// * sells reserve the quantity of the base asset being sold.
+///
+/// stop orders reserve what the order they are triggered as does, so the reservation carries over when triggered.
+///
+pub fn reserve_amount(
+    market: Market,
+    side: OrderSide,
+    order_type: OrderType,
+    price: Amount,
+    quantity: Amount,
+) -> u64 {
+    match (side, order_type) {
+        (OrderSide::Buy, OrderType::Limit | OrderType::StopLimit) => {
+            market.notional_ceil(price, quantity.get())
+        }
+        (OrderSide::Buy, OrderType::Market | OrderType::StopMarket) => {
+            let notional = u128::from(market.notional_ceil(price, quantity.get()));
+            let buffered = notional * u128::from(100 + MARKET_BUY_RESERVE_BUFFER_PCT) / 100;
+            buffered.min(u128::from(Amount::MAX.get())) as u64
+        }
+        (OrderSide::Sell, _) => quantity.get(),
+    }
+}
+
+/// the amount of [`reserve_currency`] a resting order of `quantity` still holds.
+fn held_amount(market: Market, side: OrderSide, price: Amount, quantity: u64) -> u64 {
+    match side {
+        OrderSide::Buy => market.notional_ceil(price, quantity),
+        OrderSide::Sell => quantity,
+    }
+}
+
+/// make sure the user has an account in `currency` so it can be credited.
+async fn ensure_user_account(
+    tx: &mut PgConnection,
+    user_uuid: uuid::Uuid,
+    currency: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO accounts (currency, source_type, source_id)
+        VALUES ($1, 'user', $2)
+        ON CONFLICT (source_id, currency) DO NOTHING
+        "#,
+        currency,
+        user_uuid.to_string(),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// the user's balance of `currency`, the user has to have an account in it.
+async fn balance(
+    tx: &mut PgConnection,
+    user_uuid: uuid::Uuid,
+    currency: &str,
+) -> Result<u64, sqlx::Error> {
+    let rec = sqlx::query!(
+        "SELECT calculate_balance($1, $2);",
+        user_uuid.to_string(),
+        currency,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    Ok(rec.calculate_balance.unwrap_or_default().max(0) as u64)
+}
+
+/// the user's balance of `currency`, the user's account is locked until the transaction ends so
+/// the balance can not be spent by anything else in the meantime. zero if the user has no
+/// account in it.
+pub async fn lock_balance(
+    tx: &mut PgConnection,
+    user_uuid: uuid::Uuid,
+    currency: &str,
+) -> Result<u64, sqlx::Error> {
+    let account = sqlx::query!(
+        r#"
+        SELECT id FROM accounts
+        WHERE source_type = 'user' AND source_id = $1 AND currency = $2
+        FOR UPDATE
+        "#,
+        user_uuid.to_string(),
+        currency,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    match account {
+        Some(_) => balance(&mut *tx, user_uuid, currency).await,
+        None => Ok(0),
+    }
+}
+
+/// move `amount` of `currency` from the user's account into the exchange's (reserve) account.
+async fn debit_user_to_exchange(
+    tx: &mut PgConnection,
+    user_uuid: uuid::Uuid,
+    currency: &str,
+    amount: u64,
+    transaction_type: &str,
+) -> Result<(), sqlx::Error> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
+            (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = $2),
+            (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $2),
+            $2,
+            $3,
+            $4
+        )
+        "#,
+        user_uuid.to_string(),
+        currency,
+        amount as i64,
+        transaction_type,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// pay `amount` of `currency` from the exchange's (reserve) account to the user's account.
+async fn credit_user_from_exchange(
+    tx: &mut PgConnection,
+    user_uuid: uuid::Uuid,
+    currency: &str,
+    amount: u64,
+    transaction_type: &str,
+) -> Result<(), sqlx::Error> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    ensure_user_account(&mut *tx, user_uuid, currency).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
+            (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $2),
+            (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = $2),
+            $2,
+            $3,
+            $4
+        )
+        "#,
+        user_uuid.to_string(),
+        currency,
+        amount as i64,
+        transaction_type,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// record a maker/taker match in the `trades` table, no fees are charged.
+async fn record_trade(
+    tx: &mut PgConnection,
+    market: Market,
+    taker: uuid::Uuid,
+    taker_order: OrderUuid,
+    taker_side: OrderSide,
+    fill: &MakerFill,
+) -> Result<(), sqlx::Error> {
+    let taker_side = match taker_side {
+        OrderSide::Buy => "buy",
+        OrderSide::Sell => "sell",
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO trades (market, price, quantity, taker_side, maker_order_uuid, maker_user_uuid, taker_order_uuid, taker_user_uuid)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        market.to_string(),
+        fill.maker.price().get() as i64,
+        fill.fill_amount as i64,
+        taker_side,
+        fill.maker.order_uuid().0,
+        fill.maker.owner(),
+        taker_order.0,
+        taker,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// settle a single maker/taker match.
+///
+/// The buyer receives `fill_amount` of the base asset and the seller receives the notional
+/// (maker price × `fill_amount`, rounded down) in the quote asset, both paid from the reserved funds.
+async fn settle_fill(
+    tx: &mut PgConnection,
+    market: Market,
+    taker: uuid::Uuid,
+    taker_order: OrderUuid,
+    taker_side: OrderSide,
+    fill: &MakerFill,
+) -> Result<(), sqlx::Error> {
+    record_trade(&mut *tx, market, taker, taker_order, taker_side, fill).await?;
+
+    let (buyer, seller) = match taker_side {
+        OrderSide::Buy => (taker, fill.maker.owner()),
+        OrderSide::Sell => (fill.maker.owner(), taker),
+    };
+
+    let quantity = fill.fill_amount;
+    let notional = market.notional(fill.maker.price(), fill.fill_amount);
+
+    let (base, quote) = (market.base.to_string(), market.quote.to_string());
+    credit_user_from_exchange(&mut *tx, buyer, &base, quantity, TRADE_SETTLE).await?;
+    credit_user_from_exchange(&mut *tx, seller, &quote, notional, TRADE_SETTLE).await?;
+
+    tracing::trace!(%buyer, %seller, quantity, notional, "settled fill");
+
+    Ok(())
+}
+
+/// post the ledger entries for every fill produced by a placed order.
+///
+/// Whatever the taker reserved that was neither spent on fills nor is still held by the
+/// resting remainder of the order (price improvement, market buy buffer, IOC remainders,
+/// unspent quote amounts) is released back to the taker. The trading engine rejects orders that
+/// would spend more than they reserved before they change the orderbook (see
+/// [`PlaceOrderError::ExceedsReservation`](crate::trading::PlaceOrderError::ExceedsReservation)),
+/// the fills have happened by the time they are settled.
+pub async fn settle_place_order(
+    tx: &mut PgConnection,
+    result: &PlaceOrderResult,
+) -> Result<(), sqlx::Error> {
+    for fill in &result.fills {
+        settle_fill(
+            &mut *tx,
+            result.market,
+            result.user_uuid,
+            result.order_uuid,
+            result.side,
+            fill,
+        )
+        .await?;
+    }
+
+    // resting orders cancelled by self-trade protection no longer need their reservation.
+    let maker_side = match result.side {
+        OrderSide::Buy => OrderSide::Sell,
+        OrderSide::Sell => OrderSide::Buy,
+    };
+
+    for cancel in &result.self_trade_cancels {
+        // cancelling the displayed slice of an iceberg order cancels its hidden quantity too.
+        let quantity = if cancel.cancel_amount == cancel.maker.quantity().get() {
+            cancel.maker.total_quantity().get()
+        } else {
+            cancel.cancel_amount
+        };
+        let release = held_amount(result.market, maker_side, cancel.maker.price(), quantity);
+        let currency = reserve_currency(result.market, maker_side);
+        credit_user_from_exchange(
+            &mut *tx,
+            cancel.maker.owner(),
+            &currency,
+            release,
+            RELEASE_RESERVE,
+        )
+        .await?;
+    }
+
+    // a buy by quote amount reserved exactly what it may spend.
+    let reserved = match result.quote_amount {
+        Some(quote_amount) => quote_amount.get(),
+        None => reserve_amount(
+            result.market,
+            result.side,
+            result.order_type,
+            result.price,
+            result.quantity,
+        ),
+    };
+
+    let spent = result
+        .fills
+        .iter()
+        .map(|fill| match result.side {
+            OrderSide::Buy => result.market.notional(fill.maker.price(), fill.fill_amount),
+            OrderSide::Sell => fill.fill_amount,
+        })
+        .sum::<u64>();
+
+    let held = match result.order_index {
+        // a stop order that is waiting to be triggered holds its entire reservation.
+        None if result.order_type.is_stop() => reserved,
+        Some(_) => {
+            let price = result.repriced.unwrap_or(result.price);
+            held_amount(result.market, result.side, price, result.quantity_remaining)
+        }
+        None => 0,
+    };
+
+    if spent + held > reserved {
+        tracing::error!(
+            user_uuid = %result.user_uuid,
+            reserved,
+            spent,
+            held,
+            "order spent more than was reserved"
+        );
+    }
+
+    let release = reserved.saturating_sub(spent + held);
+    let currency = reserve_currency(result.market, result.side);
+    credit_user_from_exchange(
+        &mut *tx,
+        result.user_uuid,
+        &currency,
+        release,
+        RELEASE_RESERVE,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// release the funds still held by a cancelled order back to its owner.
+pub async fn settle_cancel_order(
+    tx: &mut PgConnection,
+    result: &CancelOrderResult,
+) -> Result<(), sqlx::Error> {
+    let CancelOrderResult {
+        market,
+        side,
+        order,
+        order_type,
+    } = *result;
+
+    let release = reserve_amount(
+        market,
+        side,
+        order_type,
+        order.price(),
+        order.total_quantity(),
+    );
+    let currency = reserve_currency(market, side);
+    credit_user_from_exchange(&mut *tx, order.owner(), &currency, release, RELEASE_RESERVE).await
+}
+
+/// release the funds still held by cancelled orders, e.g. expired good-til-date orders, back to their owners.
+pub async fn settle_cancel_orders(
+    tx: &mut PgConnection,
+    cancelled: &[CancelOrderResult],
+) -> Result<(), sqlx::Error> {
+    for result in cancelled {
+        settle_cancel_order(&mut *tx, result).await?;
+    }
+
+    Ok(())
+}
+
+/// post the ledger entries for triggered stop orders, rejected ones release their reservation.
+pub async fn settle_trigger_stops(
+    tx: &mut PgConnection,
+    result: &TriggerStopsResult,
+) -> Result<(), sqlx::Error> {
+    for placed in &result.placed {
+        settle_place_order(&mut *tx, placed).await?;
+    }
+
+    for rejected in &result.rejected {
+        settle_cancel_order(&mut *tx, rejected).await?;
+    }
+
+    Ok(())
+}
+
+/// post the ledger entries for linked orders, both are reserved up-front so the cancelled one is released.
+pub async fn settle_place_oco(
+    tx: &mut PgConnection,
+    result: &PlaceOcoResult,
+) -> Result<(), sqlx::Error> {
+    for placed in &result.placed {
+        settle_place_order(&mut *tx, placed).await?;
+    }
+
+    settle_cancel_orders(&mut *tx, &result.cancelled).await
+}
+
+/// whether the owner of `order` can afford to hold it at `price` × `quantity` instead.
+///
+/// Only the difference to what the order already holds has to be available. The owner's balance
+/// is locked (see [`lock_balance`]), the difference has to be reserved in the same transaction
+/// (see [`settle_amend_order`]).
+pub async fn can_hold_amended_order(
+    tx: &mut PgConnection,
+    market: Market,
+    side: OrderSide,
+    order: &Order,
+    price: Amount,
+    quantity: Amount,
+) -> Result<bool, sqlx::Error> {
+    let held = held_amount(market, side, order.price(), order.total_quantity().get());
+    let required = held_amount(market, side, price, quantity.get());
+
+    if required <= held {
+        return Ok(true);
+    }
+
+    let currency = reserve_currency(market, side);
+    Ok(lock_balance(&mut *tx, order.owner(), &currency).await? >= required - held)
+}
+
+/// reserve or release the difference in funds held by an amended order.
+pub async fn settle_amend_order(
+    tx: &mut PgConnection,
+    result: &AmendOrderResult,
+) -> Result<(), sqlx::Error> {
+    let AmendOrderResult {
+        market,
+        side,
+        previous,
+        order,
+        ..
+    } = *result;
+
+    let held = held_amount(
+        market,
+        side,
+        previous.price(),
+        previous.total_quantity().get(),
+    );
+    let required = held_amount(market, side, order.price(), order.total_quantity().get());
+    let currency = reserve_currency(market, side);
+
+    if required > held {
+        debit_user_to_exchange(&mut *tx, order.owner(), &currency, required - held, RESERVE).await
+    } else {
+        let release = held - required;
+        credit_user_from_exchange(&mut *tx, order.owner(), &currency, release, RELEASE_RESERVE)
+            .await
+    }
+}
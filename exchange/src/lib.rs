@@ -26,6 +26,7 @@ use std::sync::Arc;
 use thiserror::Error;
 use tracing::Instrument;
 
+pub mod amount;
 pub mod asset;
 pub mod bitcoin;
 pub mod config;
@@ -34,9 +35,13 @@ pub mod signal;
 pub mod test;
 pub mod trading;
 pub mod web;
-pub use asset::Asset;
+pub use amount::Amount;
+pub use asset::{Asset, Market};
 pub use config::Configuration;
 
+pub(crate) mod candles;
+pub(crate) mod ledger;
+pub(crate) mod market_data;
 pub(crate) mod password;
 pub(crate) mod app_cx;
 use crate::app_cx::AppCx;
@@ -112,19 +117,27 @@ pub fn start_fullstack(
             .await
             .map_err(|err| StartFullstackError::BitcoinRpc(err))?;
 
-        let (te_tx, mut te_handle) =
-            spawn_trading_engine::spawn_trading_engine(&config, db.clone())
-                .init_from_db(db.clone())
-                .await?;
+        let te = spawn_trading_engine::spawn_trading_engine(&config, db.clone());
+        let te_events = te.events.clone();
+        let (te_tx, mut te_handle) = te.init_from_db(db.clone()).await?;
+
+        let assets = asset::fetch_assets(&db).await?;
+        let markets = asset::fetch_markets(&db).await?;
 
         let state = AppCx::new(
             te_tx.clone(),
+            te_events,
             btc_rpc,
             db,
             crate::jinja::make_jinja_env(&config),
             config.clone(),
+            assets,
+            markets,
         );
 
+        tokio::spawn(market_data::run(state.clone()));
+        tokio::spawn(candles::run(state.clone()));
+
         tracing::info!("launching webserver and waiting for stop signal");
 
         let res = tokio::select! {
@@ -0,0 +1,579 @@
+//! Public market data derived from the execution reports of the trading engine.
+//!
+//! A copy of every orderbook's price levels is kept up to date from the [`EngineEvent`]s of the
+//! trading engine, starting from a [`DepthSnapshot`]. Every change is published as a
+//! [`MarketDataMsg`] numbered per market, a subscriber that sees a number skipped has missed a
+//! message and should start over from a fresh snapshot.
+//!
+//! The trades of the last 24 hours are kept as well for the [`Ticker`] of every market, which is
+//! published whenever a trade happens or the best bid or ask changes. Trades from before the
+//! exchange started are not known.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::amount::format_decimal;
+use crate::app_cx::AppCx;
+use crate::trading::{DepthSnapshot, EngineEvent, EngineEventKind, OrderSide};
+use crate::{Amount, Market};
+
+/// how long to wait before asking an unresponsive trading engine for a snapshot again.
+const RESYNC_DELAY: Duration = Duration::from_secs(1);
+
+/// how long trades count towards the ticker, in milliseconds.
+const TICKER_WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// A message of the market data feed.
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketDataMsg {
+    /// the market the message is about.
+    #[serde(serialize_with = "serialize_symbol")]
+    pub market: Market,
+    /// the number of the message, one more than the message of the same market before it.
+    pub seq: u64,
+    /// what changed.
+    #[serde(flatten)]
+    pub body: MarketDataBody,
+}
+
+/// What a [`MarketDataMsg`] carries, prices and quantities are decimal strings.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MarketDataBody {
+    /// every price level of the orderbook as `[price, quantity]` pairs, best prices first.
+    Snapshot {
+        /// the bid levels, highest price first.
+        bids: Vec<[String; 2]>,
+        /// the ask levels, lowest price first.
+        asks: Vec<[String; 2]>,
+    },
+    /// the new quantity of a price level, a quantity of zero removes the level.
+    L2Update {
+        /// the side of the orderbook.
+        side: OrderSide,
+        /// the price of the level.
+        price: String,
+        /// the quantity of the level.
+        quantity: String,
+    },
+    /// a trade.
+    Trade {
+        /// the side of the incoming order, buy or sell.
+        side: OrderSide,
+        /// the price of the trade.
+        price: String,
+        /// the traded quantity.
+        quantity: String,
+    },
+}
+
+/// The statistics of a market over the last 24 hours, prices and quantities are decimal strings.
+#[derive(Debug, Clone, Serialize)]
+pub struct Ticker {
+    /// the market of the ticker.
+    #[serde(serialize_with = "serialize_symbol")]
+    pub market: Market,
+    /// the price of the last trade, however long ago it was.
+    pub last: Option<String>,
+    /// the highest bid.
+    pub bid: Option<String>,
+    /// the lowest ask.
+    pub ask: Option<String>,
+    /// the highest price traded in the last 24 hours.
+    pub high: Option<String>,
+    /// the lowest price traded in the last 24 hours.
+    pub low: Option<String>,
+    /// the quantity traded in the last 24 hours.
+    pub volume: String,
+    /// the change from the first to the last price traded in the last 24 hours in percent,
+    /// e.g. `-1.25`.
+    pub change_percent: Option<String>,
+}
+
+fn serialize_symbol<S: serde::Serializer>(
+    market: &Market,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(market)
+}
+
+/// a trade counting towards the ticker.
+#[derive(Debug, Clone, Copy)]
+struct TickerTrade {
+    /// the unix timestamp (in milliseconds) of the trade.
+    timestamp: u64,
+    price: Amount,
+    quantity: u64,
+}
+
+/// the price levels and the recent trades of a market.
+#[derive(Debug, Default)]
+struct L2Book {
+    /// the number of the last message published for the market.
+    seq: u64,
+    bids: BTreeMap<Amount, u64>,
+    asks: BTreeMap<Amount, u64>,
+    /// the price of the last trade.
+    last: Option<Amount>,
+    /// the trades of the last 24 hours, oldest first.
+    trades: VecDeque<TickerTrade>,
+}
+
+impl L2Book {
+    fn levels_mut(&mut self, side: OrderSide) -> &mut BTreeMap<Amount, u64> {
+        match side {
+            OrderSide::Buy => &mut self.bids,
+            OrderSide::Sell => &mut self.asks,
+        }
+    }
+
+    /// the highest bid and the lowest ask.
+    fn best(&self) -> (Option<Amount>, Option<Amount>) {
+        (
+            self.bids.keys().next_back().copied(),
+            self.asks.keys().next().copied(),
+        )
+    }
+
+    fn add_trade(&mut self, trade: TickerTrade) {
+        self.last = Some(trade.price);
+        self.trades.push_back(trade);
+
+        let since = trade.timestamp.saturating_sub(TICKER_WINDOW_MS);
+        while self
+            .trades
+            .front()
+            .is_some_and(|trade| trade.timestamp < since)
+        {
+            self.trades.pop_front();
+        }
+    }
+
+    /// the ticker of the market as of the unix timestamp (in milliseconds) `now`.
+    fn ticker(&self, market: Market, now: u64) -> Ticker {
+        let since = now.saturating_sub(TICKER_WINDOW_MS);
+        let trades = self.trades.iter().filter(|trade| trade.timestamp >= since);
+
+        let mut high = None::<Amount>;
+        let mut low = None::<Amount>;
+        let mut volume = 0u64;
+        let mut first = None;
+        for trade in trades {
+            high = Some(high.map_or(trade.price, |high| high.max(trade.price)));
+            low = Some(low.map_or(trade.price, |low| low.min(trade.price)));
+            volume = volume.saturating_add(trade.quantity);
+            first.get_or_insert(trade.price);
+        }
+
+        let price = |price: Amount| format_decimal(price.get(), market.quote.decimals());
+        let (bid, ask) = self.best();
+
+        Ticker {
+            market,
+            last: self.last.map(price),
+            bid: bid.map(price),
+            ask: ask.map(price),
+            high: high.map(price),
+            low: low.map(price),
+            volume: format_decimal(volume, market.base.decimals()),
+            change_percent: first
+                .zip(self.last)
+                .map(|(first, last)| change_percent(first, last)),
+        }
+    }
+}
+
+/// the change from `from` to `to` in percent with two decimals.
+fn change_percent(from: Amount, to: Amount) -> String {
+    let basis_points =
+        (i128::from(to.get()) - i128::from(from.get())) * 10_000 / i128::from(from.get());
+    let sign = if basis_points < 0 { "-" } else { "" };
+    let basis_points = basis_points.unsigned_abs();
+
+    format!("{sign}{}.{:02}", basis_points / 100, basis_points % 100)
+}
+
+/// the current unix timestamp in milliseconds.
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// The price levels of every orderbook and the feed of their changes.
+#[derive(Debug)]
+pub struct MarketData {
+    books: RwLock<BTreeMap<Market, L2Book>>,
+    tx: broadcast::Sender<MarketDataMsg>,
+    ticker_tx: broadcast::Sender<Ticker>,
+}
+
+impl MarketData {
+    /// create empty market data, a subscriber can fall `capacity` messages behind.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            books: RwLock::new(BTreeMap::new()),
+            tx: broadcast::channel(capacity).0,
+            ticker_tx: broadcast::channel(capacity).0,
+        }
+    }
+
+    /// the ticker of the market.
+    pub fn ticker(&self, market: Market) -> Ticker {
+        let books = self.books.read().unwrap();
+        match books.get(&market) {
+            Some(book) => book.ticker(market, now_millis()),
+            None => L2Book::default().ticker(market, now_millis()),
+        }
+    }
+
+    /// the feed of every ticker that changed.
+    pub fn subscribe_tickers(&self) -> broadcast::Receiver<Ticker> {
+        self.ticker_tx.subscribe()
+    }
+
+    /// a snapshot of the market and the feed of every message after it.
+    pub fn subscribe(&self, market: Market) -> (MarketDataMsg, broadcast::Receiver<MarketDataMsg>) {
+        // messages are published while the books are locked for writing, so none is missed.
+        let books = self.books.read().unwrap();
+        let snapshot = Self::snapshot_of(market, books.get(&market));
+        (snapshot, self.tx.subscribe())
+    }
+
+    /// a snapshot of every price level of the market.
+    pub fn snapshot(&self, market: Market) -> MarketDataMsg {
+        Self::snapshot_of(market, self.books.read().unwrap().get(&market))
+    }
+
+    fn snapshot_of(market: Market, book: Option<&L2Book>) -> MarketDataMsg {
+        let levels = |levels: Vec<(&Amount, &u64)>| {
+            levels
+                .into_iter()
+                .map(|(&price, &quantity)| format_level(market, price, quantity))
+                .collect()
+        };
+
+        let body = match book {
+            Some(book) => MarketDataBody::Snapshot {
+                bids: levels(book.bids.iter().rev().collect()),
+                asks: levels(book.asks.iter().collect()),
+            },
+            None => MarketDataBody::Snapshot {
+                bids: vec![],
+                asks: vec![],
+            },
+        };
+
+        MarketDataMsg {
+            market,
+            seq: book.map_or(0, |book| book.seq),
+            body,
+        }
+    }
+
+    /// replace the price levels of every market with those of `snapshot`, publishing a snapshot
+    /// of each market so subscribers start over.
+    fn resync(&self, snapshot: &DepthSnapshot) {
+        let mut books = self.books.write().unwrap();
+
+        for (&market, depth) in &snapshot.books {
+            let book = books.entry(market).or_default();
+            book.bids = depth.bids.iter().copied().collect();
+            book.asks = depth.asks.iter().copied().collect();
+            book.seq += 1;
+
+            let _ = self.tx.send(Self::snapshot_of(market, Some(book)));
+            let _ = self.ticker_tx.send(book.ticker(market, now_millis()));
+        }
+    }
+
+    /// apply an execution report published after the snapshot numbered `snapshot_seq` the books
+    /// were resynced from, earlier ones are part of the snapshot already.
+    fn apply_after(&self, snapshot_seq: u64, event: &EngineEvent) {
+        if event.seq > snapshot_seq {
+            self.apply(event);
+        }
+    }
+
+    /// apply an execution report, publishing what changed.
+    fn apply(&self, event: &EngineEvent) {
+        let market = event.market;
+        let mut books = self.books.write().unwrap();
+        let book = books.entry(market).or_default();
+        let best = book.best();
+
+        let body = match event.kind {
+            EngineEventKind::BookDelta {
+                side,
+                price,
+                quantity,
+            } => {
+                match quantity {
+                    0 => book.levels_mut(side).remove(&price),
+                    quantity => book.levels_mut(side).insert(price, quantity),
+                };
+
+                let [price, quantity] = format_level(market, price, quantity);
+                MarketDataBody::L2Update {
+                    side,
+                    price,
+                    quantity,
+                }
+            }
+            EngineEventKind::Filled(fill) => {
+                book.add_trade(TickerTrade {
+                    timestamp: event.timestamp,
+                    price: fill.price,
+                    quantity: fill.quantity,
+                });
+
+                let [price, quantity] = format_level(market, fill.price, fill.quantity);
+                MarketDataBody::Trade {
+                    side: fill.taker_side,
+                    price,
+                    quantity,
+                }
+            }
+            _ => return,
+        };
+
+        book.seq += 1;
+        let _ = self.tx.send(MarketDataMsg {
+            market,
+            seq: book.seq,
+            body,
+        });
+
+        let traded = matches!(event.kind, EngineEventKind::Filled(_));
+        if traded || book.best() != best {
+            let _ = self.ticker_tx.send(book.ticker(market, event.timestamp));
+        }
+    }
+}
+
+/// a price and a quantity as decimal strings.
+fn format_level(market: Market, price: Amount, quantity: u64) -> [String; 2] {
+    [
+        format_decimal(price.get(), market.quote.decimals()),
+        format_decimal(quantity, market.base.decimals()),
+    ]
+}
+
+/// keep the market data of `app` up to date with the trading engine, starting over from a fresh
+/// snapshot whenever the execution reports could not be kept up with.
+pub async fn run(app: AppCx) {
+    loop {
+        // subscribed before the snapshot is taken so no event after it is missed.
+        let mut events = app.subscribe_engine_events();
+
+        let Some(snapshot) = app.depth_snapshot().await else {
+            tracing::warn!("trading engine did not send a depth snapshot");
+            tokio::time::sleep(RESYNC_DELAY).await;
+            continue;
+        };
+
+        app.market_data().resync(&snapshot);
+
+        loop {
+            match events.recv().await {
+                Ok(event) => app.market_data().apply_after(snapshot.seq, &event),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "market data fell behind the trading engine");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::fixtures;
+    use crate::trading::engine_event::Fill;
+    use crate::trading::{Depth, OrderUuid};
+
+    const BTC_USD: Market = Market::new(fixtures::BTC, fixtures::USD);
+    const ETH_USD: Market = Market::new(fixtures::ETH, fixtures::USD);
+
+    fn nz(n: u64) -> Amount {
+        Amount::new(n).unwrap()
+    }
+
+    fn delta(seq: u64, market: Market, side: OrderSide, price: u64, quantity: u64) -> EngineEvent {
+        EngineEvent {
+            seq,
+            timestamp: seq,
+            market,
+            kind: EngineEventKind::BookDelta {
+                side,
+                price: nz(price),
+                quantity,
+            },
+        }
+    }
+
+    fn filled(seq: u64, timestamp: u64, price: u64, quantity: u64) -> EngineEvent {
+        EngineEvent {
+            seq,
+            timestamp,
+            market: BTC_USD,
+            kind: EngineEventKind::Filled(Fill {
+                maker_order_uuid: OrderUuid::new_v4(),
+                maker_user_uuid: uuid::Uuid::nil(),
+                taker_order_uuid: OrderUuid::new_v4(),
+                taker_user_uuid: uuid::Uuid::nil(),
+                taker_side: OrderSide::Buy,
+                price: nz(price),
+                quantity,
+            }),
+        }
+    }
+
+    fn recv_all(rx: &mut broadcast::Receiver<MarketDataMsg>) -> Vec<MarketDataMsg> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn test_seq_per_market() {
+        let market_data = MarketData::new(16);
+        let (snapshot, mut rx) = market_data.subscribe(BTC_USD);
+        assert_eq!(snapshot.seq, 0);
+
+        market_data.apply(&delta(1, BTC_USD, OrderSide::Buy, 100, 5));
+        market_data.apply(&delta(2, ETH_USD, OrderSide::Sell, 200, 1));
+        market_data.apply(&filled(3, 3, 100, 2));
+        market_data.apply(&delta(4, BTC_USD, OrderSide::Buy, 100, 3));
+        market_data.apply(&delta(5, ETH_USD, OrderSide::Sell, 200, 0));
+
+        // every market numbers its messages on its own, without gaps.
+        let msgs = recv_all(&mut rx);
+        let seqs = |market| {
+            msgs.iter()
+                .filter(|msg| msg.market == market)
+                .map(|msg| msg.seq)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(seqs(BTC_USD), [1, 2, 3]);
+        assert_eq!(seqs(ETH_USD), [1, 2]);
+        assert!(matches!(
+            &msgs[2].body,
+            MarketDataBody::Trade { side: OrderSide::Buy, price, quantity }
+                if price == "1.00" && quantity == "0.00000002"
+        ));
+
+        // a later subscriber starts from the last message.
+        let (snapshot, _rx) = market_data.subscribe(BTC_USD);
+        assert_eq!(snapshot.seq, 3);
+        assert!(matches!(
+            snapshot.body,
+            MarketDataBody::Snapshot { ref bids, ref asks }
+                if bids == &[["1.00".to_string(), "0.00000003".to_string()]] && asks.is_empty()
+        ));
+        let (snapshot, _rx) = market_data.subscribe(ETH_USD);
+        assert_eq!(snapshot.seq, 2);
+    }
+
+    #[test]
+    fn test_resync() {
+        let market_data = MarketData::new(16);
+        market_data.apply(&delta(1, BTC_USD, OrderSide::Buy, 100, 5));
+        market_data.apply(&delta(2, BTC_USD, OrderSide::Sell, 120, 1));
+
+        let (_, mut rx) = market_data.subscribe(BTC_USD);
+
+        let snapshot = DepthSnapshot {
+            seq: 4,
+            books: BTreeMap::from([(
+                BTC_USD,
+                Depth {
+                    bids: vec![(nz(110), 2), (nz(100), 5)],
+                    asks: vec![],
+                },
+            )]),
+        };
+        market_data.resync(&snapshot);
+
+        // the snapshot replaces the levels and continues the numbering.
+        let msgs = recv_all(&mut rx);
+        let [msg] = &msgs[..] else {
+            panic!("expected one snapshot, got {msgs:?}");
+        };
+        assert_eq!(msg.seq, 3);
+        assert!(matches!(
+            &msg.body,
+            MarketDataBody::Snapshot { bids, asks } if bids.len() == 2 && asks.is_empty()
+        ));
+
+        // the events up to the snapshot are part of it already.
+        market_data.apply_after(snapshot.seq, &delta(3, BTC_USD, OrderSide::Sell, 120, 0));
+        market_data.apply_after(snapshot.seq, &delta(4, BTC_USD, OrderSide::Buy, 110, 2));
+        assert!(recv_all(&mut rx).is_empty());
+
+        market_data.apply_after(snapshot.seq, &delta(5, BTC_USD, OrderSide::Buy, 110, 0));
+        let msgs = recv_all(&mut rx);
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].seq, 4);
+        assert!(matches!(
+            market_data.snapshot(BTC_USD).body,
+            MarketDataBody::Snapshot { ref bids, .. } if bids.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_ticker_window() {
+        let mut book = L2Book::default();
+        let trade = |timestamp, price, quantity| TickerTrade {
+            timestamp,
+            price: nz(price),
+            quantity,
+        };
+
+        book.add_trade(trade(0, 100, 1));
+        book.add_trade(trade(1_000, 150, 2));
+        book.add_trade(trade(TICKER_WINDOW_MS + 500, 120, 3));
+
+        // a trade drops the trades from before the window ending with it.
+        assert_eq!(book.trades.len(), 2);
+
+        let ticker = book.ticker(BTC_USD, TICKER_WINDOW_MS + 1_000);
+        assert_eq!(ticker.last.as_deref(), Some("1.20"));
+        assert_eq!(ticker.high.as_deref(), Some("1.50"));
+        assert_eq!(ticker.low.as_deref(), Some("1.20"));
+        assert_eq!(ticker.volume, "0.00000005");
+        assert_eq!(ticker.change_percent.as_deref(), Some("-20.00"));
+
+        // the window ends when the ticker is asked for, not with the last trade.
+        let ticker = book.ticker(BTC_USD, TICKER_WINDOW_MS + 1_001);
+        assert_eq!(ticker.high.as_deref(), Some("1.20"));
+        assert_eq!(ticker.low.as_deref(), Some("1.20"));
+        assert_eq!(ticker.volume, "0.00000003");
+        assert_eq!(ticker.change_percent.as_deref(), Some("0.00"));
+
+        let ticker = book.ticker(BTC_USD, 2 * TICKER_WINDOW_MS + 501);
+        assert_eq!(ticker.last.as_deref(), Some("1.20"));
+        assert_eq!(ticker.high, None);
+        assert_eq!(ticker.low, None);
+        assert_eq!(ticker.volume, "0.00000000");
+        assert_eq!(ticker.change_percent, None);
+    }
+
+    #[test]
+    fn test_change_percent() {
+        assert_eq!(change_percent(nz(100), nz(101)), "1.00");
+        assert_eq!(change_percent(nz(100), nz(99)), "-1.00");
+        assert_eq!(change_percent(nz(1), nz(3)), "200.00");
+        assert_eq!(change_percent(nz(3), nz(2)), "-33.33");
+        assert_eq!(change_percent(nz(10_000), nz(9_999)), "-0.01");
+
+        // rounded towards zero, a drop too small to show has no sign.
+        assert_eq!(change_percent(nz(3), nz(4)), "33.33");
+        assert_eq!(change_percent(nz(100_000), nz(99_999)), "0.00");
+        assert_eq!(change_percent(nz(1_000_000_000_000), nz(1)), "-99.99");
+    }
+}
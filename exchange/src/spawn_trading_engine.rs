@@ -1,12 +1,17 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use futures::StreamExt;
-use tokio::sync::mpsc;
+use sqlx::PgConnection;
+use tokio::sync::{broadcast, mpsc};
 
-use crate::trading::{self, TradeCmd};
-use crate::{Asset, Configuration};
+use crate::trading::{self, AssetsSnapshot, EngineEvents, TradeCmd};
+use crate::{ledger, Configuration};
 
 pub struct SpawnTradingEngine {
     pub input: trading::TradingEngineTx,
     pub handle: tokio::task::JoinHandle<()>,
+    /// the execution reports of the trading engine, subscribe to receive them.
+    pub events: trading::EngineEventTx,
 }
 
 impl SpawnTradingEngine {
@@ -14,48 +19,317 @@ impl SpawnTradingEngine {
         self,
         db: sqlx::PgPool,
     ) -> Result<(trading::TradingEngineTx, tokio::task::JoinHandle<()>), sqlx::Error> {
-        let Self { input, handle } = self;
+        let Self { input, handle, .. } = self;
+
+        // events logged before amounts were fixed-point would be replayed at the wrong scale, see
+        // the cut-over in the migration adding `trading_event_source.fixed_point`.
+        let legacy = sqlx::query_scalar!(
+            "SELECT id FROM trading_event_source WHERE NOT fixed_point ORDER BY id LIMIT 1"
+        )
+        .fetch_optional(&db)
+        .await?;
+        if let Some(event_id) = legacy {
+            return Err(sqlx::Error::Decode(
+                format!("event {event_id} was logged before amounts were fixed-point").into(),
+            ));
+        }
+
+        // only the events logged after the newest snapshot have to be replayed.
+        let mut last_event_id = 0;
+        if let Some((snapshot_event_id, snapshot)) = fetch_latest_snapshot(&db).await? {
+            tracing::info!(snapshot_event_id, "restoring trading engine snapshot");
+            last_event_id = snapshot_event_id;
+            input
+                .send(trading::TradingEngineCmd::Restore((
+                    snapshot_event_id,
+                    Box::new(snapshot),
+                )))
+                .await
+                .unwrap();
+        }
+
+        // every market has to be traded before the events of its orders are replayed.
+        for listing in crate::asset::fetch_markets(&db).await? {
+            input
+                .send(trading::TradingEngineCmd::ListMarket((
+                    listing.market,
+                    listing.rules,
+                )))
+                .await
+                .unwrap();
+        }
 
         // stream out rows from the orders_event_source table, deserialize them into TradeCmds
         // and send them to the trading engine for processing.
-        let mut stream = sqlx::query!(r#"SELECT id, jstr FROM trading_event_source"#,).fetch(&db);
+        let mut stream = sqlx::query!(
+            r#"SELECT id, jstr FROM trading_event_source WHERE id > $1 ORDER BY id"#,
+            last_event_id
+        )
+        .fetch(&db);
 
         while let Some(row) = stream.next().await {
             let row = row?;
             let cmd: trading::TradeCmdPayload = serde_json::from_value(row.jstr).unwrap();
             input
-                .send(trading::TradingEngineCmd::Bootstrap(cmd))
+                .send(trading::TradingEngineCmd::Bootstrap((row.id, cmd)))
                 .await
                 .unwrap();
         }
 
+        input
+            .send(trading::TradingEngineCmd::BootstrapComplete)
+            .await
+            .unwrap();
+
         Ok((input, handle))
     }
 }
 
+/// fetch the newest snapshot of the trading engine and the id of the last event applied to it.
+///
+/// a snapshot that can not be decoded is skipped with an error log, the whole event log is
+/// replayed instead.
+async fn fetch_latest_snapshot(
+    db: &sqlx::PgPool,
+) -> Result<Option<(i64, AssetsSnapshot)>, sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"SELECT last_event_id, snapshot FROM trading_snapshots ORDER BY last_event_id DESC LIMIT 1"#
+    )
+    .fetch_optional(db)
+    .await?;
+
+    let Some(rec) = rec else {
+        return Ok(None);
+    };
+
+    match AssetsSnapshot::decode(&rec.snapshot) {
+        Ok(snapshot) => Ok(Some((rec.last_event_id, snapshot))),
+        Err(err) => {
+            tracing::error!(
+                ?err,
+                rec.last_event_id,
+                "failed to decode trading engine snapshot"
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// apply a command read back from the event log, its outcome was settled when it was logged.
+fn apply_logged(assets: &mut trading::Assets, payload: trading::TradeCmdPayload) {
+    use trading::TradeCmdPayload as P;
+
+    match payload {
+        P::PlaceOrder(place_order) => {
+            let _ = trading::do_place_order(assets, place_order);
+        }
+        P::AmendOrder(amend_order) => {
+            let _ = trading::do_amend_order(assets, amend_order);
+        }
+        P::CancelOrder(cancel_order) => {
+            let _ = trading::do_cancel_order(assets, cancel_order);
+        }
+        P::ExpireOrders(expire_orders) => {
+            let _ = trading::do_expire_orders(assets, expire_orders);
+        }
+        P::TriggerStops(trigger_stops) => {
+            let _ = trading::do_trigger_stops(assets, trigger_stops);
+        }
+        P::PlaceOco(place_oco) => {
+            let _ = trading::do_place_oco(assets, place_oco);
+        }
+        P::CancelOcoSiblings(cancel_siblings) => {
+            let _ = trading::do_cancel_oco_siblings(assets, cancel_siblings);
+        }
+        P::CancelAll(cancel_all) => {
+            let _ = trading::do_cancel_all(assets, cancel_all);
+        }
+    }
+}
+
+/// rebuild the state of the trading engine from the newest snapshot and the events logged after
+/// it, returns the state with the ids of the last applied event and of the snapshot's last event.
+async fn restore_from_log(db: &sqlx::PgPool) -> Result<(trading::Assets, i64, i64), sqlx::Error> {
+    let (snapshot_event_id, mut assets) = match fetch_latest_snapshot(db).await? {
+        Some((event_id, snapshot)) => (event_id, trading::Assets::restore(snapshot)),
+        None => (0, trading::Assets::new()),
+    };
+
+    for listing in crate::asset::fetch_markets(db).await? {
+        assets.list_market(listing.market, listing.rules);
+    }
+
+    let mut last_event_id = snapshot_event_id;
+    let mut stream = sqlx::query!(
+        r#"SELECT id, jstr FROM trading_event_source WHERE id > $1 ORDER BY id"#,
+        snapshot_event_id
+    )
+    .fetch(db);
+
+    while let Some(row) = stream.next().await {
+        let row = row?;
+        let payload =
+            serde_json::from_value(row.jstr).map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+        apply_logged(&mut assets, payload);
+        last_event_id = row.id;
+    }
+
+    Ok((assets, last_event_id, snapshot_event_id))
+}
+
+/// store a snapshot of the trading engine taken after the event `last_event_id`, only the two
+/// newest snapshots are kept.
+async fn insert_snapshot(db: sqlx::PgPool, last_event_id: i64, snapshot: Vec<u8>) {
+    let write = async {
+        let mut tx = db.begin().await?;
+
+        sqlx::query!(
+            "INSERT INTO trading_snapshots (last_event_id, snapshot) VALUES ($1, $2)",
+            last_event_id,
+            snapshot
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"DELETE FROM trading_snapshots WHERE id NOT IN
+                (SELECT id FROM trading_snapshots ORDER BY last_event_id DESC LIMIT 2)"#
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    };
+
+    match write.await {
+        Ok(()) => tracing::info!(last_event_id, "stored trading engine snapshot"),
+        Err(err) => tracing::error!(
+            ?err,
+            last_event_id,
+            "failed to store trading engine snapshot"
+        ),
+    }
+}
+
+/// the current unix timestamp in seconds.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// sleep until the unix timestamp `at` (in seconds) or forever if there is no deadline.
+async fn sleep_until_unix(at: Option<u64>) {
+    match at {
+        Some(at) => tokio::time::sleep(Duration::from_secs(at.saturating_sub(unix_now()))).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// check that an amendment can be applied and that the owner of the order can afford it, before it is logged.
+///
+/// the owner's balance stays locked until `tx` ends, the amendment has to be settled in it.
+async fn check_amend_order(
+    tx: &mut PgConnection,
+    assets: &mut trading::Assets,
+    amend_order: &trading::AmendOrder,
+) -> Result<(), trading::TradingEngineError> {
+    let (market, order_index, order) = trading::check_amend_order(assets, amend_order)?;
+
+    let affordable = ledger::can_hold_amended_order(
+        tx,
+        market,
+        order_index.side(),
+        &order,
+        amend_order.price(),
+        amend_order.quantity(),
+    )
+    .await?;
+
+    if affordable {
+        Ok(())
+    } else {
+        Err(trading::AmendOrderError::InsufficientFunds.into())
+    }
+}
+
 pub fn spawn_trading_engine(config: &Configuration, db: sqlx::PgPool) -> SpawnTradingEngine {
     use trading::TradingEngineCmd as T;
 
-    async fn trading_engine_supervisor(mut rx: mpsc::Receiver<T>, db: sqlx::PgPool) {
-        use trading::{AssetBook, Assets, TradeCmdPayload as P};
+    async fn trading_engine_supervisor(
+        mut rx: mpsc::Receiver<T>,
+        db: sqlx::PgPool,
+        snapshot_interval: i64,
+        mut events: EngineEvents,
+    ) {
+        use trading::{Assets, ExpireOrders};
+
+        let mut assets = Assets::new();
+
+        // the id of the last event applied to `assets` and of the last event in a snapshot.
+        let mut last_event_id = 0;
+        let mut snapshot_event_id = 0;
 
-        let mut assets = Assets {
-            order_uuids: Default::default(),
-            eth: AssetBook::new(Asset::Ether),
-            btc: AssetBook::new(Asset::Bitcoin),
-        };
+        // whether the state could neither be logged nor restored, the engine stops so it is
+        // restored from the event log on restart.
+        let mut halted = false;
 
+        // log the input to the event source and, in the same database transaction, post the
+        // ledger entries for the outcome. a check can be run in the transaction first, the input
+        // is neither applied nor logged when it fails. the input is applied before it is logged,
+        // the state is restored from the event log if logging it fails.
         macro_rules! try_event_log {
-            ($input:expr, $e:expr) => {
+            ($input:expr, $e:expr, $settle:expr) => {
+                try_event_log!($input, |tx| Ok::<(), trading::TradingEngineError>(()), $e, $settle)
+            };
+            ($input:expr, |$tx:ident| $check:expr, $e:expr, $settle:expr) => {
                 if let Ok(jstr) = ::serde_json::to_value(&$input) {
-                    let res: Result<_, trading::TradingEngineError> = $e;
-
-                    match sqlx::query!("INSERT INTO trading_event_source (jstr) VALUES ($1)", jstr)
-                        .execute(&db)
-                        .await
-                    {
-                        Ok(_) => res,
-                        Err(e) => Err(trading::TradingEngineError::Database(e)),
+                    let mut applied = false;
+                    let write = async {
+                        let mut $tx = db.begin().await?;
+                        $check?;
+
+                        applied = true;
+                        let res: Result<_, trading::TradingEngineError> = $e;
+
+                        let event_id = sqlx::query!(
+                            "INSERT INTO trading_event_source (jstr) VALUES ($1) RETURNING id",
+                            jstr
+                        )
+                        .fetch_one(&mut *$tx)
+                        .await?
+                        .id;
+
+                        if let Ok(t) = &res {
+                            $settle(&mut *$tx, t).await?;
+                        }
+
+                        $tx.commit().await?;
+                        Ok::<_, trading::TradingEngineError>((event_id, res))
+                    };
+
+                    match write.await {
+                        Ok((event_id, res)) => {
+                            last_event_id = event_id;
+                            res
+                        }
+                        Err(e) if applied => {
+                            tracing::error!(?e, "failed to log an applied command, restoring the trading engine");
+                            match restore_from_log(&db).await {
+                                Ok((restored, restored_event_id, restored_snapshot_id)) => {
+                                    assets = restored;
+                                    last_event_id = restored_event_id;
+                                    snapshot_event_id = restored_snapshot_id;
+                                }
+                                Err(err) => {
+                                    tracing::error!(?err, "failed to restore the trading engine");
+                                    halted = true;
+                                }
+                            }
+                            Err(e)
+                        }
+                        Err(e) => Err(e),
                     }
                 } else {
                     Err(trading::TradingEngineError::UnserializableInput)
@@ -63,7 +337,20 @@ pub fn spawn_trading_engine(config: &Configuration, db: sqlx::PgPool) -> SpawnTr
             };
         }
         let mut running = true;
-        while let Some(cmd) = rx.recv().await {
+        let mut bootstrapped = false;
+        loop {
+            // good-til-date deadlines are only acted on once the event log has been replayed,
+            // otherwise orders could expire out of order with the recorded events.
+            let next_expiry = assets.next_expiry().filter(|_| running && bootstrapped);
+
+            let cmd = tokio::select! {
+                cmd = rx.recv() => match cmd {
+                    Some(cmd) => cmd,
+                    None => break,
+                },
+                () = sleep_until_unix(next_expiry) => T::Expire(ExpireOrders::new(unix_now())),
+            };
+
             if !running {
                 continue;
             }
@@ -76,27 +363,207 @@ pub fn spawn_trading_engine(config: &Configuration, db: sqlx::PgPool) -> SpawnTr
                     running = true;
                 }
                 T::Shutdown => break,
+                T::ListMarket((market, rules)) => {
+                    if assets.list_market(market, rules) {
+                        tracing::info!(%market, "listed market");
+                    }
+                }
+                T::Depth(response) => {
+                    let _ = response.send(events.depth_snapshot(&assets));
+                }
+                T::Book((market, levels, response)) => {
+                    let book = assets.books.get(&market);
+                    let _ = response.send(book.map(|book| book.orderbook().book_orders(levels)));
+                }
+                T::OpenOrders((user_uuid, response)) => {
+                    let _ = response.send(assets.open_orders(user_uuid));
+                }
                 T::Trade(TradeCmd::PlaceOrder((place_order, response))) => {
+                    let (market, user_uuid, order_uuid) = (
+                        place_order.market(),
+                        place_order.user_uuid(),
+                        place_order.order_uuid(),
+                    );
+
                     let t = try_event_log!(
                         place_order,
-                        trading::do_place_order(&mut assets, place_order)
+                        trading::do_place_order(&mut assets, place_order),
+                        ledger::settle_place_order
                     );
 
+                    match &t {
+                        Ok(placed) => events.placed(&assets, placed),
+                        Err(err) => events.rejected(market, user_uuid, order_uuid, err),
+                    }
+
                     let _ = response.send(t);
                 }
                 T::Trade(TradeCmd::CancelOrder((cancel_order, response))) => {
                     let t = try_event_log!(
                         cancel_order,
-                        trading::do_cancel_order(&mut assets, cancel_order)
+                        trading::do_cancel_order(&mut assets, cancel_order),
+                        ledger::settle_cancel_order
                     );
 
+                    if let Ok(cancelled) = &t {
+                        events.cancelled(&assets, std::slice::from_ref(cancelled));
+                    }
+
                     let _ = response.send(t);
                 }
-                T::Bootstrap(P::PlaceOrder(place_order)) => {
-                    let _ = trading::do_place_order(&mut assets, place_order);
+                T::Trade(TradeCmd::CancelAll((cancel_all, response))) => {
+                    let t = try_event_log!(
+                        cancel_all,
+                        trading::do_cancel_all(&mut assets, cancel_all),
+                        ledger::settle_cancel_orders
+                    );
+
+                    if let Ok(cancelled) = &t {
+                        events.cancelled(&assets, cancelled);
+                    }
+
+                    let _ = response.send(t);
                 }
-                T::Bootstrap(P::CancelOrder(cancel_order)) => {
-                    let _ = trading::do_cancel_order(&mut assets, cancel_order);
+                T::Trade(TradeCmd::PlaceOco((place_oco, response))) => {
+                    let legs: Vec<_> = place_oco
+                        .legs()
+                        .iter()
+                        .map(|leg| (leg.market(), leg.user_uuid(), leg.order_uuid()))
+                        .collect();
+
+                    let t = try_event_log!(
+                        place_oco,
+                        trading::do_place_oco(&mut assets, place_oco),
+                        ledger::settle_place_oco
+                    );
+
+                    match &t {
+                        Ok(placed) => events.placed_oco(&assets, placed),
+                        Err(err) => {
+                            for (market, user_uuid, order_uuid) in legs {
+                                events.rejected(market, user_uuid, order_uuid, err);
+                            }
+                        }
+                    }
+
+                    let _ = response.send(t);
+                }
+                T::Trade(TradeCmd::AmendOrder((amend_order, response))) => {
+                    let (market, user_uuid, order_uuid) = (
+                        amend_order.market(),
+                        amend_order.user_uuid(),
+                        amend_order.order_uuid(),
+                    );
+
+                    let t = try_event_log!(
+                        amend_order,
+                        |tx| check_amend_order(&mut tx, &mut assets, &amend_order).await,
+                        trading::do_amend_order(&mut assets, amend_order),
+                        ledger::settle_amend_order
+                    );
+
+                    match &t {
+                        Ok(amended) => events.amended(&assets, amended),
+                        Err(err) => events.rejected(market, user_uuid, order_uuid, err),
+                    }
+
+                    let _ = response.send(t);
+                }
+                T::Expire(expire_orders) => {
+                    let t = try_event_log!(
+                        expire_orders,
+                        trading::do_expire_orders(&mut assets, expire_orders),
+                        ledger::settle_cancel_orders
+                    );
+
+                    match t {
+                        Ok(expired) => {
+                            events.expired(&assets, &expired);
+                            tracing::info!(count = expired.len(), "expired orders");
+                        }
+                        Err(err) => tracing::error!(?err, "failed to expire orders"),
+                    }
+                }
+                T::Restore((event_id, snapshot)) => {
+                    assets = Assets::restore(*snapshot);
+                    last_event_id = event_id;
+                    snapshot_event_id = event_id;
+                }
+                T::Bootstrap((event_id, payload)) => {
+                    last_event_id = event_id;
+                    apply_logged(&mut assets, payload);
+                }
+                T::BootstrapComplete => {
+                    bootstrapped = true;
+                }
+            }
+
+            // linked orders may have lost their sibling and trades may have crossed the stop price
+            // of stop orders. each follow-up is logged as its own command so replaying the log
+            // repeats it at the same point, siblings are cancelled first so they never trigger.
+            loop {
+                if let Some(cancel_siblings) = assets.next_oco_cancels().filter(|_| bootstrapped) {
+                    let t = try_event_log!(
+                        cancel_siblings,
+                        trading::do_cancel_oco_siblings(&mut assets, cancel_siblings),
+                        ledger::settle_cancel_orders
+                    );
+
+                    match t {
+                        Ok(t) => {
+                            events.cancelled(&assets, &t);
+                            tracing::info!(count = t.len(), "cancelled linked orders");
+                        }
+                        Err(err) => {
+                            tracing::error!(?err, "failed to cancel linked orders");
+                            break;
+                        }
+                    }
+                } else if let Some(trigger_stops) =
+                    assets.next_triggered_stops().filter(|_| bootstrapped)
+                {
+                    let t = try_event_log!(
+                        trigger_stops,
+                        trading::do_trigger_stops(&mut assets, trigger_stops),
+                        ledger::settle_trigger_stops
+                    );
+
+                    match t {
+                        Ok(t) => {
+                            events.triggered(&assets, &t);
+                            tracing::info!(
+                                placed = t.placed.len(),
+                                rejected = t.rejected.len(),
+                                "triggered stop orders"
+                            );
+                        }
+                        Err(err) => {
+                            tracing::error!(?err, "failed to trigger stop orders");
+                            break;
+                        }
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            if halted {
+                break;
+            }
+
+            // snapshots are only taken once every follow-up of the last event has been logged,
+            // the snapshot is stored in the background so trading is not held up.
+            if bootstrapped
+                && snapshot_interval > 0
+                && last_event_id - snapshot_event_id >= snapshot_interval
+            {
+                snapshot_event_id = last_event_id;
+
+                match assets.snapshot().encode() {
+                    Ok(snapshot) => {
+                        tokio::spawn(insert_snapshot(db.clone(), last_event_id, snapshot));
+                    }
+                    Err(err) => tracing::error!(?err, "failed to encode trading engine snapshot"),
                 }
             }
         }
@@ -104,8 +571,20 @@ pub fn spawn_trading_engine(config: &Configuration, db: sqlx::PgPool) -> SpawnTr
         tracing::warn!("trading engine supervisor finished");
     }
 
+    let snapshot_interval = i64::try_from(config.te_snapshot_interval).unwrap_or(i64::MAX);
+
     let (input, output) = mpsc::channel(config.te_channel_capacity);
-    let handle = tokio::spawn(trading_engine_supervisor(output, db));
+    let (events, _) = broadcast::channel(config.te_event_channel_capacity);
+    let handle = tokio::spawn(trading_engine_supervisor(
+        output,
+        db,
+        snapshot_interval,
+        EngineEvents::new(events.clone()),
+    ));
 
-    SpawnTradingEngine { input, handle }
+    SpawnTradingEngine {
+        input,
+        handle,
+        events,
+    }
 }
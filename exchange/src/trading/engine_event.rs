@@ -0,0 +1,406 @@
+//! Execution reports published by the trading engine.
+//!
+//! Once a command has been logged the trading engine publishes what it did to each order as
+//! [`EngineEvent`]s on a broadcast channel, followed by the new quantity of every price level that
+//! changed. Events are numbered in the order they are published so a subscriber that lagged
+//! behind can tell it missed some, the numbering starts over whenever the trading engine starts.
+//! Nothing is published while the event log is replayed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::sync::{broadcast, oneshot};
+
+use super::{
+    AmendOrderResult, Assets, CancelOrderResult, Depth, OrderSide, OrderType, OrderUuid,
+    PlaceOcoResult, PlaceOrderResult, TradingEngineError, TriggerStopsResult,
+};
+use crate::{Amount, Market};
+
+/// type-alias for a [`tokio::sync::broadcast::Sender`] that publishes [EngineEvent]s.
+pub type EngineEventTx = broadcast::Sender<EngineEvent>;
+
+/// type-alias for a [`tokio::sync::broadcast::Receiver`] that receives [EngineEvent]s.
+pub type EngineEventRx = broadcast::Receiver<EngineEvent>;
+
+/// An execution report of the trading engine.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EngineEvent {
+    /// the number of the event, one more than the event published before it.
+    pub seq: u64,
+    /// the unix timestamp (in milliseconds) the event was published at.
+    pub timestamp: u64,
+    /// the market the event happened in.
+    pub market: Market,
+    /// what happened.
+    #[serde(flatten)]
+    pub kind: EngineEventKind,
+}
+
+/// What an [`EngineEvent`] reports.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineEventKind {
+    /// an order was accepted, its trades follow as [`EngineEventKind::Filled`].
+    Accepted {
+        /// the accepted order.
+        order_uuid: OrderUuid,
+        /// the user that placed the order.
+        user_uuid: uuid::Uuid,
+        /// the side of the order, buy or sell.
+        side: OrderSide,
+        /// the type of the order, triggered stop orders are reported as the type they are placed as.
+        order_type: OrderType,
+        /// the price of the order, after repricing a post-only order.
+        price: Amount,
+        /// the quantity of the order.
+        quantity: Amount,
+    },
+    /// an order or an amendment was rejected.
+    Rejected {
+        /// the rejected order.
+        order_uuid: OrderUuid,
+        /// the user that placed the order.
+        user_uuid: uuid::Uuid,
+        /// why it was rejected.
+        reason: String,
+    },
+    /// a resting order traded with an incoming order.
+    Filled(Fill),
+    /// an order, or part of it, was cancelled.
+    ///
+    /// this covers cancellations by the user, by self-trade protection, of linked orders and of
+    /// the remainder of an order that could not rest in the orderbook.
+    Cancelled {
+        /// the cancelled order.
+        order_uuid: OrderUuid,
+        /// the user that placed the order.
+        user_uuid: uuid::Uuid,
+        /// the side of the order, buy or sell.
+        side: OrderSide,
+        /// the cancelled quantity.
+        quantity: u64,
+    },
+    /// a good-til-date order reached its deadline.
+    Expired {
+        /// the expired order.
+        order_uuid: OrderUuid,
+        /// the user that placed the order.
+        user_uuid: uuid::Uuid,
+        /// the side of the order, buy or sell.
+        side: OrderSide,
+        /// the quantity that was left.
+        quantity: u64,
+    },
+    /// a resting order was amended.
+    Amended {
+        /// the amended order.
+        order_uuid: OrderUuid,
+        /// the user that placed the order.
+        user_uuid: uuid::Uuid,
+        /// the side of the order, buy or sell.
+        side: OrderSide,
+        /// the new price of the order.
+        price: Amount,
+        /// the new total quantity of the order.
+        quantity: Amount,
+    },
+    /// the displayed quantity of a price level changed.
+    BookDelta {
+        /// the side of the orderbook.
+        side: OrderSide,
+        /// the price of the level.
+        price: Amount,
+        /// the displayed quantity of the level now, zero once the level is gone.
+        quantity: u64,
+    },
+}
+
+/// A trade between a resting (maker) order and an incoming (taker) order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Fill {
+    /// the resting order.
+    pub maker_order_uuid: OrderUuid,
+    /// the user that placed the resting order.
+    pub maker_user_uuid: uuid::Uuid,
+    /// the incoming order.
+    pub taker_order_uuid: OrderUuid,
+    /// the user that placed the incoming order.
+    pub taker_user_uuid: uuid::Uuid,
+    /// the side of the incoming order, buy or sell.
+    pub taker_side: OrderSide,
+    /// the price of the trade, the price of the resting order.
+    pub price: Amount,
+    /// the traded quantity.
+    pub quantity: u64,
+}
+
+/// The depth of every orderbook right after an event was published, subscribers that apply the
+/// events after it to the depth keep an up to date copy of the orderbooks.
+#[derive(Debug, Clone, Default)]
+pub struct DepthSnapshot {
+    /// the number of the last event published before the snapshot was taken.
+    pub seq: u64,
+    /// the depth of the orderbook of every traded market.
+    pub books: BTreeMap<Market, Depth>,
+}
+
+/// type-alias for a [`tokio::sync::oneshot::Sender`] that sends a [DepthSnapshot].
+pub type DepthSnapshotTx = oneshot::Sender<DepthSnapshot>;
+
+/// Publishes the [`EngineEvent`]s of the trading engine.
+pub struct EngineEvents {
+    tx: EngineEventTx,
+    /// the number of the last published event.
+    seq: u64,
+    /// the price levels changed by the reported command, published once its orders are reported.
+    touched: BTreeSet<(Market, OrderSide, Amount)>,
+}
+
+impl EngineEvents {
+    /// publish events on `tx`, the first event is numbered 1.
+    pub fn new(tx: EngineEventTx) -> Self {
+        Self {
+            tx,
+            seq: 0,
+            touched: BTreeSet::new(),
+        }
+    }
+
+    /// the number of the last published event.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// the depth of every orderbook of `assets` as of the last published event.
+    pub fn depth_snapshot(&self, assets: &Assets) -> DepthSnapshot {
+        DepthSnapshot {
+            seq: self.seq,
+            books: assets
+                .books
+                .iter()
+                .map(|(&market, book)| (market, book.orderbook().depth(usize::MAX)))
+                .collect(),
+        }
+    }
+
+    /// report a placed order.
+    pub fn placed(&mut self, assets: &Assets, result: &PlaceOrderResult) {
+        self.record_placed(result);
+        self.publish_book(assets);
+    }
+
+    /// report two placed linked orders.
+    pub fn placed_oco(&mut self, assets: &Assets, result: &PlaceOcoResult) {
+        for placed in &result.placed {
+            self.record_placed(placed);
+        }
+        self.record_cancelled(&result.cancelled);
+        self.publish_book(assets);
+    }
+
+    /// report triggered stop orders.
+    pub fn triggered(&mut self, assets: &Assets, result: &TriggerStopsResult) {
+        for placed in &result.placed {
+            self.record_placed(placed);
+        }
+        self.record_cancelled(&result.rejected);
+        self.publish_book(assets);
+    }
+
+    /// report cancelled orders.
+    pub fn cancelled(&mut self, assets: &Assets, cancelled: &[CancelOrderResult]) {
+        self.record_cancelled(cancelled);
+        self.publish_book(assets);
+    }
+
+    /// report expired good-til-date orders.
+    pub fn expired(&mut self, assets: &Assets, expired: &[CancelOrderResult]) {
+        for cancelled in expired {
+            self.touch_resting(cancelled);
+            self.publish(
+                cancelled.market,
+                EngineEventKind::Expired {
+                    order_uuid: cancelled.order.order_uuid,
+                    user_uuid: cancelled.order.owner,
+                    side: cancelled.side,
+                    quantity: cancelled.order.total_quantity().get(),
+                },
+            );
+        }
+        self.publish_book(assets);
+    }
+
+    /// report an amended order.
+    pub fn amended(&mut self, assets: &Assets, result: &AmendOrderResult) {
+        let AmendOrderResult {
+            market,
+            side,
+            previous,
+            order,
+            ..
+        } = *result;
+
+        self.touched.insert((market, side, previous.price));
+        self.touched.insert((market, side, order.price));
+        self.publish(
+            market,
+            EngineEventKind::Amended {
+                order_uuid: order.order_uuid,
+                user_uuid: order.owner,
+                side,
+                price: order.price,
+                quantity: order.total_quantity(),
+            },
+        );
+        self.publish_book(assets);
+    }
+
+    /// report a rejected order or amendment.
+    pub fn rejected(
+        &mut self,
+        market: Market,
+        user_uuid: uuid::Uuid,
+        order_uuid: OrderUuid,
+        err: &TradingEngineError,
+    ) {
+        self.publish(
+            market,
+            EngineEventKind::Rejected {
+                order_uuid,
+                user_uuid,
+                reason: err.to_string(),
+            },
+        );
+    }
+
+    fn record_placed(&mut self, result: &PlaceOrderResult) {
+        let market = result.market;
+        let maker_side = match result.side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+
+        self.publish(
+            market,
+            EngineEventKind::Accepted {
+                order_uuid: result.order_uuid,
+                user_uuid: result.user_uuid,
+                side: result.side,
+                order_type: result.order_type,
+                price: result.repriced.unwrap_or(result.price),
+                quantity: result.quantity,
+            },
+        );
+
+        for cancel in &result.self_trade_cancels {
+            self.touched
+                .insert((market, maker_side, cancel.maker.price));
+            self.publish(
+                market,
+                EngineEventKind::Cancelled {
+                    order_uuid: cancel.maker.order_uuid,
+                    user_uuid: cancel.maker.owner,
+                    side: maker_side,
+                    quantity: cancel.cancel_amount,
+                },
+            );
+        }
+
+        for fill in &result.fills {
+            self.touched.insert((market, maker_side, fill.maker.price));
+            self.publish(
+                market,
+                EngineEventKind::Filled(Fill {
+                    maker_order_uuid: fill.maker.order_uuid,
+                    maker_user_uuid: fill.maker.owner,
+                    taker_order_uuid: result.order_uuid,
+                    taker_user_uuid: result.user_uuid,
+                    taker_side: result.side,
+                    price: fill.maker.price,
+                    quantity: fill.fill_amount,
+                }),
+            );
+        }
+
+        // whatever did not trade either rests, waits for its stop price or is gone.
+        let unfilled = result.quantity.get().saturating_sub(result.quantity_filled);
+        match result.order_index {
+            Some(order_index) => {
+                self.touched
+                    .insert((market, order_index.side(), order_index.price()));
+            }
+            None if !result.order_type.is_stop() && unfilled > 0 => {
+                self.publish(
+                    market,
+                    EngineEventKind::Cancelled {
+                        order_uuid: result.order_uuid,
+                        user_uuid: result.user_uuid,
+                        side: result.side,
+                        quantity: unfilled,
+                    },
+                );
+            }
+            None => {}
+        }
+    }
+
+    fn record_cancelled(&mut self, cancelled: &[CancelOrderResult]) {
+        for cancelled in cancelled {
+            self.touch_resting(cancelled);
+            self.publish(
+                cancelled.market,
+                EngineEventKind::Cancelled {
+                    order_uuid: cancelled.order.order_uuid,
+                    user_uuid: cancelled.order.owner,
+                    side: cancelled.side,
+                    quantity: cancelled.order.total_quantity().get(),
+                },
+            );
+        }
+    }
+
+    /// remember the price level of an order that left the orderbook, stop orders were never in it.
+    fn touch_resting(&mut self, cancelled: &CancelOrderResult) {
+        if !cancelled.order_type.is_stop() {
+            self.touched
+                .insert((cancelled.market, cancelled.side, cancelled.order.price));
+        }
+    }
+
+    /// publish the quantity of every price level changed since the last call.
+    fn publish_book(&mut self, assets: &Assets) {
+        for (market, side, price) in std::mem::take(&mut self.touched) {
+            let quantity = assets
+                .books
+                .get(&market)
+                .map_or(0, |book| book.orderbook().level_quantity(side, price));
+
+            self.publish(
+                market,
+                EngineEventKind::BookDelta {
+                    side,
+                    price,
+                    quantity,
+                },
+            );
+        }
+    }
+
+    fn publish(&mut self, market: Market, kind: EngineEventKind) {
+        self.seq += 1;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+
+        // nobody may be subscribed, the event is dropped then.
+        let _ = self.tx.send(EngineEvent {
+            seq: self.seq,
+            timestamp,
+            market,
+            kind,
+        });
+    }
+}
@@ -0,0 +1,131 @@
+//! Price and quantity increments and limits of a market.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{OrderType, PlaceOrder};
+use crate::Amount;
+
+/// The prices and quantities a market accepts.
+///
+/// The defaults accept any price and quantity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarketRules {
+    /// prices (including stop and protection prices) must be a multiple of this.
+    pub tick_size: Amount,
+    /// quantities (including displayed quantities) must be a multiple of this.
+    pub lot_size: Amount,
+    /// the smallest quantity of an order.
+    pub min_quantity: Amount,
+    /// the largest quantity of an order, if there is one.
+    pub max_quantity: Option<Amount>,
+    /// the smallest value of an order in the quote asset, its notional or its quote amount.
+    pub min_notional: u64,
+}
+
+impl Default for MarketRules {
+    fn default() -> Self {
+        Self {
+            tick_size: Amount::MIN,
+            lot_size: Amount::MIN,
+            min_quantity: Amount::MIN,
+            max_quantity: None,
+            min_notional: 0,
+        }
+    }
+}
+
+/// Error returned when an order breaks the rules of its market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum MarketRuleError {
+    /// a price is not a multiple of the tick size.
+    #[error("prices must be a multiple of the tick size {0}")]
+    PriceOffTick(Amount),
+    /// a quantity is not a multiple of the lot size.
+    #[error("quantities must be a multiple of the lot size {0}")]
+    QuantityOffLot(Amount),
+    /// the quantity is below the minimum quantity.
+    #[error("the quantity must be at least {0}")]
+    QuantityTooSmall(Amount),
+    /// the quantity is above the maximum quantity.
+    #[error("the quantity must be at most {0}")]
+    QuantityTooLarge(Amount),
+    /// the value of the order is below the minimum notional.
+    #[error("the order must be worth at least {0}")]
+    NotionalTooSmall(u64),
+}
+
+impl MarketRules {
+    /// check that `price` is on a tick.
+    pub fn check_price(&self, price: Amount) -> Result<(), MarketRuleError> {
+        match price.get() % self.tick_size.get() {
+            0 => Ok(()),
+            _ => Err(MarketRuleError::PriceOffTick(self.tick_size)),
+        }
+    }
+
+    /// check that `quantity` is a whole number of lots within the quantity limits.
+    pub fn check_quantity(&self, quantity: Amount) -> Result<(), MarketRuleError> {
+        if quantity.get() % self.lot_size.get() != 0 {
+            return Err(MarketRuleError::QuantityOffLot(self.lot_size));
+        }
+
+        if quantity < self.min_quantity {
+            return Err(MarketRuleError::QuantityTooSmall(self.min_quantity));
+        }
+
+        match self.max_quantity {
+            Some(max_quantity) if quantity > max_quantity => {
+                Err(MarketRuleError::QuantityTooLarge(max_quantity))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// check that an order worth `notional` in the quote asset is worth at least the minimum notional.
+    pub fn check_notional(&self, notional: u64) -> Result<(), MarketRuleError> {
+        if notional < self.min_notional {
+            return Err(MarketRuleError::NotionalTooSmall(self.min_notional));
+        }
+
+        Ok(())
+    }
+
+    /// check every price and quantity of an order.
+    ///
+    /// the price of a (stop-)market order only bounds its reservation so it is not checked
+    /// against the tick size.
+    pub fn check_order(&self, place_order: &PlaceOrder) -> Result<(), MarketRuleError> {
+        if matches!(
+            place_order.order_type,
+            OrderType::Limit | OrderType::StopLimit
+        ) {
+            self.check_price(place_order.price)?;
+        }
+
+        for price in [place_order.stop_price, place_order.protection_price]
+            .into_iter()
+            .flatten()
+        {
+            self.check_price(price)?;
+        }
+
+        self.check_quantity(place_order.quantity)?;
+
+        if let Some(display_quantity) = place_order.display_quantity {
+            if display_quantity.get() % self.lot_size.get() != 0 {
+                return Err(MarketRuleError::QuantityOffLot(self.lot_size));
+            }
+        }
+
+        let notional = match place_order.quote_amount {
+            Some(quote_amount) => quote_amount.get(),
+            None => place_order
+                .market
+                .notional(place_order.price, place_order.quantity.get()),
+        };
+
+        self.check_notional(notional)
+    }
+}
@@ -1,15 +1,20 @@
 //! Trading module for the exchange, contains the orderbook and order matching logic.
 
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
 use std::num::NonZeroU32;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::{mpsc, oneshot};
 
-use crate::Asset;
+use crate::{amount, ledger, Amount, Market};
 
 pub mod orderbook;
-pub use orderbook::{Order, OrderIndex, OrderSide, OrderType, Orderbook};
+pub use orderbook::{
+    BookOrder, BookOrders, Depth, Order, OrderIndex, OrderSide, OrderType, Orderbook,
+};
 
 pub mod self_trade_protection;
 pub use self_trade_protection::SelfTradeProtection;
@@ -17,21 +22,48 @@ pub use self_trade_protection::SelfTradeProtection;
 pub mod timeinforce;
 pub use timeinforce::TimeInForce;
 
+pub mod post_only;
+pub use post_only::PostOnly;
+
+pub mod stop_book;
+pub use stop_book::StopBook;
+
+pub mod market_rules;
+pub use market_rules::{MarketRuleError, MarketRules};
+
+pub mod snapshot;
+pub use snapshot::AssetsSnapshot;
+
+pub mod replay;
+pub use replay::{Divergence, Replay, ReplayReport};
+
+pub mod engine_event;
+pub use engine_event::{
+    DepthSnapshot, DepthSnapshotTx, EngineEvent, EngineEventKind, EngineEventRx, EngineEventTx,
+    EngineEvents,
+};
+
 pub mod pending_fill;
-pub use pending_fill::{ExecutePendingFillError, FillType, PendingFill};
+pub use pending_fill::{
+    CommittedFill, ExecutePendingFillError, FillType, MakerFill, PendingFill, SelfTradeCancel,
+};
 
 pub mod try_fill_order;
-pub use try_fill_order::{try_fill_orders, TryFillOrdersError};
+pub use try_fill_order::{
+    try_fill_orders, try_fill_orders_bounded, FillBounds, TryFillOrdersError,
+};
 
 mod te_response;
 pub use te_response::TeResponse;
 
-
 /// The unique identifier for an order.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
 pub struct OrderUuid(pub uuid::Uuid);
 impl OrderUuid {
-    fn new_v4() -> OrderUuid {
+    /// generate a new random order uuid.
+    pub fn new_v4() -> OrderUuid {
         OrderUuid(uuid::Uuid::new_v4())
     }
 }
@@ -43,16 +75,18 @@ pub type TradingEngineTx = mpsc::Sender<TradingEngineCmd>;
 pub type TradingEngineRx = mpsc::Receiver<TradingEngineCmd>;
 
 /// Data for placing an order.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PlaceOrder {
-    /// the asset to trade
-    asset: Asset,
+    /// the market to trade in
+    market: Market,
     /// the user that placed the order
     user_uuid: uuid::Uuid,
+    /// the unique identifier of the order, assigned before the order is logged so replays are deterministic.
+    order_uuid: OrderUuid,
     /// the price of the order
-    price: NonZeroU32,
+    price: Amount,
     /// the quantity of the order
-    quantity: NonZeroU32,
+    quantity: Amount,
     /// the type of order
     order_type: OrderType,
     /// the self trade protection setting
@@ -61,6 +95,31 @@ pub struct PlaceOrder {
     time_in_force: TimeInForce,
     /// the side of the order, buy or sell
     side: OrderSide,
+    /// the unix timestamp (in seconds) a good-til-date order expires at
+    #[serde(default)]
+    expire_at: Option<u64>,
+    /// the last trade price that triggers a stop order
+    #[serde(default)]
+    stop_price: Option<Amount>,
+    /// the post-only setting, `None` for orders that may take liquidity
+    #[serde(default)]
+    post_only: Option<PostOnly>,
+    /// the quantity an iceberg order displays in the orderbook while it rests, `None` to display all of it
+    #[serde(default)]
+    display_quantity: Option<Amount>,
+    /// the most of the quote currency a market buy spends, `quantity` then only caps how much is bought
+    #[serde(default)]
+    quote_amount: Option<Amount>,
+    /// market orders do not match resting orders priced above this (for buys) or below it (for sells)
+    #[serde(default)]
+    protection_price: Option<Amount>,
+    /// market orders do not match resting orders priced more than this many basis points away
+    /// from the best opposite price at the time they are matched
+    #[serde(default)]
+    max_slippage_bps: Option<NonZeroU32>,
+    /// the unix timestamp (in seconds) the order was created at, stamped before the order is
+    /// logged so replays are deterministic.
+    created_at: Option<u64>,
 }
 
 /// type-alias for a [`tokio::sync::oneshot::Sender``] that sends [PlaceOrderResult]s.
@@ -69,24 +128,126 @@ pub type PlaceOrderTx = oneshot::Sender<Result<PlaceOrderResult, TradingEngineEr
 impl PlaceOrder {
     /// create a new [`PlaceOrder``]
     pub fn new(
-        asset: Asset,
+        market: Market,
         user_uuid: uuid::Uuid,
-        price: NonZeroU32,
-        quantity: NonZeroU32,
+        order_uuid: OrderUuid,
+        price: Amount,
+        quantity: Amount,
         order_type: OrderType,
         stp: SelfTradeProtection,
         time_in_force: TimeInForce,
         side: OrderSide,
+        expire_at: Option<u64>,
+        stop_price: Option<Amount>,
+        post_only: Option<PostOnly>,
+        display_quantity: Option<Amount>,
+        quote_amount: Option<Amount>,
+        protection_price: Option<Amount>,
+        max_slippage_bps: Option<NonZeroU32>,
     ) -> Self {
         Self {
-            asset,
+            market,
             user_uuid,
+            order_uuid,
             price,
             quantity,
             order_type,
             stp,
             time_in_force,
             side,
+            expire_at,
+            stop_price,
+            post_only,
+            display_quantity,
+            quote_amount,
+            protection_price,
+            max_slippage_bps,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+        }
+    }
+
+    /// the market to trade in
+    pub fn market(&self) -> Market {
+        self.market
+    }
+
+    /// the user that placed the order
+    pub fn user_uuid(&self) -> uuid::Uuid {
+        self.user_uuid
+    }
+
+    /// the unique identifier of the order
+    pub fn order_uuid(&self) -> OrderUuid {
+        self.order_uuid
+    }
+
+    /// the order as it would rest in the orderbook.
+    fn to_order(&self) -> Order {
+        Order {
+            memo: u32::MAX,
+            quantity: self.quantity,
+            price: self.price,
+            owner: self.user_uuid,
+            expire_at: match self.time_in_force {
+                TimeInForce::GoodTilDate => self.expire_at,
+                _ => None,
+            },
+            order_uuid: self.order_uuid,
+            display_quantity: self.display_quantity,
+            hidden_quantity: 0,
+        }
+    }
+
+    /// the bounds a market order is matched within, given the best opposite price in the orderbook
+    /// and the rules of the market.
+    fn fill_bounds(&self, best: Option<Amount>, rules: MarketRules) -> FillBounds {
+        let slippage = self.max_slippage_bps.zip(best).map(|(bps, best)| {
+            let best = u128::from(best.get());
+            let bps = u128::from(bps.get());
+            let bound = match self.side {
+                OrderSide::Buy => best * (10_000 + bps) / 10_000,
+                OrderSide::Sell => (best * 10_000_u128.saturating_sub(bps)).div_ceil(10_000),
+            };
+            Amount::new(bound.min(u128::from(Amount::MAX.get())) as u64).unwrap_or(Amount::MIN)
+        });
+
+        // the tighter of the two bounds applies.
+        let protection_price = match (self.protection_price, slippage) {
+            (Some(a), Some(b)) => Some(match self.side {
+                OrderSide::Buy => a.min(b),
+                OrderSide::Sell => a.max(b),
+            }),
+            (a, b) => a.or(b),
+        };
+
+        // market buys never spend more than was reserved for them, their price only sizes the
+        // reservation.
+        let market_buy = self.side == OrderSide::Buy && self.order_type == OrderType::Market;
+        let quote_budget = (market_buy || self.quote_amount.is_some()).then(|| self.reserved());
+
+        FillBounds {
+            protection_price,
+            quote_budget,
+            lot_size: Some(rules.lot_size),
+            base_decimals: self.market.base.decimals(),
+        }
+    }
+
+    /// what is reserved for the order when it is placed, a buy by quote amount reserves exactly
+    /// that.
+    fn reserved(&self) -> u64 {
+        match self.quote_amount {
+            Some(quote_amount) => quote_amount.get(),
+            None => ledger::reserve_amount(
+                self.market,
+                self.side,
+                self.order_type,
+                self.price,
+                self.quantity,
+            ),
         }
     }
 }
@@ -100,8 +261,14 @@ pub struct CancelOrder {
     order_uuid: OrderUuid,
 }
 
-/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends [Result]s.
-pub type CancelOrderTx = oneshot::Sender<Result<(), TradingEngineError>>;
+/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends the [BookOrders] of a market.
+pub type BookTx = oneshot::Sender<Option<BookOrders>>;
+
+/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends the [OpenOrder]s of a user.
+pub type OpenOrdersTx = oneshot::Sender<Vec<OpenOrder>>;
+
+/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends [CancelOrderResult]s.
+pub type CancelOrderTx = oneshot::Sender<Result<CancelOrderResult, TradingEngineError>>;
 
 impl CancelOrder {
     /// create a new [`CancelOrder``]
@@ -113,6 +280,222 @@ impl CancelOrder {
     }
 }
 
+/// Data for cancelling every live order of a user, optionally only those of a market or side.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct CancelAll {
+    /// the user whose orders are cancelled
+    user_uuid: uuid::Uuid,
+    /// only cancel the orders of this market
+    market: Option<Market>,
+    /// only cancel the orders of this side
+    side: Option<OrderSide>,
+}
+
+/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends the [CancelOrderResult]s of a [`CancelAll`].
+pub type CancelAllTx = oneshot::Sender<Result<Vec<CancelOrderResult>, TradingEngineError>>;
+
+impl CancelAll {
+    /// create a new [`CancelAll`]
+    pub fn new(user_uuid: uuid::Uuid, market: Option<Market>, side: Option<OrderSide>) -> Self {
+        Self {
+            user_uuid,
+            market,
+            side,
+        }
+    }
+}
+
+/// Data for amending a resting order.
+///
+/// Reducing the quantity at the same price keeps the order's time priority, any other change
+/// moves the order to the back of the price level it ends up in. The quantity of iceberg orders
+/// is their total, displayed and hidden, quantity.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AmendOrder {
+    /// the user that placed the order
+    user_uuid: uuid::Uuid,
+    /// the market the order rests in, its price and quantity are in this market's assets.
+    market: Market,
+    /// the order to amend
+    order_uuid: OrderUuid,
+    /// the new price of the order
+    price: Amount,
+    /// the new quantity of the order
+    quantity: Amount,
+    /// the unix timestamp (in seconds) the order was amended at, stamped before the amendment is
+    /// logged so replays are deterministic.
+    amended_at: Option<u64>,
+}
+
+/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends [AmendOrderResult]s.
+pub type AmendOrderTx = oneshot::Sender<Result<AmendOrderResult, TradingEngineError>>;
+
+impl AmendOrder {
+    /// create a new [`AmendOrder``]
+    pub fn new(
+        user_uuid: uuid::Uuid,
+        market: Market,
+        order_uuid: OrderUuid,
+        price: Amount,
+        quantity: Amount,
+    ) -> Self {
+        Self {
+            user_uuid,
+            market,
+            order_uuid,
+            price,
+            quantity,
+            amended_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+        }
+    }
+
+    /// the user that placed the order
+    pub fn user_uuid(&self) -> uuid::Uuid {
+        self.user_uuid
+    }
+
+    /// the market the order rests in
+    pub fn market(&self) -> Market {
+        self.market
+    }
+
+    /// the order to amend
+    pub fn order_uuid(&self) -> OrderUuid {
+        self.order_uuid
+    }
+
+    /// the new price of the order
+    pub fn price(&self) -> Amount {
+        self.price
+    }
+
+    /// the new quantity of the order
+    pub fn quantity(&self) -> Amount {
+        self.quantity
+    }
+}
+
+/// Result of amending an order.
+#[derive(Debug, Clone, Copy)]
+pub struct AmendOrderResult {
+    /// the market of the amended order
+    pub market: Market,
+    /// the side of the amended order, buy or sell
+    pub side: OrderSide,
+    /// the order as it was resting in the orderbook before the amendment
+    pub previous: Order,
+    /// the order as it is resting in the orderbook after the amendment
+    pub order: Order,
+    /// the index of the amended order in the orderbook
+    pub order_index: OrderIndex,
+    /// whether the order kept its time priority in its price level
+    pub kept_priority: bool,
+}
+
+/// Data for expiring good-til-date orders, issued by the trading engine itself when an order's deadline passes.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct ExpireOrders {
+    /// every good-til-date order expiring at or before this unix timestamp (in seconds) is cancelled
+    expire_until: u64,
+}
+
+impl ExpireOrders {
+    /// create a new [`ExpireOrders`]
+    pub fn new(expire_until: u64) -> Self {
+        Self { expire_until }
+    }
+}
+
+/// Data for triggering stop orders, issued by the trading engine itself when a trade crosses a stop price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TriggerStops {
+    /// the market whose stop orders are triggered
+    market: Market,
+    /// the last trade price the stop orders are triggered by
+    last_price: Amount,
+}
+
+/// Result of triggering stop orders.
+pub struct TriggerStopsResult {
+    /// the triggered orders that were placed, in the order they were triggered.
+    pub placed: Vec<PlaceOrderResult>,
+    /// the triggered orders that were rejected when placed, e.g. fill-or-kill orders without the liquidity to fill.
+    pub rejected: Vec<CancelOrderResult>,
+}
+
+/// The unique identifier for a group of one-cancels-the-other orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub struct OcoGroupUuid(pub uuid::Uuid);
+impl OcoGroupUuid {
+    /// generate a new random group uuid.
+    pub fn new_v4() -> OcoGroupUuid {
+        OcoGroupUuid(uuid::Uuid::new_v4())
+    }
+}
+
+/// Data for placing two linked orders, e.g. a take-profit limit order and a stop-loss order.
+///
+/// As soon as either order fills, even partially, or is cancelled the other one is cancelled.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PlaceOco {
+    /// the unique identifier of the group, assigned before the orders are logged so replays are deterministic.
+    group_uuid: OcoGroupUuid,
+    /// the linked orders, placed one after the other.
+    legs: [PlaceOrder; 2],
+}
+
+/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends [PlaceOcoResult]s.
+pub type PlaceOcoTx = oneshot::Sender<Result<PlaceOcoResult, TradingEngineError>>;
+
+impl PlaceOco {
+    /// create a new [`PlaceOco`]
+    pub fn new(group_uuid: OcoGroupUuid, legs: [PlaceOrder; 2]) -> Self {
+        Self { group_uuid, legs }
+    }
+
+    /// the linked orders
+    pub fn legs(&self) -> &[PlaceOrder; 2] {
+        &self.legs
+    }
+}
+
+/// Result of placing two linked orders.
+#[derive(Debug)]
+pub struct PlaceOcoResult {
+    /// the unique identifier of the group
+    pub group_uuid: OcoGroupUuid,
+    /// the unique identifiers of the linked orders
+    pub order_uuids: [OrderUuid; 2],
+    /// the linked orders that were placed, the second one is not placed if the first one traded.
+    pub placed: Vec<PlaceOrderResult>,
+    /// the linked orders that were cancelled because the other one traded, including an unplaced second order.
+    pub cancelled: Vec<CancelOrderResult>,
+}
+
+/// Data for cancelling linked orders whose sibling filled or was cancelled, issued by the trading engine itself.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CancelOcoSiblings {
+    /// the linked orders to cancel
+    order_uuids: Vec<OrderUuid>,
+}
+
+/// Result of canceling an order.
+#[derive(Debug, Clone, Copy)]
+pub struct CancelOrderResult {
+    /// the market of the cancelled order
+    pub market: Market,
+    /// the side of the cancelled order, buy or sell
+    pub side: OrderSide,
+    /// the order as it was resting in the orderbook, i.e. with its remaining quantity
+    pub order: Order,
+    /// the type of the cancelled order, orders resting in the orderbook are held at their
+    /// price and reported as [`OrderType::Limit`].
+    pub order_type: OrderType,
+}
+
 /// Error that can occur when placing an order.
 #[derive(Debug, Error)]
 pub enum PlaceOrderError {
@@ -125,19 +508,46 @@ pub enum PlaceOrderError {
     /// error that can occur when executing a pending fill operation.
     #[error("error while executing pending fill")]
     ExecutePendingFillError(#[from] ExecutePendingFillError),
+    /// stop orders require a stop price and other orders must not have one.
+    #[error("stop orders require a stop price and other orders must not have one")]
+    InvalidStopPrice,
+    /// a post-only order would have taken liquidity.
+    #[error("post-only order would have taken liquidity")]
+    PostOnlyWouldTake,
+    /// the order would spend and hold more than was reserved for it.
+    #[error("order would spend more than was reserved for it")]
+    ExceedsReservation,
+    /// the order breaks the rules of its market.
+    #[error("{0}")]
+    MarketRule(#[from] MarketRuleError),
+}
+
+/// Error that can occur when amending an order.
+#[derive(Debug, Error)]
+pub enum AmendOrderError {
+    /// the amended price would match against the opposite side of the orderbook.
+    #[error("the amended price would cross the orderbook")]
+    WouldCross,
+    /// the user does not have the funds to hold the amended order.
+    #[error("insufficient funds to hold the amended order")]
+    InsufficientFunds,
+    /// the amended order breaks the rules of its market.
+    #[error("{0}")]
+    MarketRule(#[from] MarketRuleError),
 }
 
 /// Result of placing an order.
+#[derive(Debug)]
 pub struct PlaceOrderResult {
     // original order information
-    /// the asset to trade
-    pub asset: Asset,
+    /// the market to trade
+    pub market: Market,
     /// the user that placed the order
     pub user_uuid: uuid::Uuid,
     /// the price of the order
-    pub price: NonZeroU32,
+    pub price: Amount,
     /// the quantity of the order
-    pub quantity: NonZeroU32,
+    pub quantity: Amount,
     /// the type of order
     pub order_type: OrderType,
     /// the self trade protection setting
@@ -146,7 +556,15 @@ pub struct PlaceOrderResult {
     pub time_in_force: TimeInForce,
     /// the side of the order, buy or sell
     pub side: OrderSide,
+    /// the unix timestamp (in seconds) a good-til-date order expires at
+    pub expire_at: Option<u64>,
+    /// the post-only setting
+    pub post_only: Option<PostOnly>,
+    /// the most of the quote currency a market buy spends
+    pub quote_amount: Option<Amount>,
     // result of the order
+    /// the price a post-only order was moved to so it would not take liquidity
+    pub repriced: Option<Amount>,
     /// the unique identifier for the order
     pub order_uuid: OrderUuid,
     /// the index of the order in the orderbook
@@ -154,9 +572,20 @@ pub struct PlaceOrderResult {
     /// the type of fill that occurred
     pub fill_type: FillType,
     /// the quantity filled
-    pub quantity_filled: u32,
+    pub quantity_filled: u64,
     /// the quantity remaining
-    pub quantity_remaining: u32,
+    pub quantity_remaining: u64,
+    /// the maker orders that were filled against this order, used to settle the trade.
+    pub fills: Vec<MakerFill>,
+    /// the resting orders of the same user that were cancelled by self-trade protection.
+    pub self_trade_cancels: Vec<SelfTradeCancel>,
+}
+
+impl PlaceOrderResult {
+    /// whether the order is resting in the orderbook or the stop book without having traded.
+    fn is_resting_untouched(&self) -> bool {
+        self.quantity_filled == 0 && (self.order_index.is_some() || self.order_type.is_stop())
+    }
 }
 
 /// place an order
@@ -165,29 +594,117 @@ pub fn do_place_order(
     place_order: PlaceOrder,
 ) -> Result<PlaceOrderResult, TradingEngineError> {
     let PlaceOrder {
-        asset,
+        market,
         user_uuid,
+        order_uuid,
         price,
         quantity,
         order_type,
         stp,
         time_in_force,
         side,
+        expire_at,
+        stop_price,
+        post_only,
+        quote_amount,
+        created_at,
+        ..
     } = place_order;
 
-    let asset_book = assets.match_asset_mut(asset);
+    let taker = place_order.to_order();
 
-    let taker: Order = Order {
-        memo: u32::MAX,
-        quantity,
-        price,
+    let Some(book) = assets.books.get(&market) else {
+        return Err(TradingEngineError::UnknownMarket(market));
+    };
+
+    let rules = book.rules;
+    rules
+        .check_order(&place_order)
+        .map_err(PlaceOrderError::MarketRule)?;
+
+    if order_type.is_stop() != stop_price.is_some() {
+        return Err(PlaceOrderError::InvalidStopPrice.into());
+    }
+
+    if let Some(stop_price) = stop_price {
+        // stop orders wait in the stop book until a trade crosses their stop price.
+        assets
+            .match_market_mut(market)
+            .stops
+            .insert(stop_price, place_order);
+        assets.stop_uuids.insert(order_uuid, market);
+
+        if let Some(expire_at) = taker.expire_at {
+            assets.expiries.push(Reverse((expire_at, order_uuid)));
+        }
+
+        return Ok(PlaceOrderResult {
+            market,
+            user_uuid,
+            order_index: None,
+            price,
+            quantity,
+            order_type,
+            stp,
+            time_in_force,
+            side,
+            expire_at,
+            post_only,
+            quote_amount,
+            order_uuid,
+            repriced: None,
+            fill_type: FillType::None,
+            quantity_filled: 0,
+            quantity_remaining: quantity.get(),
+            fills: vec![],
+            self_trade_cancels: vec![],
+        });
+    }
+
+    let orderbook = assets.match_market_mut(market).orderbook_mut();
+
+    let opposite = match side {
+        OrderSide::Buy => OrderSide::Sell,
+        OrderSide::Sell => OrderSide::Buy,
     };
+    let best = orderbook
+        .iter_rel(opposite)
+        .next()
+        .map(|(_, best)| best.price);
+    let mut bounds = place_order.fill_bounds(best, rules);
+
+    // create a pending fill and maybe execute it, self-trade protection is applied while matching.
+    let Ok(pending_fill) = try_fill_orders_bounded(orderbook, taker, side, order_type, stp, bounds);
+
+    // post-only orders never take liquidity, the best opposite price is the first maker filled.
+    if let (Some(post_only), Some(best)) = (post_only, pending_fill.maker_fills().first()) {
+        let best = best.maker.price.get();
+        pending_fill.abort();
 
-    // create a pending fill and maybe execute it.
-    let pending_fill = try_fill_orders(asset_book.orderbook_mut(), taker, side, order_type)
-        .expect("todo: handle error");
+        let repriced = match side {
+            OrderSide::Buy => best.checked_sub(rules.tick_size.get()),
+            OrderSide::Sell => best.checked_add(rules.tick_size.get()),
+        };
+
+        return match (post_only, repriced.and_then(Amount::new)) {
+            (PostOnly::Reprice, Some(repriced)) => {
+                let place_order = PlaceOrder {
+                    price: repriced,
+                    post_only: Some(PostOnly::Reject),
+                    ..place_order
+                };
 
-    // TODO: self trade protection
+                // the result reports the order as it was placed, reservations were made at that price.
+                do_place_order(assets, place_order).map(|result| PlaceOrderResult {
+                    price,
+                    post_only: Some(post_only),
+                    repriced: Some(repriced),
+                    ..result
+                })
+            }
+            _ => Err(PlaceOrderError::PostOnlyWouldTake.into()),
+        };
+    }
 
     // enforce time-in-force depending on fill type.
     match (pending_fill.taker_fill_outcome(), time_in_force) {
@@ -210,64 +727,207 @@ pub fn do_place_order(
         }
     }
 
-    // commit the fill.
-    match pending_fill.commit() {
-        Ok((fill_type, order)) => {
-            if let Some(order) = order {
-                let order_index = if matches!(time_in_force, TimeInForce::ImmediateOrCancel) {
-                    // partial fill, but we do not add it to the orderbook because it is an IOC order.
-                    None
-                } else {
-                    // order was not completely filled, add it to the orderbook.
-                    Some(match side {
-                        OrderSide::Buy => asset_book.orderbook_mut().push_bid(order),
-                        OrderSide::Sell => asset_book.orderbook_mut().push_ask(order),
-                    })
-                };
+    // a buy pays for its fills and holds what rests of it out of its reservation, it must never
+    // need more than that.
+    let reserved = place_order.reserved();
+    let spent_on = |fills: &[MakerFill]| {
+        fills
+            .iter()
+            .map(|fill| market.notional(fill.maker.price, fill.fill_amount))
+            .sum::<u64>()
+    };
+    let overspends = |spent: u64, remaining: u64| {
+        let held = match time_in_force {
+            TimeInForce::ImmediateOrCancel => 0,
+            _ => market.notional_ceil(price, remaining),
+        };
+        side == OrderSide::Buy && spent.saturating_add(held) > reserved
+    };
 
-                assert!(quantity.get() >= order.quantity.get());
+    if overspends(
+        spent_on(pending_fill.maker_fills()),
+        pending_fill.taker_remaining_quantity(),
+    ) {
+        pending_fill.abort();
+        return Err(PlaceOrderError::ExceedsReservation.into());
+    }
 
-                Ok(PlaceOrderResult {
-                    asset,
-                    user_uuid,
-                    order_index,
-                    price,
-                    quantity,
-                    order_type,
-                    stp,
-                    time_in_force,
-                    side,
-                    order_uuid: OrderUuid::new_v4(),
-                    fill_type,
-                    quantity_filled: quantity.get() - order.quantity.get(),
-                    quantity_remaining: order.quantity.get(),
-                })
-            } else {
-                // order is None means that the order was completely filled.
-                Ok(PlaceOrderResult {
-                    asset,
-                    user_uuid,
-                    order_index: None,
-                    price,
-                    quantity,
-                    order_type,
-                    stp,
-                    time_in_force,
-                    side,
-                    order_uuid: OrderUuid::new_v4(),
-                    fill_type,
-                    quantity_filled: quantity.get(),
-                    quantity_remaining: 0,
-                })
+    // commit the fill, the remainder of the taker's order is matched again as long as iceberg
+    // orders replenish their displayed slice at the back of a price level it may still cross.
+    let mut committed = pending_fill.commit().map_err(commit_fill_error)?;
+    track_makers(assets, market, &committed);
+    let mut replenished = !committed.replenished.is_empty();
+    let budget = bounds.quote_budget;
+
+    while let (Some(taker), true) = (committed.taker_remaining, replenished) {
+        // what is left of the budget after the fills so far.
+        let spent = spent_on(&committed.maker_fills);
+        bounds.quote_budget = budget.map(|budget| budget.saturating_sub(spent));
+
+        let orderbook = assets.match_market_mut(market).orderbook_mut();
+        let Ok(pending_fill) =
+            try_fill_orders_bounded(orderbook, taker, side, order_type, stp, bounds);
+
+        // what has been committed already stands, the order stops matching instead.
+        let spent = spent + spent_on(pending_fill.maker_fills());
+        if overspends(spent, pending_fill.taker_remaining_quantity()) {
+            pending_fill.abort();
+            break;
+        }
+
+        let next = pending_fill.commit().map_err(commit_fill_error)?;
+        track_makers(assets, market, &next);
+        replenished = !next.replenished.is_empty();
+        committed = committed.chain(next);
+    }
+
+    let CommittedFill {
+        taker_fill_outcome: fill_type,
+        taker_remaining: order,
+        maker_fills: fills,
+        self_trade_cancels,
+        ..
+    } = committed;
+
+    // part of the order may have been cancelled by self-trade protection instead of filled.
+    let quantity_filled = fills.iter().map(|fill| fill.fill_amount).sum::<u64>();
+
+    if let Some(fill) = fills.last() {
+        assets.match_market_mut(market).last_price = Some(fill.maker.price);
+    }
+
+    let result = if let Some(order) = order {
+        // iceberg orders only display a slice of what is left.
+        let order = order.displayed();
+
+        let order_index = if matches!(time_in_force, TimeInForce::ImmediateOrCancel) {
+            // partial fill, but we do not add it to the orderbook because it is an IOC order.
+            None
+        } else {
+            // order was not completely filled, add it to the orderbook.
+            let orderbook = assets.match_market_mut(market).orderbook_mut();
+            Some(match side {
+                OrderSide::Buy => orderbook.push_bid(order),
+                OrderSide::Sell => orderbook.push_ask(order),
+            })
+        };
+
+        assert!(quantity >= order.total_quantity());
+
+        if let Some(order_index) = order_index {
+            assets.order_uuids.insert(order_uuid, (order_index, market));
+            let resting = RestingOrder {
+                quantity,
+                time_in_force,
+                created_at,
+            };
+            assets.track_user_order(user_uuid, order_uuid, resting);
+
+            if let Some(expire_at) = order.expire_at {
+                // the order is resting, track it so it can be cancelled when it expires.
+                assets.expiries.push(Reverse((expire_at, order_uuid)));
             }
         }
-        Err(err) => {
-            tracing::error!(?err, "failed to commit fill");
-            Err(TradingEngineError::from(
-                PlaceOrderError::ExecutePendingFillError(err),
-            ))
+
+        PlaceOrderResult {
+            market,
+            user_uuid,
+            order_index,
+            price,
+            quantity,
+            order_type,
+            stp,
+            time_in_force,
+            side,
+            expire_at,
+            post_only,
+            quote_amount,
+            order_uuid,
+            repriced: None,
+            fill_type,
+            quantity_filled,
+            quantity_remaining: order.total_quantity().get(),
+            fills,
+            self_trade_cancels,
+        }
+    } else {
+        // order is None means that the order was completely filled or cancelled by self-trade protection.
+        PlaceOrderResult {
+            market,
+            user_uuid,
+            order_index: None,
+            price,
+            quantity,
+            order_type,
+            stp,
+            time_in_force,
+            side,
+            expire_at,
+            post_only,
+            quote_amount,
+            order_uuid,
+            repriced: None,
+            fill_type,
+            quantity_filled,
+            quantity_remaining: 0,
+            fills,
+            self_trade_cancels,
+        }
+    };
+
+    // a linked order that traded or did not rest, e.g. a triggered stop order, cancels its sibling.
+    if !result.is_resting_untouched() {
+        assets.dissolve_oco_group(order_uuid);
+    }
+
+    Ok(result)
+}
+
+/// log and wrap an error that occurred while committing a pending fill.
+fn commit_fill_error(err: ExecutePendingFillError) -> TradingEngineError {
+    tracing::error!(?err, "failed to commit fill");
+    TradingEngineError::from(PlaceOrderError::ExecutePendingFillError(err))
+}
+
+/// keep the order uuids of the makers touched by a committed fill up to date.
+fn track_makers(assets: &mut Assets, market: Market, committed: &CommittedFill) {
+    // makers that left the orderbook can no longer be cancelled.
+    let filled = committed
+        .maker_fills
+        .iter()
+        .filter(|fill| fill.fill_type == FillType::Complete)
+        .map(|fill| fill.maker);
+    let cancelled = committed
+        .self_trade_cancels
+        .iter()
+        .filter(|cancel| cancel.cancel_amount == cancel.maker.quantity.get())
+        .map(|cancel| cancel.maker);
+    for maker in filled.chain(cancelled.clone()) {
+        assets.order_uuids.remove(&maker.order_uuid);
+
+        // iceberg orders that show their next slice are still open.
+        let replenished = committed
+            .replenished
+            .iter()
+            .any(|&(_, order_uuid)| order_uuid == maker.order_uuid);
+        if !replenished {
+            assets.untrack_user_order(maker.owner, maker.order_uuid);
         }
     }
+
+    // linked orders cancel their sibling as soon as they trade or are cancelled.
+    let traded = committed
+        .maker_fills
+        .iter()
+        .map(|fill| fill.maker.order_uuid);
+    for maker_uuid in traded.chain(cancelled.map(|maker| maker.order_uuid)) {
+        assets.dissolve_oco_group(maker_uuid);
+    }
+
+    // unless they are iceberg orders that show their next slice.
+    for &(order_index, order_uuid) in &committed.replenished {
+        assets.order_uuids.insert(order_uuid, (order_index, market));
+    }
 }
 
 /// cancel an order
@@ -277,64 +937,473 @@ pub fn do_cancel_order(
         user_uuid,
         order_uuid,
     }: CancelOrder,
-) -> Result<(), TradingEngineError> {
-    let (order_index, asset) = match assets.order_uuids.get(&order_uuid).cloned() {
+) -> Result<CancelOrderResult, TradingEngineError> {
+    let (order_index, market) = match assets.order_uuids.get(&order_uuid).cloned() {
         Some((a, b)) => (a, b),
         None => {
-            return Err(TradingEngineError::OrderNotFound(user_uuid, order_uuid));
+            // the order may be a stop order that has not been triggered yet.
+            let stop = assets
+                .stop_uuids
+                .get(&order_uuid)
+                .copied()
+                .filter(|&market| {
+                    let stops = &assets.match_market_mut(market).stops;
+                    matches!(stops.get(&order_uuid), Some(stop) if stop.user_uuid == user_uuid)
+                });
+
+            return match stop.and_then(|market| remove_stop(assets, market, order_uuid)) {
+                Some(cancelled) => {
+                    assets.dissolve_oco_group(order_uuid);
+                    Ok(cancelled)
+                }
+                None => Err(TradingEngineError::OrderNotFound(user_uuid, order_uuid)),
+            };
         }
     };
 
-    let asset_book = assets.match_asset_mut(asset);
+    let orderbook = assets.match_market_mut(market).orderbook_mut();
 
-    asset_book
-        .orderbook_mut()
-        .remove(order_index)
-        .expect("checked order");
+    // orders of other users are reported as not found so their existence is not leaked.
+    match orderbook.get_mut(order_index) {
+        Some(order) if order.owner == user_uuid => (),
+        _ => return Err(TradingEngineError::OrderNotFound(user_uuid, order_uuid)),
+    }
 
-    Ok(())
-}
+    let order = orderbook.remove(order_index).expect("checked order");
+    assets.order_uuids.remove(&order_uuid);
+    assets.untrack_user_order(user_uuid, order_uuid);
+    assets.dissolve_oco_group(order_uuid);
 
-/// Error that can occur when interacting with the trading engine.
-#[derive(Debug, Error)]
-pub enum TradingEngineError {
-    /// the trading engine is suspended
-    #[error("the trading engine is suspended")]
-    Suspended,
-    /// unserializable input to trading engine
-    #[error("unserializable input to trading engine")]
-    UnserializableInput,
-    /// order not found
-    #[error("order not found for user {0:?} and order uuid {1:?}")]
-    OrderNotFound(uuid::Uuid, OrderUuid),
-    /// database error
-    #[error("database error")]
-    Database(#[from] sqlx::Error),
-    /// error that can occur when executing a pending fill operation.
-    #[error("place order error")]
-    PlaceOrder(#[from] PlaceOrderError),
+    Ok(CancelOrderResult {
+        market,
+        side: order_index.side(),
+        order,
+        order_type: OrderType::Limit,
+    })
 }
 
-/// payload for a trade command
-#[derive(Deserialize, Serialize)]
-#[serde(untagged)]
-pub enum TradeCmdPayload {
-    /// place order data
-    PlaceOrder(PlaceOrder),
-    /// cancel order data
-    CancelOrder(CancelOrder),
-}
+/// remove a stop order that has not been triggered yet.
+fn remove_stop(
+    assets: &mut Assets,
+    market: Market,
+    order_uuid: OrderUuid,
+) -> Option<CancelOrderResult> {
+    let stop = assets.match_market_mut(market).stops.remove(&order_uuid)?;
+    assets.stop_uuids.remove(&order_uuid);
 
-/// enumeration of all the commands the trading engine can process.
-pub enum TradeCmd {
-    /// place an order
-    PlaceOrder((PlaceOrder, PlaceOrderTx)),
-    /// cancel an order
-    CancelOrder((CancelOrder, CancelOrderTx)),
+    Some(CancelOrderResult {
+        market,
+        side: stop.side,
+        order: stop.to_order(),
+        order_type: stop.order_type,
+    })
 }
 
-/// enumeration of all the commands the trading engine can process.
-pub enum TradingEngineCmd {
+/// remove a resting order or a stop order that has not been triggered yet, whoever placed it.
+fn remove_order(assets: &mut Assets, order_uuid: OrderUuid) -> Option<CancelOrderResult> {
+    let Some((order_index, market)) = assets.order_uuids.remove(&order_uuid) else {
+        let market = assets.stop_uuids.get(&order_uuid).copied()?;
+        return remove_stop(assets, market, order_uuid);
+    };
+
+    let order = assets
+        .match_market_mut(market)
+        .orderbook_mut()
+        .remove(order_index)
+        .expect("resting orders are tracked in order_uuids");
+    assets.untrack_user_order(order.owner, order_uuid);
+
+    Some(CancelOrderResult {
+        market,
+        side: order_index.side(),
+        order,
+        order_type: OrderType::Limit,
+    })
+}
+
+/// look up the order an amendment applies to and check that the amendment can be applied,
+/// returns the market and index of the order and the order as it currently rests in the orderbook.
+pub fn check_amend_order(
+    assets: &mut Assets,
+    amend_order: &AmendOrder,
+) -> Result<(Market, OrderIndex, Order), TradingEngineError> {
+    let &AmendOrder {
+        user_uuid,
+        market: amended_market,
+        order_uuid,
+        price,
+        quantity,
+        ..
+    } = amend_order;
+
+    // the new price and quantity were parsed with the decimals of the amended market.
+    let order_market = assets.order_uuids.get(&order_uuid).copied();
+    let Some((order_index, market)) = order_market.filter(|&(_, market)| market == amended_market)
+    else {
+        return Err(TradingEngineError::OrderNotFound(user_uuid, order_uuid));
+    };
+
+    let book = assets.match_market_mut(market);
+    let rules = book.rules;
+    let orderbook = book.orderbook_mut();
+
+    // orders of other users are reported as not found so their existence is not leaked.
+    let order = match orderbook.get_mut(order_index) {
+        Some(order) if order.owner == user_uuid => *order,
+        _ => return Err(TradingEngineError::OrderNotFound(user_uuid, order_uuid)),
+    };
+
+    // amended orders are never matched, a price that would trade has to be placed as a new order.
+    let side = order_index.side();
+    let opposite = match side {
+        OrderSide::Buy => OrderSide::Sell,
+        OrderSide::Sell => OrderSide::Buy,
+    };
+
+    let crosses = orderbook
+        .iter_rel(opposite)
+        .next()
+        .is_some_and(|(_, best)| match side {
+            OrderSide::Buy => price >= best.price,
+            OrderSide::Sell => price <= best.price,
+        });
+
+    if crosses {
+        return Err(AmendOrderError::WouldCross.into());
+    }
+
+    rules
+        .check_price(price)
+        .and_then(|()| rules.check_quantity(quantity))
+        .and_then(|()| rules.check_notional(market.notional(price, quantity.get())))
+        .map_err(AmendOrderError::MarketRule)?;
+
+    Ok((market, order_index, order))
+}
+
+/// amend an order
+pub fn do_amend_order(
+    assets: &mut Assets,
+    amend_order: AmendOrder,
+) -> Result<AmendOrderResult, TradingEngineError> {
+    let (market, order_index, previous) = check_amend_order(assets, &amend_order)?;
+
+    let AmendOrder {
+        order_uuid,
+        price,
+        quantity,
+        amended_at,
+        ..
+    } = amend_order;
+
+    let side = order_index.side();
+    let orderbook = assets.match_market_mut(market).orderbook_mut();
+
+    // the quantity of an iceberg order is its total quantity, the hidden reserve shrinks first.
+    if price == previous.price && quantity <= previous.total_quantity() {
+        // reducing the quantity in place keeps the order's time priority.
+        let order = orderbook.get_mut(order_index).expect("checked order");
+        order.quantity = order.quantity.min(quantity);
+        order.hidden_quantity = quantity.get() - order.quantity.get();
+        let order = *order;
+
+        assets.amend_user_order(&previous, quantity, None);
+
+        return Ok(AmendOrderResult {
+            market,
+            side,
+            previous,
+            order,
+            order_index,
+            kept_priority: true,
+        });
+    }
+
+    let mut order = orderbook.remove(order_index).expect("checked order");
+    order.price = price;
+    order.quantity = quantity;
+    order.hidden_quantity = 0;
+    let order = order.displayed();
+
+    let order_index = match side {
+        OrderSide::Buy => orderbook.push_bid(order),
+        OrderSide::Sell => orderbook.push_ask(order),
+    };
+
+    let order = *orderbook.get_mut(order_index).expect("pushed order");
+    assets.order_uuids.insert(order_uuid, (order_index, market));
+    assets.amend_user_order(&previous, quantity, amended_at);
+
+    Ok(AmendOrderResult {
+        market,
+        side,
+        previous,
+        order,
+        order_index,
+        kept_priority: false,
+    })
+}
+
+/// expire every good-til-date order whose deadline is at or before `expire_until`.
+pub fn do_expire_orders(
+    assets: &mut Assets,
+    ExpireOrders { expire_until }: ExpireOrders,
+) -> Result<Vec<CancelOrderResult>, TradingEngineError> {
+    let mut expired = vec![];
+
+    while let Some(&Reverse((expire_at, order_uuid))) = assets.expiries.peek() {
+        if expire_at > expire_until {
+            break;
+        }
+
+        assets.expiries.pop();
+
+        // the order may have been filled or cancelled since, in which case the entry is stale.
+        // stop orders that have not been triggered expire as well.
+        if let Some(cancelled) = remove_order(assets, order_uuid) {
+            assets.dissolve_oco_group(order_uuid);
+            expired.push(cancelled);
+        }
+    }
+
+    Ok(expired)
+}
+
+/// place every stop order of the market that is triggered by the last trade price.
+pub fn do_trigger_stops(
+    assets: &mut Assets,
+    TriggerStops { market, last_price }: TriggerStops,
+) -> Result<TriggerStopsResult, TradingEngineError> {
+    let mut placed = vec![];
+    let mut rejected = vec![];
+
+    let triggered = assets
+        .match_market_mut(market)
+        .stops
+        .take_triggered(last_price);
+
+    for mut place_order in triggered {
+        assets.stop_uuids.remove(&place_order.order_uuid);
+
+        place_order.order_type = place_order.order_type.triggered();
+        place_order.stop_price = None;
+
+        let side = place_order.side;
+        let order = place_order.to_order();
+        let order_type = place_order.order_type;
+
+        match do_place_order(assets, place_order) {
+            Ok(result) => placed.push(result),
+            Err(err) => {
+                tracing::info!(?err, order_uuid = ?order.order_uuid, "triggered stop order was rejected");
+                assets.dissolve_oco_group(order.order_uuid);
+                rejected.push(CancelOrderResult {
+                    market,
+                    side,
+                    order,
+                    order_type,
+                });
+            }
+        }
+    }
+
+    Ok(TriggerStopsResult { placed, rejected })
+}
+
+/// place two linked orders, the second order is only placed if the first one rests without trading.
+pub fn do_place_oco(
+    assets: &mut Assets,
+    PlaceOco { group_uuid, legs }: PlaceOco,
+) -> Result<PlaceOcoResult, TradingEngineError> {
+    let [first, second] = legs;
+    let order_uuids = [first.order_uuid, second.order_uuid];
+
+    // the second order is released as if it was cancelled when it is not placed.
+    let unplaced = CancelOrderResult {
+        market: second.market,
+        side: second.side,
+        order: second.to_order(),
+        order_type: second.order_type,
+    };
+
+    let first = do_place_order(assets, first)?;
+    if !first.is_resting_untouched() {
+        return Ok(PlaceOcoResult {
+            group_uuid,
+            order_uuids,
+            placed: vec![first],
+            cancelled: vec![unplaced],
+        });
+    }
+
+    let second = match do_place_order(assets, second) {
+        Ok(second) => second,
+        Err(err) => {
+            tracing::info!(?err, ?group_uuid, "second linked order was rejected");
+            let cancelled = remove_order(assets, order_uuids[0]);
+
+            return Ok(PlaceOcoResult {
+                group_uuid,
+                order_uuids,
+                placed: vec![first],
+                cancelled: cancelled.into_iter().chain([unplaced]).collect(),
+            });
+        }
+    };
+
+    // the second order may have traded, or cancelled the first one through self-trade protection.
+    let first_resting = assets.order_uuids.contains_key(&order_uuids[0])
+        || assets.stop_uuids.contains_key(&order_uuids[0]);
+
+    let cancelled = match (second.is_resting_untouched(), first_resting) {
+        (true, true) => {
+            for order_uuid in order_uuids {
+                assets.oco_legs.insert(order_uuid, group_uuid);
+            }
+            assets.oco_groups.insert(group_uuid, order_uuids);
+            None
+        }
+        (false, _) => remove_order(assets, order_uuids[0]),
+        (true, false) => remove_order(assets, order_uuids[1]),
+    };
+
+    Ok(PlaceOcoResult {
+        group_uuid,
+        order_uuids,
+        placed: vec![first, second],
+        cancelled: cancelled.into_iter().collect(),
+    })
+}
+
+/// cancel linked orders whose sibling filled or was cancelled.
+pub fn do_cancel_oco_siblings(
+    assets: &mut Assets,
+    CancelOcoSiblings { order_uuids }: CancelOcoSiblings,
+) -> Result<Vec<CancelOrderResult>, TradingEngineError> {
+    assets
+        .oco_cancels
+        .retain(|order_uuid| !order_uuids.contains(order_uuid));
+
+    Ok(order_uuids
+        .into_iter()
+        .filter_map(|order_uuid| remove_order(assets, order_uuid))
+        .collect())
+}
+
+/// cancel every resting order and every stop order that has not been triggered yet of a user,
+/// in the order of their uuids.
+pub fn do_cancel_all(
+    assets: &mut Assets,
+    CancelAll {
+        user_uuid,
+        market,
+        side,
+    }: CancelAll,
+) -> Result<Vec<CancelOrderResult>, TradingEngineError> {
+    if let Some(market) = market.filter(|market| !assets.books.contains_key(market)) {
+        return Err(TradingEngineError::UnknownMarket(market));
+    }
+
+    let matches = |order_market: Market, order_side: OrderSide| {
+        market.map_or(true, |m| m == order_market) && side.map_or(true, |s| s == order_side)
+    };
+
+    let resting = assets
+        .user_orders
+        .get(&user_uuid)
+        .into_iter()
+        .flat_map(|orders| orders.keys())
+        .filter_map(|order_uuid| {
+            let &(order_index, market) = assets.order_uuids.get(order_uuid)?;
+            matches(market, order_index.side()).then_some(order_uuid)
+        });
+
+    let stops = assets
+        .stop_uuids
+        .iter()
+        .filter(|&(order_uuid, &market)| {
+            let stop = assets.books[&market].stops.get(order_uuid);
+            stop.is_some_and(|stop| stop.user_uuid == user_uuid && matches(market, stop.side))
+        })
+        .map(|(order_uuid, _)| order_uuid);
+
+    let mut order_uuids = resting.chain(stops).copied().collect::<Vec<_>>();
+    order_uuids.sort();
+
+    Ok(order_uuids
+        .into_iter()
+        .filter_map(|order_uuid| {
+            let cancelled = remove_order(assets, order_uuid)?;
+            assets.dissolve_oco_group(order_uuid);
+            Some(cancelled)
+        })
+        .collect())
+}
+
+/// Error that can occur when interacting with the trading engine.
+#[derive(Debug, Error)]
+pub enum TradingEngineError {
+    /// the trading engine is suspended
+    #[error("the trading engine is suspended")]
+    Suspended,
+    /// unserializable input to trading engine
+    #[error("unserializable input to trading engine")]
+    UnserializableInput,
+    /// order not found
+    #[error("order not found for user {0:?} and order uuid {1:?}")]
+    OrderNotFound(uuid::Uuid, OrderUuid),
+    /// the market is not traded
+    #[error("market {0} is not traded")]
+    UnknownMarket(Market),
+    /// database error
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+    /// error that can occur when executing a pending fill operation.
+    #[error("place order error")]
+    PlaceOrder(#[from] PlaceOrderError),
+    /// error that can occur when amending an order.
+    #[error("amend order error")]
+    AmendOrder(#[from] AmendOrderError),
+}
+
+/// payload for a trade command
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum TradeCmdPayload {
+    /// place order data
+    PlaceOrder(PlaceOrder),
+    /// amend order data, has to be tried before [`CancelOrder`] which it is a superset of.
+    AmendOrder(AmendOrder),
+    /// cancel order data
+    CancelOrder(CancelOrder),
+    /// expire orders data
+    ExpireOrders(ExpireOrders),
+    /// trigger stops data
+    TriggerStops(TriggerStops),
+    /// place linked orders data
+    PlaceOco(PlaceOco),
+    /// cancel linked orders data
+    CancelOcoSiblings(CancelOcoSiblings),
+    /// cancel all orders data, has to be tried after [`CancelOrder`] which is a superset of it.
+    CancelAll(CancelAll),
+}
+
+/// enumeration of all the commands the trading engine can process.
+pub enum TradeCmd {
+    /// place an order
+    PlaceOrder((PlaceOrder, PlaceOrderTx)),
+    /// cancel an order
+    CancelOrder((CancelOrder, CancelOrderTx)),
+    /// amend an order
+    AmendOrder((AmendOrder, AmendOrderTx)),
+    /// place two linked orders
+    PlaceOco((PlaceOco, PlaceOcoTx)),
+    /// cancel every order of a user
+    CancelAll((CancelAll, CancelAllTx)),
+}
+
+/// enumeration of all the commands the trading engine can process.
+pub enum TradingEngineCmd {
     /// a signal to shutdown the trading engine
     Shutdown,
     /// suspend the engine
@@ -343,8 +1412,26 @@ pub enum TradingEngineCmd {
     Resume,
     /// a trade command like placing an order or canceling an order.
     Trade(TradeCmd),
-    /// a trade command deserialized from json used to initialize the trading engine.
-    Bootstrap(TradeCmdPayload),
+    /// start trading a market, the `markets` table is its record so it is not logged.
+    ListMarket((Market, MarketRules)),
+    /// send the depth of every orderbook, it does not change anything so it is not logged.
+    Depth(DepthSnapshotTx),
+    /// send the orders of the best price levels of a market's orderbook, `None` if the market
+    /// is not traded.
+    Book((Market, usize, BookTx)),
+    /// send the resting orders of a user, it does not change anything so it is not logged.
+    OpenOrders((uuid::Uuid, OpenOrdersTx)),
+    /// expire good-til-date orders, issued by the trading engine itself when a deadline passes.
+    Expire(ExpireOrders),
+    /// restore the state of the trading engine from a snapshot taken after the event with the
+    /// given id, it is sent before any bootstrap command.
+    Restore((i64, Box<AssetsSnapshot>)),
+    /// a trade command deserialized from json used to initialize the trading engine, with the id
+    /// of its event.
+    Bootstrap((i64, TradeCmdPayload)),
+    /// a signal that every bootstrap command has been sent, time-driven commands like
+    /// expiring orders are only issued by the engine after this.
+    BootstrapComplete,
 }
 impl TradingEngineCmd {
     pub(crate) fn consume_respond_with_error(self, err: TradingEngineError) {
@@ -356,6 +1443,15 @@ impl TradingEngineCmd {
                 TradeCmd::CancelOrder((_, tx)) => {
                     let _ = tx.send(Err(err));
                 }
+                TradeCmd::AmendOrder((_, tx)) => {
+                    let _ = tx.send(Err(err));
+                }
+                TradeCmd::PlaceOco((_, tx)) => {
+                    let _ = tx.send(Err(err));
+                }
+                TradeCmd::CancelAll((_, tx)) => {
+                    let _ = tx.send(Err(err));
+                }
             };
         }
     }
@@ -363,42 +1459,264 @@ impl TradingEngineCmd {
 
 /// the "state" of an asset book for a trading engine.
 pub struct AssetBook {
-    asset: Asset,
-    orderbook: Orderbook,
+    market: Market,
+    rules: MarketRules,
+    /// boxed as the inline price levels make an orderbook too large to move around on the stack.
+    orderbook: Box<Orderbook>,
+    stops: StopBook,
+    last_price: Option<Amount>,
 }
 
 impl AssetBook {
     /// create a new asset book
-    pub fn new(asset: Asset) -> Self {
+    pub fn new(market: Market, rules: MarketRules) -> Self {
         Self {
-            asset,
-            orderbook: Orderbook::new(),
+            market,
+            rules,
+            orderbook: Box::new(Orderbook::new()),
+            stops: StopBook::default(),
+            last_price: None,
         }
     }
 
+    /// the prices and quantities the market accepts
+    pub fn rules(&self) -> &MarketRules {
+        &self.rules
+    }
+
+    /// the price of the last trade
+    pub fn last_price(&self) -> Option<Amount> {
+        self.last_price
+    }
+
+    /// the stop orders that are triggered by the last trade price, if there are any.
+    fn triggered_stops(&self) -> Option<TriggerStops> {
+        let last_price = self.last_price?;
+
+        self.stops.is_triggered(last_price).then_some(TriggerStops {
+            market: self.market,
+            last_price,
+        })
+    }
+
+    /// get the orderbook
+    pub fn orderbook(&self) -> &Orderbook {
+        &self.orderbook
+    }
+
     /// get the asset
     pub fn orderbook_mut(&mut self) -> &mut Orderbook {
         &mut self.orderbook
     }
 }
 
-/// multiple asset books for a trading engine.
+/// What is kept of a resting order besides the order itself.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RestingOrder {
+    /// the quantity the order was placed with, what it was amended to plus what had been filled.
+    pub quantity: Amount,
+    /// the time in force setting.
+    pub time_in_force: TimeInForce,
+    /// the unix timestamp (in seconds) the order was created at or last lost its time priority
+    /// by an amendment, if it is known.
+    pub created_at: Option<u64>,
+}
+
+/// A resting order of a user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpenOrder {
+    /// the unique identifier of the order.
+    pub order_uuid: OrderUuid,
+    /// the market the order rests in.
+    pub market: Market,
+    /// the side of the order, buy or sell.
+    pub side: OrderSide,
+    /// the price of the order.
+    pub price: Amount,
+    /// the quantity the order was placed with, what it was amended to plus what had been filled.
+    pub quantity: Amount,
+    /// the quantity that is left, including the hidden quantity of an iceberg order.
+    pub remaining: Amount,
+    /// the time in force setting.
+    pub time_in_force: TimeInForce,
+    /// the unix timestamp (in seconds) the order was created at or last lost its time priority
+    /// by an amendment, if it is known.
+    pub created_at: Option<u64>,
+}
+
+/// the asset books of every market for a trading engine.
 pub struct Assets {
-    /// map of order uuids to order indexes and assets.
-    pub order_uuids: ahash::AHashMap<OrderUuid, (OrderIndex, Asset)>,
-    /// the asset book for ether
-    pub eth: AssetBook,
-    /// the asset book for bitcoin
-    pub btc: AssetBook,
+    /// map of order uuids to order indexes and markets.
+    pub order_uuids: ahash::AHashMap<OrderUuid, (OrderIndex, Market)>,
+    /// map of users to their resting orders, kept alongside `order_uuids`.
+    pub user_orders: ahash::AHashMap<uuid::Uuid, ahash::AHashMap<OrderUuid, RestingOrder>>,
+    /// map of the order uuids of stop orders that have not been triggered yet to their markets.
+    pub stop_uuids: ahash::AHashMap<OrderUuid, Market>,
+    /// deadlines of resting good-til-date orders, soonest first. entries are not removed when
+    /// the order leaves the book early so they must be checked against `order_uuids` when popped.
+    pub expiries: BinaryHeap<Reverse<(u64, OrderUuid)>>,
+    /// map of one-cancels-the-other groups to their linked orders, while both of them are live.
+    pub oco_groups: ahash::AHashMap<OcoGroupUuid, [OrderUuid; 2]>,
+    /// map of the order uuids of linked orders to their group.
+    pub oco_legs: ahash::AHashMap<OrderUuid, OcoGroupUuid>,
+    /// linked orders whose sibling filled or was cancelled, waiting to be cancelled.
+    pub oco_cancels: Vec<OrderUuid>,
+    /// map of the traded markets to their asset books.
+    pub books: BTreeMap<Market, AssetBook>,
 }
 
 impl Assets {
-    fn match_asset_mut(&mut self, asset: Asset) -> &mut AssetBook {
-        match asset {
-            Asset::Ether => &mut self.eth,
-            Asset::Bitcoin => &mut self.btc,
+    /// create the state without any markets, they are added with [`Assets::list_market`].
+    pub fn new() -> Self {
+        Self::with_markets([])
+    }
+
+    /// create the asset books for `markets` accepting any price and quantity, all of them empty.
+    pub fn with_markets(markets: impl IntoIterator<Item = Market>) -> Self {
+        Self {
+            order_uuids: Default::default(),
+            user_orders: Default::default(),
+            stop_uuids: Default::default(),
+            expiries: Default::default(),
+            oco_groups: Default::default(),
+            oco_legs: Default::default(),
+            oco_cancels: Default::default(),
+            books: markets
+                .into_iter()
+                .map(|market| (market, AssetBook::new(market, MarketRules::default())))
+                .collect(),
+        }
+    }
+
+    /// start trading `market` with an empty asset book, returns false if it is already traded.
+    pub fn list_market(&mut self, market: Market, rules: MarketRules) -> bool {
+        match self.books.entry(market) {
+            std::collections::btree_map::Entry::Occupied(_) => false,
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                entry.insert(AssetBook::new(market, rules));
+                true
+            }
+        }
+    }
+
+    /// the asset book of a market, if it is traded.
+    pub fn book_mut(&mut self, market: Market) -> Option<&mut AssetBook> {
+        self.books.get_mut(&market)
+    }
+
+    /// the unix timestamp (in seconds) of the next good-til-date order deadline.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiries
+            .peek()
+            .map(|&Reverse((expire_at, _))| expire_at)
+    }
+
+    /// the next batch of stop orders that is triggered by the last trade price of its market.
+    pub fn next_triggered_stops(&self) -> Option<TriggerStops> {
+        self.books.values().find_map(AssetBook::triggered_stops)
+    }
+
+    /// the linked orders whose sibling filled or was cancelled, if there are any.
+    pub fn next_oco_cancels(&self) -> Option<CancelOcoSiblings> {
+        (!self.oco_cancels.is_empty()).then(|| CancelOcoSiblings {
+            order_uuids: self.oco_cancels.clone(),
+        })
+    }
+
+    /// the resting orders of a user, oldest first.
+    pub fn open_orders(&self, user_uuid: uuid::Uuid) -> Vec<OpenOrder> {
+        let Some(orders) = self.user_orders.get(&user_uuid) else {
+            return vec![];
+        };
+
+        let mut open_orders = orders
+            .iter()
+            .filter_map(|(&order_uuid, resting)| {
+                let &(order_index, market) = self.order_uuids.get(&order_uuid)?;
+                let order = self.books.get(&market)?.orderbook().get(order_index)?;
+
+                Some(OpenOrder {
+                    order_uuid,
+                    market,
+                    side: order_index.side(),
+                    price: order.price,
+                    quantity: resting.quantity,
+                    remaining: order.total_quantity(),
+                    time_in_force: resting.time_in_force,
+                    created_at: resting.created_at,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        open_orders.sort_by_key(|order| (order.created_at, order.order_uuid));
+        open_orders
+    }
+
+    /// add a resting order to the orders of its owner.
+    fn track_user_order(
+        &mut self,
+        owner: uuid::Uuid,
+        order_uuid: OrderUuid,
+        resting: RestingOrder,
+    ) {
+        self.user_orders
+            .entry(owner)
+            .or_default()
+            .insert(order_uuid, resting);
+    }
+
+    /// update the orders of the owner of an amended order, `previous` is the order as it rested
+    /// before the amendment. an order that lost its time priority at `requeued_at` is listed as
+    /// if it was placed again then.
+    fn amend_user_order(&mut self, previous: &Order, quantity: Amount, requeued_at: Option<u64>) {
+        let resting = self
+            .user_orders
+            .get_mut(&previous.owner)
+            .and_then(|orders| orders.get_mut(&previous.order_uuid));
+
+        if let Some(resting) = resting {
+            // what has been filled so far stays part of the order's quantity.
+            let filled = resting.quantity.get() - previous.total_quantity().get();
+            resting.quantity =
+                Amount::new(filled.saturating_add(quantity.get())).unwrap_or(Amount::MAX);
+            if requeued_at.is_some() {
+                resting.created_at = requeued_at;
+            }
+        }
+    }
+
+    /// remove an order that left the orderbook from the orders of its owner.
+    fn untrack_user_order(&mut self, owner: uuid::Uuid, order_uuid: OrderUuid) {
+        if let Some(orders) = self.user_orders.get_mut(&owner) {
+            orders.remove(&order_uuid);
+            if orders.is_empty() {
+                self.user_orders.remove(&owner);
+            }
+        }
+    }
+
+    /// dissolve the group of a linked order that traded or was cancelled, queueing its sibling
+    /// to be cancelled. does nothing for orders that are not linked.
+    fn dissolve_oco_group(&mut self, order_uuid: OrderUuid) {
+        let Some(group_uuid) = self.oco_legs.remove(&order_uuid) else {
+            return;
+        };
+
+        let legs = self
+            .oco_groups
+            .remove(&group_uuid)
+            .expect("linked orders are tracked with their group");
+
+        for sibling in legs.into_iter().filter(|&leg| leg != order_uuid) {
+            self.oco_legs.remove(&sibling);
+            self.oco_cancels.push(sibling);
         }
     }
+
+    fn match_market_mut(&mut self, market: Market) -> &mut AssetBook {
+        self.book_mut(market)
+            .expect("orders are only accepted for traded markets")
+    }
 }
 
 #[cfg(test)]
@@ -406,11 +1724,14 @@ mod tests {
     use tokio::task_local;
     use uuid::Uuid;
 
+    use crate::asset::fixtures;
     use crate::spawn_trading_engine::{spawn_trading_engine, SpawnTradingEngine};
     use crate::Configuration;
 
     use super::*;
 
+    const BTC_USD: Market = Market::new(fixtures::BTC, fixtures::USD);
+
     task_local! {
         static CX: (TradingEngineTx, sqlx::PgPool);
     }
@@ -421,19 +1742,28 @@ mod tests {
         (config, spawn_trading_engine)
     }
 
-    async fn place_order(user_uuid: Uuid, price: u32, quantity: u32) -> PlaceOrderResult {
+    async fn place_order(user_uuid: Uuid, price: u64, quantity: u64) -> PlaceOrderResult {
         let (te, db) = CX.with(|cx| cx.clone());
 
         let (tx, rx) = oneshot::channel();
         let order = PlaceOrder {
-            asset: Asset::Bitcoin,
+            market: BTC_USD,
             user_uuid: Uuid::new_v4(),
-            price: NonZeroU32::new(price).expect("price was zero"),
-            quantity: NonZeroU32::new(quantity).expect("quantity was zero"),
+            order_uuid: OrderUuid::new_v4(),
+            price: Amount::new(price).expect("price was zero"),
+            quantity: Amount::new(quantity).expect("quantity was zero"),
             order_type: OrderType::Market,
             stp: SelfTradeProtection::CancelOldest,
             time_in_force: TimeInForce::GoodTilCanceled,
             side: OrderSide::Buy,
+            expire_at: None,
+            stop_price: None,
+            post_only: None,
+            display_quantity: None,
+            quote_amount: None,
+            protection_price: None,
+            max_slippage_bps: None,
+            created_at: None,
         };
 
         te.send(TradingEngineCmd::Trade(TradeCmd::PlaceOrder((order, tx))))
@@ -445,6 +1775,57 @@ mod tests {
             .expect("place-order Err")
     }
 
+    async fn place_limit_order(
+        user_uuid: Uuid,
+        side: OrderSide,
+        price: u64,
+        quantity: u64,
+    ) -> PlaceOrderResult {
+        let (te, _db) = CX.with(|cx| cx.clone());
+
+        let (tx, rx) = oneshot::channel();
+        let order = PlaceOrder {
+            market: BTC_USD,
+            user_uuid,
+            order_uuid: OrderUuid::new_v4(),
+            price: Amount::new(price).expect("price was zero"),
+            quantity: Amount::new(quantity).expect("quantity was zero"),
+            order_type: OrderType::Limit,
+            stp: SelfTradeProtection::CancelOldest,
+            time_in_force: TimeInForce::GoodTilCanceled,
+            side,
+            expire_at: None,
+            stop_price: None,
+            post_only: None,
+            display_quantity: None,
+            quote_amount: None,
+            protection_price: None,
+            max_slippage_bps: None,
+            created_at: None,
+        };
+
+        te.send(TradingEngineCmd::Trade(TradeCmd::PlaceOrder((order, tx))))
+            .await
+            .expect("place-order send error");
+
+        rx.await
+            .expect("oneshot rx failure")
+            .expect("place-order Err")
+    }
+
+    async fn balance(db: &sqlx::PgPool, user_uuid: Uuid, currency: &str) -> i64 {
+        sqlx::query!(
+            "SELECT calculate_balance($1, $2);",
+            user_uuid.to_string(),
+            currency
+        )
+        .fetch_one(db)
+        .await
+        .unwrap()
+        .calculate_balance
+        .unwrap_or_default()
+    }
+
     #[sqlx::test]
     async fn test_startup_then_shutdown(db: sqlx::PgPool) {
         let (_config, te) = trading_engine_fixture(db).await;
@@ -466,8 +1847,1556 @@ mod tests {
             let users = (0..100).map(|_| new_user_uuid()).collect::<Vec<_>>();
             let bob = users[0];
 
-            let PlaceOrderResult { asset, .. } = place_order(bob, 1, 1).await;
-            assert_eq!(asset, Asset::Bitcoin);
+            let PlaceOrderResult { market, .. } = place_order(bob, 1, 1).await;
+            assert_eq!(market, BTC_USD);
         });
     }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_bootstrap_refuses_unscaled_events(db: sqlx::PgPool) {
+        sqlx::query!(
+            r#"INSERT INTO trading_event_source (jstr, fixed_point) VALUES ('{}', FALSE)"#
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let (_config, te) = trading_engine_fixture(db.clone()).await;
+        let err = te.init_from_db(db).await.unwrap_err();
+        assert!(matches!(err, sqlx::Error::Decode(_)));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fill_settles_into_ledger(db: sqlx::PgPool) {
+        let (_config, te) = trading_engine_fixture(db.clone()).await;
+        let (te, _task) = te.init_from_db(db.clone()).await.unwrap();
+
+        CX.scope((te, db.clone()), async {
+            let alice = new_user_uuid();
+            let bob = new_user_uuid();
+
+            let maker = place_limit_order(alice, OrderSide::Sell, 10, 5 * BTC).await;
+            assert_eq!(maker.fill_type, FillType::None);
+
+            let taker = place_limit_order(bob, OrderSide::Buy, 10, 3 * BTC).await;
+            assert_eq!(taker.fill_type, FillType::Complete);
+            assert_eq!(taker.fills.len(), 1);
+
+            assert_eq!(balance(&db, bob, "BTC").await, 3 * BTC as i64);
+            assert_eq!(balance(&db, alice, "USD").await, 30);
+
+            // bob was willing to pay 12, the price improvement of 2 per unit is released.
+            let taker = place_limit_order(bob, OrderSide::Buy, 12, 2 * BTC).await;
+            assert_eq!(taker.fill_type, FillType::Complete);
+            assert_eq!(balance(&db, bob, "BTC").await, 5 * BTC as i64);
+            assert_eq!(balance(&db, bob, "USD").await, 4);
+        })
+        .await;
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fills_record_trades(db: sqlx::PgPool) {
+        let (_config, te) = trading_engine_fixture(db.clone()).await;
+        let (te, _task) = te.init_from_db(db.clone()).await.unwrap();
+
+        let order = |user_uuid, side, order_type, price, quantity, stop_price: Option<u64>| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(quantity),
+                order_type,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                stop_price.map(nz),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        let trades = |order_uuid: OrderUuid| {
+            let db = db.clone();
+            async move {
+                sqlx::query_scalar!(
+                    "SELECT COUNT(*) FROM trades WHERE taker_order_uuid = $1",
+                    order_uuid.0
+                )
+                .fetch_one(&db)
+                .await
+                .unwrap()
+                .unwrap_or_default()
+            }
+        };
+
+        CX.scope((te.clone(), db.clone()), async {
+            let alice = new_user_uuid();
+            let bob = new_user_uuid();
+            let carol = new_user_uuid();
+            let dave = new_user_uuid();
+
+            place_limit_order(alice, OrderSide::Sell, 10, 5 * BTC).await;
+
+            let (tx, rx) = oneshot::channel();
+            let stop = order(
+                carol,
+                OrderSide::Buy,
+                OrderType::StopMarket,
+                12,
+                BTC,
+                Some(10),
+            );
+            te.send(TradingEngineCmd::Trade(TradeCmd::PlaceOrder((stop, tx))))
+                .await
+                .unwrap();
+            let stop = rx.await.unwrap().unwrap();
+            assert_eq!(stop.fill_type, FillType::None);
+
+            // the trade at 10 triggers carol's stop, which trades on its own.
+            let taker = place_limit_order(bob, OrderSide::Buy, 10, BTC).await;
+            assert_eq!(taker.fill_type, FillType::Complete);
+
+            // the limit order trades right away, so the stop order is never placed.
+            let (tx, rx) = oneshot::channel();
+            let place_oco = PlaceOco::new(
+                OcoGroupUuid::new_v4(),
+                [
+                    order(dave, OrderSide::Buy, OrderType::Limit, 10, BTC, None),
+                    order(
+                        dave,
+                        OrderSide::Buy,
+                        OrderType::StopMarket,
+                        12,
+                        BTC,
+                        Some(11),
+                    ),
+                ],
+            );
+            te.send(TradingEngineCmd::Trade(TradeCmd::PlaceOco((place_oco, tx))))
+                .await
+                .unwrap();
+            let oco = rx.await.unwrap().unwrap();
+            assert_eq!(oco.placed[0].fill_type, FillType::Complete);
+
+            assert_eq!(trades(taker.order_uuid).await, 1);
+            assert_eq!(trades(stop.order_uuid).await, 1);
+            assert_eq!(trades(oco.order_uuids[0]).await, 1);
+            assert_eq!(trades(oco.order_uuids[1]).await, 0);
+
+            let total = sqlx::query_scalar!("SELECT COUNT(*) FROM trades")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            assert_eq!(total, Some(3));
+        })
+        .await;
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_amend_order_reserves_in_ledger(db: sqlx::PgPool) {
+        let (_config, te) = trading_engine_fixture(db.clone()).await;
+        let (te, _task) = te.init_from_db(db.clone()).await.unwrap();
+
+        CX.scope((te.clone(), db.clone()), async {
+            let bob = new_user_uuid();
+
+            let order = place_limit_order(bob, OrderSide::Buy, 10, BTC).await;
+
+            let amend = |price, quantity| {
+                let te = te.clone();
+                async move {
+                    let (tx, rx) = oneshot::channel();
+                    let amend_order = AmendOrder::new(
+                        bob,
+                        BTC_USD,
+                        order.order_uuid,
+                        Amount::new(price).unwrap(),
+                        Amount::new(quantity).unwrap(),
+                    );
+                    te.send(TradingEngineCmd::Trade(TradeCmd::AmendOrder((
+                        amend_order,
+                        tx,
+                    ))))
+                    .await
+                    .unwrap();
+                    rx.await.unwrap()
+                }
+            };
+
+            // holding 2 at 10 needs another 10 bob does not have.
+            let err = amend(10, 2 * BTC).await.unwrap_err();
+            assert!(matches!(
+                err,
+                TradingEngineError::AmendOrder(AmendOrderError::InsufficientFunds)
+            ));
+
+            // the 5 released by holding 1 at 5 pay for holding it at 10 again.
+            amend(5, BTC).await.unwrap();
+            assert_eq!(balance(&db, bob, "USD").await, 5);
+            amend(10, BTC).await.unwrap();
+            assert_eq!(balance(&db, bob, "USD").await, 0);
+            amend(11, BTC).await.unwrap_err();
+        })
+        .await;
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_engine_events(db: sqlx::PgPool) {
+        let (_config, te) = trading_engine_fixture(db.clone()).await;
+        let mut events = te.events.subscribe();
+        let (te, _task) = te.init_from_db(db.clone()).await.unwrap();
+
+        CX.scope((te, db), async {
+            let alice = new_user_uuid();
+            let bob = new_user_uuid();
+
+            let maker = place_limit_order(alice, OrderSide::Sell, 10, 5 * BTC).await;
+            let taker = place_limit_order(bob, OrderSide::Buy, 10, 3 * BTC).await;
+
+            let price = Amount::new(10).unwrap();
+            let expected = [
+                EngineEventKind::Accepted {
+                    order_uuid: maker.order_uuid,
+                    user_uuid: alice,
+                    side: OrderSide::Sell,
+                    order_type: OrderType::Limit,
+                    price,
+                    quantity: Amount::new(5 * BTC).unwrap(),
+                },
+                EngineEventKind::BookDelta {
+                    side: OrderSide::Sell,
+                    price,
+                    quantity: 5 * BTC,
+                },
+                EngineEventKind::Accepted {
+                    order_uuid: taker.order_uuid,
+                    user_uuid: bob,
+                    side: OrderSide::Buy,
+                    order_type: OrderType::Limit,
+                    price,
+                    quantity: Amount::new(3 * BTC).unwrap(),
+                },
+                EngineEventKind::Filled(engine_event::Fill {
+                    maker_order_uuid: maker.order_uuid,
+                    maker_user_uuid: alice,
+                    taker_order_uuid: taker.order_uuid,
+                    taker_user_uuid: bob,
+                    taker_side: OrderSide::Buy,
+                    price,
+                    quantity: 3 * BTC,
+                }),
+                EngineEventKind::BookDelta {
+                    side: OrderSide::Sell,
+                    price,
+                    quantity: 2 * BTC,
+                },
+            ];
+
+            for (seq, kind) in (1..).zip(expected) {
+                let event = events.try_recv().expect("event was published");
+                assert_eq!(event.seq, seq);
+                assert_eq!(event.market, BTC_USD);
+                assert_eq!(event.kind, kind);
+            }
+            assert!(events.try_recv().is_err());
+        })
+        .await;
+    }
+
+    #[test]
+    fn test_good_til_date_expiry() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+
+        let place_order = PlaceOrder::new(
+            BTC_USD,
+            new_user_uuid(),
+            OrderUuid::new_v4(),
+            Amount::new(10).unwrap(),
+            Amount::new(5).unwrap(),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilDate,
+            OrderSide::Sell,
+            Some(100),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        let result = do_place_order(&mut assets, place_order).unwrap();
+        assert!(result.order_index.is_some());
+        assert_eq!(assets.next_expiry(), Some(100));
+
+        let expired = do_expire_orders(&mut assets, ExpireOrders::new(99)).unwrap();
+        assert!(expired.is_empty());
+
+        let expired = do_expire_orders(&mut assets, ExpireOrders::new(100)).unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].order.quantity().get(), 5);
+        assert_eq!(assets.next_expiry(), None);
+        assert_eq!(
+            assets
+                .book_mut(BTC_USD)
+                .unwrap()
+                .orderbook_mut()
+                .iter_rel(OrderSide::Sell)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_book_orders() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+
+        let mut rest = |side, price, quantity| {
+            let place_order = PlaceOrder::new(
+                BTC_USD,
+                new_user_uuid(),
+                OrderUuid::new_v4(),
+                Amount::new(price).unwrap(),
+                Amount::new(quantity).unwrap(),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            do_place_order(&mut assets, place_order).unwrap().order_uuid
+        };
+
+        let first = rest(OrderSide::Sell, 10, 2);
+        let second = rest(OrderSide::Sell, 10, 3);
+        rest(OrderSide::Sell, 11, 1);
+        rest(OrderSide::Buy, 8, 4);
+
+        let orderbook = assets.book_mut(BTC_USD).unwrap().orderbook();
+        let book = orderbook.book_orders(1);
+        let asks: Vec<_> = book.asks.iter().map(|order| order.order_uuid).collect();
+        assert_eq!(asks, [first, second]);
+        assert_eq!(book.bids.len(), 1);
+
+        let depth = book.depth();
+        assert_eq!(depth.asks, [(Amount::new(10).unwrap(), 5)]);
+        assert_eq!(depth.bids, [(Amount::new(8).unwrap(), 4)]);
+
+        assert_eq!(orderbook.book_orders(2).asks.len(), 3);
+        assert_eq!(orderbook.book_orders(2).depth(), orderbook.depth(2));
+    }
+
+    #[test]
+    fn test_cancel_order_by_uuid() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let limit_order = |user_uuid, side, quantity| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                Amount::new(10).unwrap(),
+                Amount::new(quantity).unwrap(),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        // a resting order is registered under the uuid it was placed with.
+        let place_order = limit_order(alice, OrderSide::Sell, 5);
+        let order_uuid = place_order.order_uuid;
+        let result = do_place_order(&mut assets, place_order).unwrap();
+        assert_eq!(result.order_uuid, order_uuid);
+        assert!(assets.order_uuids.contains_key(&order_uuid));
+
+        // only the owner can cancel the order.
+        let err = do_cancel_order(&mut assets, CancelOrder::new(bob, order_uuid)).unwrap_err();
+        assert!(matches!(err, TradingEngineError::OrderNotFound(..)));
+
+        let cancelled = do_cancel_order(&mut assets, CancelOrder::new(alice, order_uuid)).unwrap();
+        assert_eq!(cancelled.order.order_uuid(), order_uuid);
+        assert!(!assets.order_uuids.contains_key(&order_uuid));
+
+        let err = do_cancel_order(&mut assets, CancelOrder::new(alice, order_uuid)).unwrap_err();
+        assert!(matches!(err, TradingEngineError::OrderNotFound(..)));
+
+        // a completely filled maker can no longer be cancelled.
+        let place_order = limit_order(alice, OrderSide::Sell, 5);
+        let order_uuid = place_order.order_uuid;
+        do_place_order(&mut assets, place_order).unwrap();
+        do_place_order(&mut assets, limit_order(bob, OrderSide::Buy, 5)).unwrap();
+        assert!(assets.order_uuids.is_empty());
+
+        let err = do_cancel_order(&mut assets, CancelOrder::new(alice, order_uuid)).unwrap_err();
+        assert!(matches!(err, TradingEngineError::OrderNotFound(..)));
+    }
+
+    #[test]
+    fn test_open_orders() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let limit_order = |user_uuid, side, price, quantity, time_in_force| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                Amount::new(price).unwrap(),
+                Amount::new(quantity).unwrap(),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                time_in_force,
+                side,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        let first = limit_order(alice, OrderSide::Sell, 10, 5, TimeInForce::GoodTilCanceled);
+        let second = limit_order(alice, OrderSide::Sell, 11, 3, TimeInForce::GoodTilCanceled);
+        let (first_uuid, second_uuid) = (first.order_uuid, second.order_uuid);
+        do_place_order(&mut assets, first).unwrap();
+        do_place_order(&mut assets, second).unwrap();
+
+        // a partial fill leaves the order open with what remains of it.
+        let taker = limit_order(bob, OrderSide::Buy, 10, 2, TimeInForce::ImmediateOrCancel);
+        do_place_order(&mut assets, taker).unwrap();
+        assert!(assets.open_orders(bob).is_empty());
+
+        let open_orders = assets.open_orders(alice);
+        assert_eq!(open_orders.len(), 2);
+        let first = open_orders
+            .iter()
+            .find(|order| order.order_uuid == first_uuid)
+            .unwrap();
+        assert_eq!(first.market, BTC_USD);
+        assert_eq!(first.side, OrderSide::Sell);
+        assert_eq!(first.price.get(), 10);
+        assert_eq!(first.quantity.get(), 5);
+        assert_eq!(first.remaining.get(), 3);
+        assert!(first.created_at.is_some());
+
+        // filled and cancelled orders are no longer open.
+        let taker = limit_order(bob, OrderSide::Buy, 10, 3, TimeInForce::GoodTilCanceled);
+        do_place_order(&mut assets, taker).unwrap();
+        let open_orders = assets.open_orders(alice);
+        assert_eq!(open_orders.len(), 1);
+        assert_eq!(open_orders[0].order_uuid, second_uuid);
+
+        do_cancel_order(&mut assets, CancelOrder::new(alice, second_uuid)).unwrap();
+        assert!(assets.open_orders(alice).is_empty());
+        assert!(assets.user_orders.is_empty());
+
+        // amended orders keep what was filled of them, one that lost its time priority is
+        // listed as if it was placed again.
+        let third = limit_order(alice, OrderSide::Sell, 12, 4, TimeInForce::GoodTilCanceled);
+        let fourth = limit_order(alice, OrderSide::Sell, 13, 1, TimeInForce::GoodTilCanceled);
+        let (third_uuid, fourth_uuid) = (third.order_uuid, fourth.order_uuid);
+        do_place_order(&mut assets, third).unwrap();
+        do_place_order(&mut assets, fourth).unwrap();
+        let taker = limit_order(bob, OrderSide::Buy, 12, 1, TimeInForce::ImmediateOrCancel);
+        do_place_order(&mut assets, taker).unwrap();
+
+        let amend = |quantity, amended_at| AmendOrder {
+            user_uuid: alice,
+            market: BTC_USD,
+            order_uuid: third_uuid,
+            price: Amount::new(12).unwrap(),
+            quantity: Amount::new(quantity).unwrap(),
+            amended_at: Some(amended_at),
+        };
+
+        do_amend_order(&mut assets, amend(2, u64::MAX)).unwrap();
+        let open_orders = assets.open_orders(alice);
+        let third = open_orders
+            .iter()
+            .find(|order| order.order_uuid == third_uuid)
+            .unwrap();
+        assert_eq!(third.quantity.get(), 3);
+        assert_eq!(third.remaining.get(), 2);
+        assert_ne!(third.created_at, Some(u64::MAX));
+
+        do_amend_order(&mut assets, amend(5, u64::MAX)).unwrap();
+        let open_orders = assets.open_orders(alice);
+        assert_eq!(open_orders[0].order_uuid, fourth_uuid);
+        assert_eq!(open_orders[1].order_uuid, third_uuid);
+        assert_eq!(open_orders[1].quantity.get(), 6);
+        assert_eq!(open_orders[1].remaining.get(), 5);
+        assert_eq!(open_orders[1].created_at, Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_cancel_all() {
+        const ETH_USD: Market = Market::new(fixtures::ETH, fixtures::USD);
+        let mut assets = Assets::with_markets([BTC_USD, ETH_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let mut place = |market, user_uuid, side, order_type, price, stop_price: Option<u64>| {
+            let place_order = PlaceOrder::new(
+                market,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(1),
+                order_type,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                stop_price.map(nz),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            do_place_order(&mut assets, place_order).unwrap().order_uuid
+        };
+
+        let btc_buy = place(BTC_USD, alice, OrderSide::Buy, OrderType::Limit, 9, None);
+        let btc_sell = place(BTC_USD, alice, OrderSide::Sell, OrderType::Limit, 11, None);
+        let btc_stop = place(
+            BTC_USD,
+            alice,
+            OrderSide::Sell,
+            OrderType::StopMarket,
+            5,
+            Some(6),
+        );
+        let eth_sell = place(ETH_USD, alice, OrderSide::Sell, OrderType::Limit, 11, None);
+        let bob_sell = place(BTC_USD, bob, OrderSide::Sell, OrderType::Limit, 12, None);
+
+        let err = do_cancel_all(
+            &mut assets,
+            CancelAll::new(alice, Some(Market::new(fixtures::ETH, fixtures::BTC)), None),
+        )
+        .unwrap_err();
+        assert!(matches!(err, TradingEngineError::UnknownMarket(..)));
+
+        // only the sells of the market are cancelled, the stop order among them.
+        let cancelled = do_cancel_all(
+            &mut assets,
+            CancelAll::new(alice, Some(BTC_USD), Some(OrderSide::Sell)),
+        )
+        .unwrap();
+        let mut cancelled_uuids = cancelled
+            .iter()
+            .map(|c| c.order.order_uuid())
+            .collect::<Vec<_>>();
+        let mut expected = vec![btc_sell, btc_stop];
+        cancelled_uuids.sort();
+        expected.sort();
+        assert_eq!(cancelled_uuids, expected);
+        assert!(assets.stop_uuids.is_empty());
+
+        let open_orders = assets.open_orders(alice);
+        assert_eq!(open_orders.len(), 2);
+        assert!(open_orders
+            .iter()
+            .all(|order| [btc_buy, eth_sell].contains(&order.order_uuid)));
+
+        // without filters every order of the user is cancelled, those of others are left.
+        let cancelled = do_cancel_all(&mut assets, CancelAll::new(alice, None, None)).unwrap();
+        assert_eq!(cancelled.len(), 2);
+        assert!(assets.open_orders(alice).is_empty());
+        assert!(
+            do_cancel_all(&mut assets, CancelAll::new(alice, None, None))
+                .unwrap()
+                .is_empty()
+        );
+
+        let open_orders = assets.open_orders(bob);
+        assert_eq!(open_orders.len(), 1);
+        assert_eq!(open_orders[0].order_uuid, bob_sell);
+    }
+
+    #[test]
+    fn test_amend_order_priority() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let mut sell = |user_uuid, price, quantity| {
+            let place_order = PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                Amount::new(price).unwrap(),
+                Amount::new(quantity).unwrap(),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                OrderSide::Sell,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            do_place_order(&mut assets, place_order).unwrap().order_uuid
+        };
+
+        let first = sell(alice, 10, 5);
+        let second = sell(bob, 10, 5);
+
+        let owners = |assets: &mut Assets| {
+            let orderbook = assets.book_mut(BTC_USD).unwrap().orderbook_mut();
+            let asks = orderbook.iter_rel(OrderSide::Sell);
+            asks.map(|(_, order)| order.order_uuid())
+                .collect::<Vec<_>>()
+        };
+
+        // reducing the quantity keeps the order at the front of its price level.
+        let amend = AmendOrder::new(alice, BTC_USD, first, nz(10), nz(3));
+        let result = do_amend_order(&mut assets, amend).unwrap();
+        assert!(result.kept_priority);
+        assert_eq!(result.order.quantity().get(), 3);
+        assert_eq!(owners(&mut assets), vec![first, second]);
+
+        // increasing the quantity moves it to the back.
+        let amend = AmendOrder::new(alice, BTC_USD, first, nz(10), nz(4));
+        let result = do_amend_order(&mut assets, amend).unwrap();
+        assert!(!result.kept_priority);
+        assert_eq!(owners(&mut assets), vec![second, first]);
+
+        // as does a price change, the order stays cancellable under the same uuid.
+        let amend = AmendOrder::new(bob, BTC_USD, second, nz(11), nz(5));
+        let result = do_amend_order(&mut assets, amend).unwrap();
+        assert!(!result.kept_priority);
+        assert_eq!(result.previous.price().get(), 10);
+        assert_eq!(owners(&mut assets), vec![first, second]);
+        assert_eq!(assets.order_uuids[&second].0, result.order_index);
+
+        // amendments never trade.
+        let bid = PlaceOrder::new(
+            BTC_USD,
+            bob,
+            OrderUuid::new_v4(),
+            nz(8),
+            nz(1),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilCanceled,
+            OrderSide::Buy,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        do_place_order(&mut assets, bid).unwrap();
+
+        let amend = AmendOrder::new(alice, BTC_USD, first, nz(8), nz(4));
+        let err = do_amend_order(&mut assets, amend).unwrap_err();
+        assert!(matches!(
+            err,
+            TradingEngineError::AmendOrder(AmendOrderError::WouldCross)
+        ));
+
+        // only the owner can amend the order.
+        let amend = AmendOrder::new(bob, BTC_USD, first, nz(10), nz(1));
+        let err = do_amend_order(&mut assets, amend).unwrap_err();
+        assert!(matches!(err, TradingEngineError::OrderNotFound(..)));
+    }
+
+    fn nz(n: u64) -> Amount {
+        Amount::new(n).unwrap()
+    }
+
+    /// one whole bitcoin in satoshis, prices are for one whole bitcoin.
+    const BTC: u64 = 100_000_000;
+
+    #[test]
+    fn test_stop_orders_trigger() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+        let carol = Uuid::from_u128(3);
+
+        let order = |user_uuid, side, order_type, price, quantity, stop_price: Option<u64>| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(quantity),
+                order_type,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                stop_price.map(nz),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        do_place_order(
+            &mut assets,
+            order(alice, OrderSide::Sell, OrderType::Limit, 10, 5, None),
+        )
+        .unwrap();
+        do_place_order(
+            &mut assets,
+            order(alice, OrderSide::Sell, OrderType::Limit, 12, 5, None),
+        )
+        .unwrap();
+
+        // a stop without a stop price is rejected.
+        let err = do_place_order(
+            &mut assets,
+            order(carol, OrderSide::Buy, OrderType::StopMarket, 12, 2, None),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            TradingEngineError::PlaceOrder(PlaceOrderError::InvalidStopPrice)
+        ));
+
+        let stop = do_place_order(
+            &mut assets,
+            order(
+                carol,
+                OrderSide::Buy,
+                OrderType::StopMarket,
+                12,
+                2,
+                Some(10),
+            ),
+        )
+        .unwrap();
+        assert_eq!(stop.fill_type, FillType::None);
+        assert!(stop.order_index.is_none());
+        assert!(assets.next_triggered_stops().is_none());
+
+        // a cancellable stop that only its owner can cancel.
+        let cancel = do_place_order(
+            &mut assets,
+            order(carol, OrderSide::Sell, OrderType::StopLimit, 5, 1, Some(6)),
+        )
+        .unwrap();
+        let err =
+            do_cancel_order(&mut assets, CancelOrder::new(bob, cancel.order_uuid)).unwrap_err();
+        assert!(matches!(err, TradingEngineError::OrderNotFound(..)));
+        let cancelled =
+            do_cancel_order(&mut assets, CancelOrder::new(carol, cancel.order_uuid)).unwrap();
+        assert_eq!(cancelled.order_type, OrderType::StopLimit);
+
+        // a trade at 10 crosses the stop price.
+        do_place_order(
+            &mut assets,
+            order(bob, OrderSide::Buy, OrderType::Limit, 10, 1, None),
+        )
+        .unwrap();
+        let trigger_stops = assets.next_triggered_stops().expect("stop triggered");
+
+        // triggering is logged and replayed as its own command.
+        let jstr = serde_json::to_value(trigger_stops).unwrap();
+        let payload: TradeCmdPayload = serde_json::from_value(jstr).unwrap();
+        assert!(matches!(payload, TradeCmdPayload::TriggerStops(_)));
+
+        let triggered = do_trigger_stops(&mut assets, trigger_stops).unwrap();
+        assert!(triggered.rejected.is_empty());
+        assert_eq!(triggered.placed.len(), 1);
+
+        let placed = &triggered.placed[0];
+        assert_eq!(placed.order_uuid, stop.order_uuid);
+        assert_eq!(placed.order_type, OrderType::Market);
+        assert_eq!(placed.fill_type, FillType::Complete);
+        assert_eq!(placed.quantity_filled, 2);
+
+        assert!(assets.next_triggered_stops().is_none());
+        assert!(assets.stop_uuids.is_empty());
+    }
+
+    #[test]
+    fn test_decimal_amounts() {
+        use crate::amount::{format_decimal, AmountError};
+
+        assert_eq!(Amount::parse_decimal("0.0015", 8), Ok(nz(150_000)));
+        assert_eq!(Amount::parse_decimal("1.50", 1), Ok(nz(15)));
+        assert_eq!(Amount::parse_decimal("12", 2), Ok(nz(1200)));
+        assert_eq!(Amount::parse_decimal(".5", 2), Ok(nz(50)));
+        assert_eq!(
+            Amount::parse_decimal("0.001", 2),
+            Err(AmountError::TooManyDecimals(2))
+        );
+        assert_eq!(Amount::parse_decimal("0.00", 2), Err(AmountError::Zero));
+        for invalid in ["", ".", "-1", "1e3", "1.2.3", " 1"] {
+            assert_eq!(
+                Amount::parse_decimal(invalid, 8),
+                Err(AmountError::Invalid),
+                "{invalid:?}"
+            );
+        }
+        assert_eq!(
+            Amount::parse_decimal("100000000000", 8),
+            Err(AmountError::TooLarge)
+        );
+
+        assert_eq!(format_decimal(150_000, 8), "0.00150000");
+        assert_eq!(nz(1200).to_decimal(2), "12.00");
+
+        // 0.0015 BTC at 30000.01 USD is worth 45.000015 USD, trades pay 45.00 and buys hold 45.01.
+        let price = Amount::parse_decimal("30000.01", 2).unwrap();
+        assert_eq!(BTC_USD.notional(price, 150_000), 4500);
+        assert_eq!(BTC_USD.notional_ceil(price, 150_000), 4501);
+        assert_eq!(amount::quantity_for(price, 4500, 8), 149_999);
+    }
+
+    #[test]
+    fn test_market_rules() {
+        let mut assets = Assets::new();
+        let rules = MarketRules {
+            tick_size: nz(5),
+            lot_size: nz(2 * BTC),
+            min_quantity: nz(2 * BTC),
+            max_quantity: Some(nz(100 * BTC)),
+            min_notional: 50,
+        };
+        assert!(assets.list_market(BTC_USD, rules));
+        assert!(!assets.list_market(BTC_USD, rules));
+
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let order = |user_uuid, side, price, quantity, post_only| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(quantity * BTC),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                None,
+                post_only,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        let rejected = |price, quantity| {
+            rules
+                .check_order(&order(alice, OrderSide::Sell, price, quantity, None))
+                .unwrap_err()
+        };
+        assert_eq!(rejected(12, 10), MarketRuleError::PriceOffTick(nz(5)));
+        assert_eq!(
+            rejected(10, 9),
+            MarketRuleError::QuantityOffLot(nz(2 * BTC))
+        );
+        assert_eq!(
+            rejected(100, 102),
+            MarketRuleError::QuantityTooLarge(nz(100 * BTC))
+        );
+        assert_eq!(rejected(20, 2), MarketRuleError::NotionalTooSmall(50));
+
+        // the engine rejects what breaks the rules even if it was not checked before.
+        let result = do_place_order(&mut assets, order(alice, OrderSide::Sell, 12, 10, None));
+        assert!(matches!(
+            result,
+            Err(TradingEngineError::PlaceOrder(PlaceOrderError::MarketRule(
+                MarketRuleError::PriceOffTick(_)
+            )))
+        ));
+
+        do_place_order(&mut assets, order(alice, OrderSide::Sell, 10, 10, None)).unwrap();
+
+        // post-only orders are repriced a whole tick away from the best ask.
+        let repriced = do_place_order(
+            &mut assets,
+            order(bob, OrderSide::Buy, 15, 10, Some(PostOnly::Reprice)),
+        )
+        .unwrap();
+        assert_eq!(repriced.repriced, Some(nz(5)));
+
+        // a buy by quote amount only fills whole lots, 50 buys 5 BTC at 10 but 4 BTC are bought.
+        let quote_buy = PlaceOrder::new(
+            BTC_USD,
+            bob,
+            OrderUuid::new_v4(),
+            nz(10),
+            nz(10 * BTC),
+            OrderType::Market,
+            SelfTradeProtection::default(),
+            TimeInForce::ImmediateOrCancel,
+            OrderSide::Buy,
+            None,
+            None,
+            None,
+            None,
+            Some(nz(50)),
+            None,
+            None,
+        );
+        let bought = do_place_order(&mut assets, quote_buy).unwrap();
+        assert_eq!(bought.quantity_filled, 4 * BTC);
+        assert_eq!(bought.fills.len(), 1);
+    }
+
+    #[test]
+    fn test_post_only() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let order = |user_uuid, side, price, post_only| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(5),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                None,
+                post_only,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        do_place_order(&mut assets, order(alice, OrderSide::Sell, 10, None)).unwrap();
+
+        // post-only orders that do not cross rest as usual.
+        let resting = do_place_order(
+            &mut assets,
+            order(bob, OrderSide::Buy, 8, Some(PostOnly::Reject)),
+        )
+        .unwrap();
+        assert!(resting.order_index.is_some());
+        assert_eq!(resting.repriced, None);
+
+        let result = do_place_order(
+            &mut assets,
+            order(bob, OrderSide::Buy, 11, Some(PostOnly::Reject)),
+        );
+        assert!(matches!(
+            result,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::PostOnlyWouldTake
+            ))
+        ));
+
+        // repriced one tick below the best ask instead of taking it.
+        let repriced = do_place_order(
+            &mut assets,
+            order(bob, OrderSide::Buy, 11, Some(PostOnly::Reprice)),
+        )
+        .unwrap();
+        assert_eq!(repriced.price.get(), 11);
+        assert_eq!(repriced.repriced, Some(nz(9)));
+        assert_eq!(repriced.fill_type, FillType::None);
+        assert_eq!(
+            repriced.order_index.map(|oix| oix.side()),
+            Some(OrderSide::Buy)
+        );
+
+        // nothing was taken from the ask.
+        let asks = assets
+            .book_mut(BTC_USD)
+            .unwrap()
+            .orderbook_mut()
+            .iter_rel(OrderSide::Sell);
+        assert_eq!(
+            asks.map(|(_, order)| order.quantity().get()).sum::<u64>(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_iceberg_replenishes_at_back_of_level() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+        let carol = Uuid::from_u128(3);
+
+        let order = |user_uuid, side, quantity, display_quantity: Option<u64>| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(10),
+                nz(quantity),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                None,
+                None,
+                display_quantity.map(nz),
+                None,
+                None,
+                None,
+            )
+        };
+
+        let asks = |assets: &mut Assets| {
+            let orderbook = assets.book_mut(BTC_USD).unwrap().orderbook_mut();
+            let asks = orderbook.iter_rel(OrderSide::Sell);
+            asks.map(|(_, order)| (order.owner(), order.quantity().get()))
+                .collect::<Vec<_>>()
+        };
+
+        // only the displayed slice is visible in the orderbook.
+        let iceberg =
+            do_place_order(&mut assets, order(alice, OrderSide::Sell, 10, Some(3))).unwrap();
+        assert_eq!(iceberg.quantity_remaining, 10);
+        do_place_order(&mut assets, order(carol, OrderSide::Sell, 2, None)).unwrap();
+        assert_eq!(asks(&mut assets), vec![(alice, 3), (carol, 2)]);
+
+        // consuming the slice shows the next one behind carol's order.
+        let taker = do_place_order(&mut assets, order(bob, OrderSide::Buy, 3, None)).unwrap();
+        assert_eq!(taker.fill_type, FillType::Complete);
+        assert_eq!(asks(&mut assets), vec![(carol, 2), (alice, 3)]);
+
+        // the replenished slice can still be cancelled under the same uuid.
+        let (order_index, _) = assets.order_uuids[&iceberg.order_uuid];
+        let orderbook = assets.book_mut(BTC_USD).unwrap().orderbook_mut();
+        let slice = orderbook.get_mut(order_index).unwrap();
+        assert_eq!(slice.total_quantity().get(), 7);
+        assert_eq!(assets.open_orders(alice)[0].remaining.get(), 7);
+
+        // a taker larger than the book trades through the slices replenished while it matched.
+        let taker = do_place_order(&mut assets, order(bob, OrderSide::Buy, 20, None)).unwrap();
+        assert_eq!(taker.quantity_filled, 9);
+        assert_eq!(taker.quantity_remaining, 11);
+        assert!(asks(&mut assets).is_empty());
+        assert!(!assets.order_uuids.contains_key(&iceberg.order_uuid));
+        assert!(assets.open_orders(alice).is_empty());
+    }
+
+    #[test]
+    fn test_oco_cancels_sibling() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let order = |user_uuid, side, order_type, price, quantity, stop_price: Option<u64>| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(quantity),
+                order_type,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                stop_price.map(nz),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        // a take-profit limit order and a stop-loss order.
+        let place_oco = PlaceOco::new(
+            OcoGroupUuid::new_v4(),
+            [
+                order(alice, OrderSide::Sell, OrderType::Limit, 12, 5, None),
+                order(alice, OrderSide::Sell, OrderType::StopMarket, 8, 5, Some(9)),
+            ],
+        );
+
+        // linked orders are logged and replayed as a single command.
+        let jstr = serde_json::to_value(&place_oco).unwrap();
+        let payload: TradeCmdPayload = serde_json::from_value(jstr).unwrap();
+        assert!(matches!(payload, TradeCmdPayload::PlaceOco(_)));
+
+        let oco = do_place_oco(&mut assets, place_oco).unwrap();
+        let [take_profit, stop_loss] = oco.order_uuids;
+        assert_eq!(oco.placed.len(), 2);
+        assert!(oco.cancelled.is_empty());
+        assert_eq!(assets.oco_groups[&oco.group_uuid], oco.order_uuids);
+        assert!(assets.next_oco_cancels().is_none());
+
+        // a partial fill of the take-profit order cancels the stop-loss order.
+        do_place_order(
+            &mut assets,
+            order(bob, OrderSide::Buy, OrderType::Limit, 12, 2, None),
+        )
+        .unwrap();
+        assert!(assets.oco_groups.is_empty());
+        assert!(assets.oco_legs.is_empty());
+
+        let cancel_siblings = assets.next_oco_cancels().expect("sibling queued");
+        let jstr = serde_json::to_value(&cancel_siblings).unwrap();
+        let payload: TradeCmdPayload = serde_json::from_value(jstr).unwrap();
+        assert!(matches!(payload, TradeCmdPayload::CancelOcoSiblings(_)));
+
+        let cancelled = do_cancel_oco_siblings(&mut assets, cancel_siblings).unwrap();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].order.order_uuid(), stop_loss);
+        assert_eq!(cancelled[0].order_type, OrderType::StopMarket);
+        assert!(assets.stop_uuids.is_empty());
+        assert!(assets.next_oco_cancels().is_none());
+        assert!(assets.order_uuids.contains_key(&take_profit));
+
+        // cancelling either order cancels the other one.
+        let oco = do_place_oco(
+            &mut assets,
+            PlaceOco::new(
+                OcoGroupUuid::new_v4(),
+                [
+                    order(alice, OrderSide::Sell, OrderType::Limit, 20, 1, None),
+                    order(alice, OrderSide::Sell, OrderType::Limit, 21, 1, None),
+                ],
+            ),
+        )
+        .unwrap();
+        let [first, second] = oco.order_uuids;
+        do_cancel_order(&mut assets, CancelOrder::new(alice, second)).unwrap();
+
+        let cancel_siblings = assets.next_oco_cancels().expect("sibling queued");
+        let cancelled = do_cancel_oco_siblings(&mut assets, cancel_siblings).unwrap();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].order.order_uuid(), first);
+        assert!(!assets.order_uuids.contains_key(&first));
+
+        // a first order that trades right away means the second one is never placed.
+        do_place_order(
+            &mut assets,
+            order(bob, OrderSide::Buy, OrderType::Limit, 5, 1, None),
+        )
+        .unwrap();
+        let oco = do_place_oco(
+            &mut assets,
+            PlaceOco::new(
+                OcoGroupUuid::new_v4(),
+                [
+                    order(alice, OrderSide::Sell, OrderType::Limit, 5, 1, None),
+                    order(alice, OrderSide::Sell, OrderType::StopMarket, 4, 1, Some(4)),
+                ],
+            ),
+        )
+        .unwrap();
+        assert_eq!(oco.placed.len(), 1);
+        assert_eq!(oco.placed[0].quantity_filled, 1);
+        assert_eq!(oco.cancelled.len(), 1);
+        assert_eq!(oco.cancelled[0].order.order_uuid(), oco.order_uuids[1]);
+        assert!(assets.stop_uuids.is_empty());
+        assert!(assets.oco_groups.is_empty());
+        assert!(assets.next_oco_cancels().is_none());
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let order = |user_uuid, side, order_type, price, quantity, stop_price: Option<u64>| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(quantity),
+                order_type,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                stop_price.map(nz),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        for (price, quantity) in [(10, 2), (10, 3), (11, 4)] {
+            let sell = order(
+                alice,
+                OrderSide::Sell,
+                OrderType::Limit,
+                price,
+                quantity,
+                None,
+            );
+            do_place_order(&mut assets, sell).unwrap();
+        }
+        do_place_order(
+            &mut assets,
+            order(bob, OrderSide::Buy, OrderType::Limit, 10, 1, None),
+        )
+        .unwrap();
+        do_place_oco(
+            &mut assets,
+            PlaceOco::new(
+                OcoGroupUuid::new_v4(),
+                [
+                    order(bob, OrderSide::Buy, OrderType::Limit, 8, 5, None),
+                    order(bob, OrderSide::Buy, OrderType::StopMarket, 12, 5, Some(11)),
+                ],
+            ),
+        )
+        .unwrap();
+
+        let snapshot = assets.snapshot().encode().unwrap();
+        let mut restored = Assets::restore(AssetsSnapshot::decode(&snapshot).unwrap());
+
+        // the restored state matches the original, including the order of each price level.
+        let book = |assets: &mut Assets, side| {
+            assets
+                .book_mut(BTC_USD)
+                .unwrap()
+                .orderbook_mut()
+                .iter_rel(side)
+                .collect::<Vec<_>>()
+        };
+        for side in [OrderSide::Buy, OrderSide::Sell] {
+            assert_eq!(book(&mut assets, side), book(&mut restored, side));
+        }
+        assert_eq!(assets.order_uuids, restored.order_uuids);
+        assert_eq!(assets.stop_uuids, restored.stop_uuids);
+        assert_eq!(assets.oco_legs, restored.oco_legs);
+        assert_eq!(assets.open_orders(alice), restored.open_orders(alice));
+        assert_eq!(assets.open_orders(bob), restored.open_orders(bob));
+
+        // snapshots taken before the orders of every user were kept rebuild them.
+        let mut legacy = AssetsSnapshot::decode(&snapshot).unwrap();
+        legacy.user_orders = None;
+        let legacy = Assets::restore(legacy);
+        let remaining = |assets: &Assets, user_uuid| {
+            let mut orders = assets
+                .open_orders(user_uuid)
+                .into_iter()
+                .map(|order| (order.order_uuid, order.remaining))
+                .collect::<Vec<_>>();
+            orders.sort();
+            orders
+        };
+        assert_eq!(remaining(&assets, alice), remaining(&legacy, alice));
+        assert_eq!(remaining(&assets, bob), remaining(&legacy, bob));
+        assert_eq!(remaining(&legacy, alice).len(), 3);
+
+        // and it keeps trading the same way, triggering the stop order of the linked orders.
+        let taker = order(bob, OrderSide::Buy, OrderType::Limit, 11, 6, None);
+        let expected = do_place_order(&mut assets, taker.clone()).unwrap();
+        let result = do_place_order(&mut restored, taker).unwrap();
+        assert_eq!(expected.quantity_filled, result.quantity_filled);
+        assert_eq!(expected.fills.len(), result.fills.len());
+        assert!(restored.next_triggered_stops().is_some());
+        assert_eq!(
+            assets.next_triggered_stops().map(|t| t.last_price),
+            restored.next_triggered_stops().map(|t| t.last_price)
+        );
+        for side in [OrderSide::Buy, OrderSide::Sell] {
+            assert_eq!(book(&mut assets, side), book(&mut restored, side));
+        }
+    }
+
+    #[test]
+    fn test_replay_reports_divergences() {
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+        let carol = Uuid::from_u128(3);
+
+        let order = |user_uuid, side, order_type, price, quantity, stop_price: Option<u64>| {
+            let place_order = PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(quantity),
+                order_type,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                None,
+                stop_price.map(nz),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+
+            // replays read the commands back from the log.
+            let jstr = serde_json::to_value(&place_order).unwrap();
+            serde_json::from_value::<TradeCmdPayload>(jstr).unwrap()
+        };
+        let trigger_stops = || {
+            TradeCmdPayload::TriggerStops(TriggerStops {
+                market: BTC_USD,
+                last_price: nz(10),
+            })
+        };
+
+        let sell = order(alice, OrderSide::Sell, OrderType::Limit, 10, 5, None);
+        let stop = order(
+            carol,
+            OrderSide::Buy,
+            OrderType::StopMarket,
+            11,
+            1,
+            Some(10),
+        );
+        let buy = order(bob, OrderSide::Buy, OrderType::Limit, 10, 2, None);
+        let jstr = |payload: &TradeCmdPayload| serde_json::to_string(payload).unwrap();
+        let log = [jstr(&sell), jstr(&stop), jstr(&buy), jstr(&trigger_stops())];
+
+        // the trade at 10 triggers the stop order, which the engine logged right after it.
+        let replay_log = |log: &[String]| {
+            let mut replay = Replay::new([(BTC_USD, MarketRules::default())]);
+            for (event_id, jstr) in (1..).zip(log) {
+                replay.apply(event_id, serde_json::from_str(jstr).unwrap());
+            }
+            replay
+        };
+
+        let replay = replay_log(&log);
+        let snapshot = replay.assets().snapshot();
+        let report = replay.finish();
+        assert!(report.divergences.is_empty(), "{:?}", report.divergences);
+        assert_eq!(report.events, 4);
+        assert_eq!(report.digest, replay_log(&log).finish().digest);
+        assert_eq!(report.digest, snapshot.digest());
+
+        // a snapshot of a different state diverges.
+        let mut replay = replay_log(&log[..3]);
+        replay.check_snapshot(&snapshot);
+        assert!(matches!(
+            replay.finish().divergences[..],
+            [
+                Divergence::Snapshot { event_id: 3, .. },
+                Divergence::MissingFollowUp { .. }
+            ]
+        ));
+
+        // the trigger is logged after the next order, and then once more with nothing to trigger.
+        let mut replay = replay_log(&log[..3]);
+        replay.apply(4, order(bob, OrderSide::Buy, OrderType::Limit, 9, 1, None));
+        replay.apply(5, trigger_stops());
+        replay.apply(6, trigger_stops());
+        let report = replay.finish();
+        assert!(matches!(
+            report.divergences[..],
+            [
+                Divergence::MissingFollowUp { event_id: 4, .. },
+                Divergence::UnexpectedFollowUp { event_id: 6, .. },
+                ..
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_market_buy_by_quote_amount() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let sell = |price, quantity: u64| {
+            PlaceOrder::new(
+                BTC_USD,
+                alice,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(quantity * BTC),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                OrderSide::Sell,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+        let market_buy =
+            |price, quantity, quote_amount, protection_price, max_slippage_bps: u32| {
+                PlaceOrder::new(
+                    BTC_USD,
+                    bob,
+                    OrderUuid::new_v4(),
+                    nz(price),
+                    nz(quantity),
+                    OrderType::Market,
+                    SelfTradeProtection::default(),
+                    TimeInForce::ImmediateOrCancel,
+                    OrderSide::Buy,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Amount::new(quote_amount),
+                    Amount::new(protection_price),
+                    NonZeroU32::new(max_slippage_bps),
+                )
+            };
+
+        do_place_order(&mut assets, sell(10, 2)).unwrap();
+        do_place_order(&mut assets, sell(11, 2)).unwrap();
+        do_place_order(&mut assets, sell(15, 5)).unwrap();
+
+        // 31 buys 2 at 10 and 1 at 11.
+        let result =
+            do_place_order(&mut assets, market_buy(10, Amount::MAX.get(), 31, 0, 0)).unwrap();
+        assert_eq!(result.quantity_filled, 3 * BTC);
+        assert_eq!(result.quote_amount, Amount::new(31));
+        assert!(result.order_index.is_none());
+        let spent = result
+            .fills
+            .iter()
+            .map(|fill| BTC_USD.notional(fill.maker.price(), fill.fill_amount))
+            .sum::<u64>();
+        assert_eq!(spent, 31);
+
+        // 5% slippage from the best price of 11 stops before the asks at 15.
+        let result = do_place_order(&mut assets, market_buy(10, 10 * BTC, 0, 0, 500)).unwrap();
+        assert_eq!(result.quantity_filled, BTC);
+        assert_eq!(result.fill_type, FillType::Partial);
+
+        // nothing is left at or below the protection price.
+        let err = do_place_order(&mut assets, market_buy(15, BTC, 0, 14, 0)).unwrap_err();
+        assert!(matches!(
+            err,
+            TradingEngineError::PlaceOrder(PlaceOrderError::InsufficientLiquidity)
+        ));
+
+        // without any other bound a market buy only spends what its price reserved, the 10 it
+        // reserved at 10 buy two thirds at 15.
+        let result = do_place_order(&mut assets, market_buy(10, BTC, 0, 0, 0)).unwrap();
+        assert_eq!(result.fill_type, FillType::Partial);
+        assert_eq!(result.quantity_filled, 66_666_666);
+
+        // the 157 reserved at 15 buy the rest.
+        let result = do_place_order(&mut assets, market_buy(15, 10 * BTC, 0, 0, 0)).unwrap();
+        assert_eq!(result.quantity_filled, 5 * BTC - 66_666_666);
+    }
+
+    #[test]
+    fn test_market_buy_within_reservation() {
+        let mut assets = Assets::with_markets([BTC_USD]);
+        let alice = Uuid::from_u128(1);
+        let bob = Uuid::from_u128(2);
+
+        let order = |user_uuid, side, order_type, price, time_in_force| {
+            PlaceOrder::new(
+                BTC_USD,
+                user_uuid,
+                OrderUuid::new_v4(),
+                nz(price),
+                nz(2 * BTC),
+                order_type,
+                SelfTradeProtection::default(),
+                time_in_force,
+                side,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+        };
+
+        let ask = order(
+            alice,
+            OrderSide::Sell,
+            OrderType::Limit,
+            115,
+            TimeInForce::GoodTilCanceled,
+        );
+        do_place_order(
+            &mut assets,
+            PlaceOrder {
+                quantity: nz(BTC),
+                ..ask
+            },
+        )
+        .unwrap();
+
+        // 2 at 100 reserve 210, buying 1 at 115 and holding 1 at 100 would need 215.
+        let buy = order(
+            bob,
+            OrderSide::Buy,
+            OrderType::Market,
+            100,
+            TimeInForce::GoodTilCanceled,
+        );
+        let err = do_place_order(&mut assets, buy).unwrap_err();
+        assert!(matches!(
+            err,
+            TradingEngineError::PlaceOrder(PlaceOrderError::ExceedsReservation)
+        ));
+        assert_eq!(
+            assets.books[&BTC_USD]
+                .orderbook()
+                .iter_rel(OrderSide::Sell)
+                .count(),
+            1
+        );
+
+        // nothing is held for what an immediate-or-cancel order does not buy.
+        let buy = order(
+            bob,
+            OrderSide::Buy,
+            OrderType::Market,
+            100,
+            TimeInForce::ImmediateOrCancel,
+        );
+        let result = do_place_order(&mut assets, buy).unwrap();
+        assert_eq!(result.quantity_filled, BTC);
+        assert!(result.order_index.is_none());
+    }
 }
@@ -1,13 +1,15 @@
 //! The orderbook module contains the data structures and logic for the orderbook.
 
-use std::num::NonZeroU32;
-
 use tinyvec::{tiny_vec, TinyVec};
 
 use serde::{Deserialize, Serialize};
 
+use super::snapshot::{OrderbookSnapshot, PriceLevelSnapshot};
+use super::OrderUuid;
+use crate::Amount;
+
 /// The side of an order.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum OrderSide {
     /// Buy side.
@@ -27,31 +29,110 @@ pub enum OrderType {
     /// Market order.
     #[serde(rename = "market")]
     Market,
+    /// Stop-market order, placed as a market order once the last trade price crosses its stop price.
+    #[serde(rename = "stop_market")]
+    StopMarket,
+    /// Stop-limit order, placed as a limit order once the last trade price crosses its stop price.
+    #[serde(rename = "stop_limit")]
+    StopLimit,
+}
+
+impl OrderType {
+    /// Returns `true` for orders that wait in the stop book until they are triggered.
+    #[inline]
+    pub fn is_stop(&self) -> bool {
+        matches!(self, Self::StopMarket | Self::StopLimit)
+    }
+
+    /// Returns the type a stop order is placed as once it is triggered.
+    #[inline]
+    pub fn triggered(&self) -> Self {
+        match self {
+            Self::StopMarket => Self::Market,
+            Self::StopLimit => Self::Limit,
+            other => *other,
+        }
+    }
 }
 
 /// The time in force of an order.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Order {
     /// Some distinct, monotonic sequence number for the order.
     pub(super) memo: u32,
     /// The quantity of the order.
-    pub(super) quantity: NonZeroU32,
+    pub(super) quantity: Amount,
     /// The price of the order.
-    pub(super) price: NonZeroU32,
+    pub(super) price: Amount,
+    /// The user that owns the order.
+    pub(super) owner: uuid::Uuid,
+    /// The unix timestamp (in seconds) a good-til-date order expires at.
+    pub(super) expire_at: Option<u64>,
+    /// The unique identifier of the order, assigned before the order is logged.
+    pub(super) order_uuid: OrderUuid,
+    /// The size of the slice an iceberg order shows in the orderbook, `None` for fully displayed orders.
+    pub(super) display_quantity: Option<Amount>,
+    /// The quantity of an iceberg order that is held back, `quantity` is the displayed slice.
+    pub(super) hidden_quantity: u64,
 }
 
 impl Order {
     /// Returns the quantity of the order.
     #[inline]
-    pub fn quantity(&self) -> NonZeroU32 {
+    pub fn quantity(&self) -> Amount {
         self.quantity
     }
 
     /// Returns the price of the order.
     #[inline]
-    pub fn price(&self) -> NonZeroU32 {
+    pub fn price(&self) -> Amount {
         self.price
     }
+
+    /// Returns the user that owns the order.
+    #[inline]
+    pub fn owner(&self) -> uuid::Uuid {
+        self.owner
+    }
+
+    /// Returns the unix timestamp (in seconds) the order expires at, if it is a good-til-date order.
+    #[inline]
+    pub fn expire_at(&self) -> Option<u64> {
+        self.expire_at
+    }
+
+    /// Returns the unique identifier of the order.
+    #[inline]
+    pub fn order_uuid(&self) -> OrderUuid {
+        self.order_uuid
+    }
+
+    /// Returns the displayed and hidden quantity of the order together.
+    #[inline]
+    pub fn total_quantity(&self) -> Amount {
+        self.quantity
+            .checked_add(self.hidden_quantity)
+            .expect("total quantity of an order fits in an amount")
+    }
+
+    /// Returns the order with its total quantity split into the displayed slice and the hidden
+    /// reserve, orders without a display quantity are returned as-is.
+    pub(super) fn displayed(mut self) -> Self {
+        if let Some(display_quantity) = self.display_quantity {
+            let total = self.total_quantity();
+            self.quantity = total.min(display_quantity);
+            self.hidden_quantity = total.get() - self.quantity.get();
+        }
+        self
+    }
+
+    /// Returns the next slice of an iceberg order once its displayed slice is consumed, `None` if
+    /// there is no hidden quantity left.
+    pub(super) fn replenished(mut self) -> Option<Self> {
+        self.quantity = Amount::new(self.hidden_quantity)?;
+        self.hidden_quantity = 0;
+        Some(self.displayed())
+    }
 }
 
 /// The threshold at which the [`PriceLevel`] will switch from using array storage to heap storage.
@@ -61,7 +142,7 @@ const PRICE_LEVEL_INNER_CAPACITY: usize = 64;
 #[derive(Debug, Default)]
 pub struct PriceLevel {
     /// The price of the orders in this price level.
-    price: u32,
+    price: u64,
     /// The sequence number generator for the next order to be added to this price level.
     memo_seq: u32,
     /// The inner data structure storing the orders in this price level.
@@ -79,8 +160,8 @@ impl PriceLevel {
 
     #[inline]
     #[track_caller]
-    fn push_order(&mut self, mut t: Order) -> (NonZeroU32, u32) {
-        let price = NonZeroU32::new(self.price).expect("price for price-level should not be zero");
+    fn push_order(&mut self, mut t: Order) -> (Amount, u32) {
+        let price = Amount::new(self.price).expect("price for price-level should not be zero");
         let memo = self.memo_seq;
         self.memo_seq += 1;
         t.memo = memo;
@@ -121,7 +202,7 @@ impl MultiplePriceLevels {
     }
 
     /// Returns the [`PriceLevel`] for the given price.
-    pub fn get_or_insert_price_level(&mut self, price: NonZeroU32) -> &mut PriceLevel {
+    pub fn get_or_insert_price_level(&mut self, price: Amount) -> &mut PriceLevel {
         let index = self
             .inner
             .binary_search_by_key(&price.get(), |level| level.price);
@@ -143,7 +224,7 @@ impl MultiplePriceLevels {
     }
 
     /// Pushes an order to the [`MultiplePriceLevels`] returns a tuple of the price and memo of the order.
-    pub fn push_order_to_level(&mut self, t: Order) -> (NonZeroU32, u32) {
+    pub fn push_order_to_level(&mut self, t: Order) -> (Amount, u32) {
         let index = self
             .inner
             .binary_search_by_key(&t.price.get(), |level| level.price);
@@ -167,7 +248,7 @@ impl MultiplePriceLevels {
     }
 
     /// Removes an order from the [`MultiplePriceLevels`] returns the order if it existed.
-    pub fn remove_order_from_level(&mut self, (price, memo): (NonZeroU32, u32)) -> Option<Order> {
+    pub fn remove_order_from_level(&mut self, (price, memo): (Amount, u32)) -> Option<Order> {
         let price_level_index = self
             .inner
             .binary_search_by_key(&price.get(), |level| level.price)
@@ -186,8 +267,22 @@ impl MultiplePriceLevels {
         order
     }
 
+    /// Returns a reference to an [`Order`] in the [`MultiplePriceLevels`] if it exists.
+    pub fn get(&self, (price, memo): (Amount, u32)) -> Option<&Order> {
+        let index = self
+            .inner
+            .binary_search_by_key(&price.get(), |level| level.price)
+            .ok()?;
+
+        self.inner
+            .get(index)?
+            .inner
+            .iter()
+            .find_map(|o| o.as_ref().filter(|o| o.memo == memo))
+    }
+
     /// Returns a mutable reference to an [`Order`] in the [`MultiplePriceLevels`] if it exists.
-    pub fn get_mut(&mut self, (price, memo): (NonZeroU32, u32)) -> Option<&mut Order> {
+    pub fn get_mut(&mut self, (price, memo): (Amount, u32)) -> Option<&mut Order> {
         let index = self
             .inner
             .binary_search_by_key(&price.get(), |level| level.price)
@@ -203,13 +298,79 @@ impl MultiplePriceLevels {
 }
 
 /// An index into the [`Orderbook`] which can be used to identify an order.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct OrderIndex {
     side: OrderSide,
-    price: NonZeroU32,
+    price: Amount,
     memo: u32,
 }
 
+impl OrderIndex {
+    /// Returns the side of the orderbook the order is on.
+    #[inline]
+    pub fn side(&self) -> OrderSide {
+        self.side
+    }
+
+    /// Returns the price level the order is in.
+    #[inline]
+    pub fn price(&self) -> Amount {
+        self.price
+    }
+}
+
+/// The displayed quantity of the price levels of an [`Orderbook`], best prices first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Depth {
+    /// the price and quantity of the bid levels, highest price first.
+    pub bids: Vec<(Amount, u64)>,
+    /// the price and quantity of the ask levels, lowest price first.
+    pub asks: Vec<(Amount, u64)>,
+}
+
+/// A resting order as it is shown to the public, without its owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BookOrder {
+    /// the unique identifier of the order.
+    pub order_uuid: OrderUuid,
+    /// the price of the order.
+    pub price: Amount,
+    /// the displayed quantity of the order.
+    pub quantity: Amount,
+}
+
+/// The orders in the best price levels of an [`Orderbook`], in the order they are matched.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookOrders {
+    /// the bids, highest price first.
+    pub bids: Vec<BookOrder>,
+    /// the asks, lowest price first.
+    pub asks: Vec<BookOrder>,
+}
+
+impl BookOrders {
+    /// the orders aggregated into price levels.
+    pub fn depth(&self) -> Depth {
+        fn levels(orders: &[BookOrder]) -> Vec<(Amount, u64)> {
+            let mut levels: Vec<(Amount, u64)> = vec![];
+            for order in orders {
+                match levels.last_mut() {
+                    Some((price, quantity)) if *price == order.price => {
+                        *quantity += order.quantity.get()
+                    }
+                    _ => levels.push((order.price, order.quantity.get())),
+                }
+            }
+            levels
+        }
+
+        Depth {
+            bids: levels(&self.bids),
+            asks: levels(&self.asks),
+        }
+    }
+}
+
 /// The orderbook.
 pub struct Orderbook {
     /// The bids in the orderbook.
@@ -312,6 +473,18 @@ impl Orderbook {
         }
     }
 
+    /// get a reference to an order in the orderbook, returns `None` if the order does not exist.
+    pub fn get(&self, order_index: OrderIndex) -> Option<&Order> {
+        let OrderIndex { side, price, memo } = order_index;
+
+        let levels = match side {
+            OrderSide::Buy => &self.bids,
+            OrderSide::Sell => &self.asks,
+        };
+
+        levels.get((price, memo))
+    }
+
     /// get a mutable reference to an order in the orderbook, returns `None` if the order does not exist.
     #[inline]
     #[track_caller]
@@ -325,4 +498,112 @@ impl Orderbook {
 
         levels.get_mut((price, memo))
     }
+
+    /// the displayed quantity of the best `levels` price levels of each side.
+    pub fn depth(&self, levels: usize) -> Depth {
+        fn side<'a>(
+            iter: impl Iterator<Item = &'a PriceLevel>,
+            levels: usize,
+        ) -> Vec<(Amount, u64)> {
+            iter.take(levels)
+                .map(|level| {
+                    let price =
+                        Amount::new(level.price).expect("price for price-level should not be zero");
+                    (price, level.iter().map(|order| order.quantity.get()).sum())
+                })
+                .collect()
+        }
+
+        Depth {
+            bids: side(self.bids.iter_inner_rev(), levels),
+            asks: side(self.asks.iter_inner(), levels),
+        }
+    }
+
+    /// the orders of the best `levels` price levels of each side, hidden quantities of iceberg
+    /// orders are left out.
+    pub fn book_orders(&self, levels: usize) -> BookOrders {
+        let side = |side| {
+            let mut prices = 0;
+            let mut last_price = None;
+
+            self.iter_rel(side)
+                .take_while(|(_, order)| {
+                    if last_price != Some(order.price) {
+                        last_price = Some(order.price);
+                        prices += 1;
+                    }
+                    prices <= levels
+                })
+                .map(|(_, order)| BookOrder {
+                    order_uuid: order.order_uuid,
+                    price: order.price,
+                    quantity: order.quantity,
+                })
+                .collect()
+        };
+
+        BookOrders {
+            bids: side(OrderSide::Buy),
+            asks: side(OrderSide::Sell),
+        }
+    }
+
+    /// the displayed quantity of every order in a price level, zero if there is no such level.
+    pub fn level_quantity(&self, side: OrderSide, price: Amount) -> u64 {
+        let levels = match side {
+            OrderSide::Buy => &self.bids,
+            OrderSide::Sell => &self.asks,
+        };
+
+        levels
+            .inner
+            .binary_search_by_key(&price.get(), |level| level.price)
+            .map_or(0, |index| {
+                levels.inner[index]
+                    .iter()
+                    .map(|order| order.quantity.get())
+                    .sum()
+            })
+    }
+}
+
+impl Orderbook {
+    /// copy the price levels of the orderbook, including the sequence numbers of the next orders.
+    pub(super) fn snapshot(&self) -> OrderbookSnapshot {
+        let levels = |levels: &MultiplePriceLevels| {
+            levels
+                .iter_inner()
+                .map(|level| PriceLevelSnapshot {
+                    price: level.price,
+                    memo_seq: level.memo_seq,
+                    orders: level.iter().copied().collect(),
+                })
+                .collect()
+        };
+
+        OrderbookSnapshot {
+            bids: levels(&self.bids),
+            asks: levels(&self.asks),
+        }
+    }
+
+    /// rebuild an orderbook from a snapshot, every [`OrderIndex`] of the original stays valid.
+    pub(super) fn restore(snapshot: OrderbookSnapshot) -> Self {
+        let levels = |levels: Vec<PriceLevelSnapshot>| MultiplePriceLevels {
+            inner: levels
+                .into_iter()
+                .map(|level| PriceLevel {
+                    price: level.price,
+                    memo_seq: level.memo_seq,
+                    inner: level.orders.into_iter().map(Some).collect(),
+                })
+                .collect(),
+        };
+
+        Self {
+            bids: levels(snapshot.bids),
+            asks: levels(snapshot.asks),
+        }
+    }
 }
@@ -1,7 +1,5 @@
 //! Pending fill operations on the [`Orderbook`].
 
-use std::num::NonZeroU32;
-
 use thiserror::Error;
 
 use super::*;
@@ -32,7 +30,49 @@ pub struct MakerFill {
     pub oix: OrderIndex,
     pub maker: Order,
     pub fill_type: FillType,
-    pub fill_amount: u32,
+    pub fill_amount: u64,
+}
+
+/// a resting order (or part of it) that is cancelled by [`SelfTradeProtection`] instead of being filled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub struct SelfTradeCancel {
+    pub oix: OrderIndex,
+    pub maker: Order,
+    /// the quantity of the maker order that is cancelled, the order is removed if this is its entire quantity.
+    pub cancel_amount: u64,
+}
+
+/// The result of committing a [`PendingFill`].
+#[derive(Debug)]
+pub struct CommittedFill {
+    /// The outcome of the fill operation for the taker's order.
+    pub taker_fill_outcome: FillType,
+    /// The unfilled remainder of the taker's order, `None` if nothing is left.
+    pub taker_remaining: Option<Order>,
+    /// The maker orders that were filled.
+    pub maker_fills: Vec<MakerFill>,
+    /// The maker orders that were cancelled by self-trade protection.
+    pub self_trade_cancels: Vec<SelfTradeCancel>,
+    /// The iceberg orders whose displayed slice was filled and that show their next slice at
+    /// the back of their price level, under a new [`OrderIndex`].
+    pub replenished: Vec<(OrderIndex, OrderUuid)>,
+}
+
+impl CommittedFill {
+    /// Append the fill of the remainder of the taker's order, committed after this one.
+    pub fn chain(mut self, next: CommittedFill) -> CommittedFill {
+        self.taker_fill_outcome = match next.taker_fill_outcome {
+            FillType::Complete => FillType::Complete,
+            FillType::None => self.taker_fill_outcome,
+            FillType::Partial => FillType::Partial,
+        };
+        self.taker_remaining = next.taker_remaining;
+        self.maker_fills.extend(next.maker_fills);
+        self.self_trade_cancels.extend(next.self_trade_cancels);
+        self.replenished.extend(next.replenished);
+        self
+    }
 }
 
 /// A pending fill operation on the [`Orderbook`].
@@ -49,6 +89,10 @@ pub struct PendingFill<'a> {
     pub(super) maker_fills: Vec<MakerFill>,
     /// The outcome of the fill operation for the taker's order.
     pub(super) taker_fill_outcome: FillType,
+    /// The maker orders that are cancelled by self-trade protection.
+    pub(super) self_trade_cancels: Vec<SelfTradeCancel>,
+    /// The quantity of the taker's order that is cancelled by self-trade protection.
+    pub(super) taker_self_trade_cancelled: u64,
 }
 
 impl<'a> PendingFill<'a> {
@@ -60,6 +104,8 @@ impl<'a> PendingFill<'a> {
         order_type: OrderType,
         maker_fills: Vec<MakerFill>,
         taker_fill_outcome: FillType,
+        self_trade_cancels: Vec<SelfTradeCancel>,
+        taker_self_trade_cancelled: u64,
     ) -> Self {
         Self {
             orderbook,
@@ -68,6 +114,8 @@ impl<'a> PendingFill<'a> {
             order_type,
             maker_fills,
             taker_fill_outcome,
+            self_trade_cancels,
+            taker_self_trade_cancelled,
         }
     }
 
@@ -86,27 +134,47 @@ impl<'a> PendingFill<'a> {
         self.taker_fill_outcome
     }
 
+    /// Returns the maker orders that would be filled.
+    pub fn maker_fills(&self) -> &[MakerFill] {
+        &self.maker_fills
+    }
+
+    /// Returns the quantity of the taker's order that would be left, neither filled nor cancelled
+    /// by self-trade protection.
+    pub fn taker_remaining_quantity(&self) -> u64 {
+        let filled = self
+            .maker_fills
+            .iter()
+            .map(|fill| fill.fill_amount)
+            .sum::<u64>();
+        self.taker.quantity.get() - self.taker_self_trade_cancelled - filled
+    }
+
     /// Abort the pending fill operation.
     pub fn abort(self) {
         // Do nothing and drop the reference to the orderbook.
     }
 
     /// Execute the pending fill operation.
-    pub fn commit(self) -> Result<(FillType, Option<Order>), ExecutePendingFillError> {
-        let mut taker_order_remaining_quantity = self.taker.quantity.get();
+    pub fn commit(self) -> Result<CommittedFill, ExecutePendingFillError> {
+        let mut taker_order_remaining_quantity =
+            self.taker.quantity.get() - self.taker_self_trade_cancelled;
+        let mut replenished = vec![];
 
-        for fill in &self.maker_fills {
-            if self.orderbook.get_mut(fill.oix).is_none() {
-                return Err(ExecutePendingFillError::InvalidOrderIndex(fill.oix));
+        let oixs = self.maker_fills.iter().map(|fill| fill.oix);
+        let oixs = oixs.chain(self.self_trade_cancels.iter().map(|cancel| cancel.oix));
+        for oix in oixs {
+            if self.orderbook.get_mut(oix).is_none() {
+                return Err(ExecutePendingFillError::InvalidOrderIndex(oix));
             }
         }
 
-        for MakerFill {
+        for &MakerFill {
             oix,
             maker: order,
             fill_type,
-            ..
-        } in self.maker_fills
+            fill_amount,
+        } in &self.maker_fills
         {
             match fill_type {
                 // complete fill for a maker order.
@@ -118,40 +186,85 @@ impl<'a> PendingFill<'a> {
                     assert_eq!(maker_order, order);
                     // if this also filled the taker order, then we wont loop again.
                     taker_order_remaining_quantity -= maker_order.quantity.get();
+
+                    // iceberg orders show their next slice, losing time priority.
+                    if let Some(slice) = maker_order.replenished() {
+                        let oix = match oix.side() {
+                            OrderSide::Buy => self.orderbook.push_bid(slice),
+                            OrderSide::Sell => self.orderbook.push_ask(slice),
+                        };
+                        replenished.push((oix, slice.order_uuid));
+                    }
                 }
-                // partial fill for a maker order also means a complete fill for the taker order.
+                // partial fill for a maker order means the taker order ran out of quantity, or of quote budget.
                 FillType::Partial => {
                     let maker_order = self
                         .orderbook
                         .get_mut(oix)
                         .ok_or(ExecutePendingFillError::InvalidOrderIndex(oix))?; // this should never fail because we already checked that the order exists.
                     assert_eq!(*maker_order, order);
-                    assert!(taker_order_remaining_quantity < maker_order.quantity.get());
+                    assert!(taker_order_remaining_quantity >= fill_amount);
+                    assert!(fill_amount < maker_order.quantity.get());
                     maker_order.quantity =
-                    NonZeroU32::new(maker_order.quantity.get() - taker_order_remaining_quantity).expect("partial fills of maker orders will always have a quantity greater than zero");
-                    taker_order_remaining_quantity = 0;
+                    Amount::new(maker_order.quantity.get() - fill_amount).expect("partial fills of maker orders will always have a quantity greater than zero");
+                    taker_order_remaining_quantity -= fill_amount;
                 }
                 FillType::None => unreachable!(),
             }
         }
 
+        for &SelfTradeCancel {
+            oix,
+            maker: order,
+            cancel_amount,
+        } in &self.self_trade_cancels
+        {
+            if cancel_amount == order.quantity.get() {
+                let maker_order = self
+                    .orderbook
+                    .remove(oix)
+                    .ok_or(ExecutePendingFillError::InvalidOrderIndex(oix))?;
+                assert_eq!(maker_order, order);
+            } else {
+                let maker_order = self
+                    .orderbook
+                    .get_mut(oix)
+                    .ok_or(ExecutePendingFillError::InvalidOrderIndex(oix))?;
+                assert_eq!(*maker_order, order);
+                maker_order.quantity = Amount::new(maker_order.quantity.get() - cancel_amount)
+                    .expect("decreased maker orders will always have a quantity greater than zero");
+            }
+        }
+
         match self.taker_fill_outcome {
             FillType::Complete => assert_eq!(taker_order_remaining_quantity, 0),
             FillType::Partial => {
-                assert!(self.taker.quantity.get() > taker_order_remaining_quantity)
+                assert!(
+                    self.taker.quantity.get() - self.taker_self_trade_cancelled
+                        > taker_order_remaining_quantity
+                )
             }
-            FillType::None => assert_eq!(taker_order_remaining_quantity, self.taker.quantity.get()),
+            FillType::None => assert_eq!(
+                taker_order_remaining_quantity,
+                self.taker.quantity.get() - self.taker_self_trade_cancelled
+            ),
         }
 
-        let taker_order = if let Some(quantity) = NonZeroU32::new(taker_order_remaining_quantity) {
+        let taker_order = if let Some(quantity) = Amount::new(taker_order_remaining_quantity) {
             let mut taker_order = self.taker;
             taker_order.quantity = quantity;
             Some(taker_order)
         } else {
-            // the taker order was completely filled.
+            // the taker order was completely filled (or cancelled by self-trade protection).
             None
         };
 
-        Ok((self.taker_fill_outcome, taker_order))
+        Ok(CommittedFill {
+            taker_fill_outcome: self.taker_fill_outcome,
+            taker_remaining: taker_order,
+            maker_fills: self.maker_fills,
+            self_trade_cancels: self.self_trade_cancels,
+            replenished,
+        })
     }
 }
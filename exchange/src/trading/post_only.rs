@@ -0,0 +1,16 @@
+//! Post-only (maker-only) handling of an order.
+
+use serde::{Deserialize, Serialize};
+
+/// What happens to a post-only order that would take liquidity when it is placed.
+///
+/// Post-only orders never fill against resting orders, they are only ever the maker of a trade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PostOnly {
+    /// Reject. The order is rejected, nothing is placed.
+    #[serde(rename = "reject")]
+    Reject,
+    /// Reprice. The order is moved one tick away from the best opposite price and placed there.
+    #[serde(rename = "reprice")]
+    Reprice,
+}
@@ -0,0 +1,321 @@
+//! Offline replays of the event log.
+//!
+//! A replay applies the logged commands to fresh [`Assets`] exactly like the trading engine does
+//! while bootstrapping, without a database or a running engine. Commands issued by the engine
+//! itself (triggering stop orders, cancelling linked orders) are checked against what the replayed
+//! state says the engine should have issued at that point, any mismatch is reported as a
+//! [`Divergence`]. Two replays of the same log always end with the same [`AssetsSnapshot::digest`].
+
+use sha2::{Digest as _, Sha256};
+use thiserror::Error;
+
+use super::{
+    do_amend_order, do_cancel_all, do_cancel_oco_siblings, do_cancel_order, do_expire_orders,
+    do_place_oco, do_place_order, do_trigger_stops, Assets, AssetsSnapshot, MarketRules,
+    TradeCmdPayload, TradingEngineError,
+};
+use crate::Market;
+
+/// A difference between the logged events and what the replay says the trading engine did.
+#[derive(Debug, Error)]
+pub enum Divergence {
+    /// the engine should have logged a follow-up command before this event.
+    #[error("event {event_id}: expected the engine to log {expected} before {logged}")]
+    MissingFollowUp {
+        /// the event logged instead of the follow-up.
+        event_id: i64,
+        /// the follow-up that is missing.
+        expected: &'static str,
+        /// the command that was logged instead.
+        logged: &'static str,
+    },
+    /// a follow-up command was logged that does not match the replayed state.
+    #[error("event {event_id}: {logged} was logged but the engine had no reason to issue it")]
+    UnexpectedFollowUp {
+        /// the unexpected event.
+        event_id: i64,
+        /// the logged follow-up.
+        logged: &'static str,
+    },
+    /// a follow-up command failed to replay.
+    #[error("event {event_id}: replaying {logged} failed: {error}")]
+    FollowUpFailed {
+        /// the failed event.
+        event_id: i64,
+        /// the logged follow-up.
+        logged: &'static str,
+        /// why it failed.
+        error: TradingEngineError,
+    },
+    /// a stored snapshot does not match the replayed state at the same event.
+    #[error("event {event_id}: the snapshot digest {recorded} does not match the replayed digest {replayed}")]
+    Snapshot {
+        /// the last event applied to the snapshot.
+        event_id: i64,
+        /// the digest of the stored snapshot.
+        recorded: String,
+        /// the digest of the replayed state.
+        replayed: String,
+    },
+}
+
+/// The state of an offline replay of the event log.
+pub struct Replay {
+    assets: Assets,
+    /// list markets with the default rules when an event names a market that is not traded.
+    list_any_market: bool,
+    /// the id of the last applied event.
+    last_event_id: i64,
+    /// the number of applied events.
+    events: u64,
+    /// the number of events the engine rejected, e.g. orders without the liquidity to fill.
+    rejected: u64,
+    divergences: Vec<Divergence>,
+}
+
+impl Replay {
+    /// replay events of orders in `markets`.
+    pub fn new(markets: impl IntoIterator<Item = (Market, MarketRules)>) -> Self {
+        let mut assets = Assets::new();
+        for (market, rules) in markets {
+            assets.list_market(market, rules);
+        }
+
+        Self {
+            assets,
+            list_any_market: false,
+            last_event_id: 0,
+            events: 0,
+            rejected: 0,
+            divergences: vec![],
+        }
+    }
+
+    /// replay events of orders in any market, each accepting any price and quantity.
+    ///
+    /// used when the rules of the markets are not known, e.g. for an exported log. post-only
+    /// orders are repriced by the tick size so their outcome may differ from the live engine.
+    pub fn with_any_market() -> Self {
+        Self {
+            list_any_market: true,
+            ..Self::new([])
+        }
+    }
+
+    /// apply the event `event_id` with the logged `payload`.
+    pub fn apply(&mut self, event_id: i64, payload: TradeCmdPayload) {
+        let logged = command_name(&payload);
+        let follow_up = matches!(
+            payload,
+            TradeCmdPayload::TriggerStops(_) | TradeCmdPayload::CancelOcoSiblings(_)
+        );
+
+        // follow-ups are logged right after the event that caused them, linked orders first.
+        let expected = match &payload {
+            TradeCmdPayload::CancelOcoSiblings(cancel_siblings) => {
+                self.assets.next_oco_cancels().as_ref() == Some(cancel_siblings)
+            }
+            TradeCmdPayload::TriggerStops(trigger_stops) => {
+                self.assets.next_oco_cancels().is_none()
+                    && self.assets.next_triggered_stops().as_ref() == Some(trigger_stops)
+            }
+            _ => true,
+        };
+
+        if follow_up && !expected {
+            self.divergences
+                .push(Divergence::UnexpectedFollowUp { event_id, logged });
+        } else if let Some(expected) = self.pending_follow_up().filter(|_| !follow_up) {
+            self.divergences.push(Divergence::MissingFollowUp {
+                event_id,
+                expected,
+                logged,
+            });
+        }
+
+        if self.list_any_market {
+            if let Some(market) = payload_market(&payload) {
+                self.assets.list_market(market, MarketRules::default());
+            }
+        }
+
+        let assets = &mut self.assets;
+        let result = match payload {
+            TradeCmdPayload::PlaceOrder(place_order) => {
+                do_place_order(assets, place_order).map(drop)
+            }
+            TradeCmdPayload::AmendOrder(amend_order) => {
+                do_amend_order(assets, amend_order).map(drop)
+            }
+            TradeCmdPayload::CancelOrder(cancel_order) => {
+                do_cancel_order(assets, cancel_order).map(drop)
+            }
+            TradeCmdPayload::ExpireOrders(expire_orders) => {
+                do_expire_orders(assets, expire_orders).map(drop)
+            }
+            TradeCmdPayload::TriggerStops(trigger_stops) => {
+                do_trigger_stops(assets, trigger_stops).map(drop)
+            }
+            TradeCmdPayload::PlaceOco(place_oco) => do_place_oco(assets, place_oco).map(drop),
+            TradeCmdPayload::CancelOcoSiblings(cancel_siblings) => {
+                do_cancel_oco_siblings(assets, cancel_siblings).map(drop)
+            }
+            TradeCmdPayload::CancelAll(cancel_all) => do_cancel_all(assets, cancel_all).map(drop),
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(error) if follow_up => self.divergences.push(Divergence::FollowUpFailed {
+                event_id,
+                logged,
+                error,
+            }),
+            Err(_) => self.rejected += 1,
+        }
+
+        self.last_event_id = event_id;
+        self.events += 1;
+    }
+
+    /// compare a snapshot stored after the last applied event with the replayed state.
+    pub fn check_snapshot(&mut self, snapshot: &AssetsSnapshot) {
+        let recorded = snapshot.digest();
+        let replayed = self.digest();
+
+        if recorded != replayed {
+            self.divergences.push(Divergence::Snapshot {
+                event_id: self.last_event_id,
+                recorded,
+                replayed,
+            });
+        }
+    }
+
+    /// finish the replay, the engine should have logged every follow-up of the last event.
+    pub fn finish(mut self) -> ReplayReport {
+        if let Some(expected) = self.pending_follow_up() {
+            self.divergences.push(Divergence::MissingFollowUp {
+                event_id: self.last_event_id,
+                expected,
+                logged: "nothing",
+            });
+        }
+
+        ReplayReport {
+            digest: self.digest(),
+            last_event_id: self.last_event_id,
+            events: self.events,
+            rejected: self.rejected,
+            divergences: self.divergences,
+            assets: self.assets,
+        }
+    }
+
+    /// the replayed state.
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    /// the digest of the replayed state.
+    pub fn digest(&self) -> String {
+        self.assets.snapshot().digest()
+    }
+
+    /// the follow-up the engine has to log next, if there is one.
+    fn pending_follow_up(&self) -> Option<&'static str> {
+        if self.assets.next_oco_cancels().is_some() {
+            Some("a cancellation of linked orders")
+        } else if self.assets.next_triggered_stops().is_some() {
+            Some("a trigger of stop orders")
+        } else {
+            None
+        }
+    }
+}
+
+/// The outcome of a finished [`Replay`].
+pub struct ReplayReport {
+    /// the digest of the replayed state, see [`AssetsSnapshot::digest`].
+    pub digest: String,
+    /// the id of the last applied event.
+    pub last_event_id: i64,
+    /// the number of applied events.
+    pub events: u64,
+    /// the number of events the engine rejected.
+    pub rejected: u64,
+    /// every difference between the log and the replay.
+    pub divergences: Vec<Divergence>,
+    /// the replayed state.
+    pub assets: Assets,
+}
+
+impl AssetsSnapshot {
+    /// a SHA-256 digest of the snapshot as hex.
+    ///
+    /// entries of maps are sorted first so equal states have equal digests no matter the order
+    /// the maps were iterated in.
+    pub fn digest(&self) -> String {
+        let mut order_uuids = self.order_uuids.clone();
+        order_uuids.sort_by_key(|&(order_uuid, ..)| order_uuid);
+
+        let mut user_orders = self.user_orders.clone();
+        if let Some(user_orders) = &mut user_orders {
+            user_orders.sort_by_key(|&(_, order_uuid, _)| order_uuid);
+        }
+
+        let mut stop_uuids = self.stop_uuids.clone();
+        stop_uuids.sort_by_key(|&(order_uuid, _)| order_uuid);
+
+        let mut expiries = self.expiries.clone();
+        expiries.sort();
+
+        let mut oco_groups = self.oco_groups.clone();
+        oco_groups.sort_by_key(|&(group_uuid, _)| group_uuid.0);
+
+        let mut oco_legs = self.oco_legs.clone();
+        oco_legs.sort_by_key(|&(order_uuid, _)| order_uuid);
+
+        let sorted = AssetsSnapshot {
+            books: vec![],
+            order_uuids,
+            user_orders,
+            stop_uuids,
+            expiries,
+            oco_groups,
+            oco_legs,
+            oco_cancels: self.oco_cancels.clone(),
+        };
+
+        let mut hasher = Sha256::new();
+        for book in &self.books {
+            hasher.update(rmp_serde::to_vec(book).expect("snapshots are always serializable"));
+        }
+        hasher.update(rmp_serde::to_vec(&sorted).expect("snapshots are always serializable"));
+
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// the name of a logged command for reports.
+fn command_name(payload: &TradeCmdPayload) -> &'static str {
+    match payload {
+        TradeCmdPayload::PlaceOrder(_) => "an order",
+        TradeCmdPayload::AmendOrder(_) => "an amendment",
+        TradeCmdPayload::CancelOrder(_) => "a cancellation",
+        TradeCmdPayload::ExpireOrders(_) => "an expiry of orders",
+        TradeCmdPayload::TriggerStops(_) => "a trigger of stop orders",
+        TradeCmdPayload::PlaceOco(_) => "linked orders",
+        TradeCmdPayload::CancelOcoSiblings(_) => "a cancellation of linked orders",
+        TradeCmdPayload::CancelAll(_) => "a cancellation of every order",
+    }
+}
+
+/// the market a logged command places orders in.
+fn payload_market(payload: &TradeCmdPayload) -> Option<Market> {
+    match payload {
+        TradeCmdPayload::PlaceOrder(place_order) => Some(place_order.market),
+        TradeCmdPayload::PlaceOco(place_oco) => Some(place_oco.legs[0].market),
+        TradeCmdPayload::AmendOrder(amend_order) => Some(amend_order.market),
+        _ => None,
+    }
+}
@@ -3,18 +3,20 @@
 use serde::{Deserialize, Serialize};
 
 /// The self-trade protection of an order.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+///
+/// Applied by the taker's order when it would match against a resting order of the same owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SelfTradeProtection {
-    /// Decrease and cancel.
+    /// Decrease and cancel. The smaller of the two orders is cancelled and the larger is decreased by the same quantity, if they are the same size both are cancelled.
     #[serde(rename = "dc")]
     DecreaseCancel,
-    /// Cancel oldest.
+    /// Cancel oldest. The resting order is cancelled and matching continues.
     #[serde(rename = "co")]
     CancelOldest,
-    /// Cancel newest.
+    /// Cancel newest. The remainder of the taker's order is cancelled and matching stops.
     #[serde(rename = "cn")]
     CancelNewest,
-    /// Cancel both.
+    /// Cancel both. The resting order and the remainder of the taker's order are cancelled.
     #[serde(rename = "cb")]
     CancelBoth,
 }
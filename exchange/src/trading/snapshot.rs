@@ -0,0 +1,202 @@
+//! Snapshots of the trading engine state.
+//!
+//! Replaying the whole event log on every start gets slower as the log grows. Instead the engine
+//! periodically stores a snapshot of its [`Assets`] tagged with the id of the last event applied
+//! to them, on startup the newest snapshot is restored and only the events after it are replayed.
+
+use std::cmp::Reverse;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    AssetBook, Assets, MarketRules, OcoGroupUuid, Order, OrderIndex, OrderUuid, PlaceOrder,
+    RestingOrder, TimeInForce,
+};
+use crate::{Amount, Market};
+
+/// The state of every asset book of a trading engine.
+///
+/// Maps are stored as lists of their entries, lists that are not in the order the engine keeps them
+/// in (like the `expiries` heap) are put back in order when the snapshot is restored.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetsSnapshot {
+    /// the asset book of every traded market.
+    pub books: Vec<AssetBookSnapshot>,
+    /// the orders resting in an orderbook.
+    pub order_uuids: Vec<(OrderUuid, OrderIndex, Market)>,
+    /// the resting orders of every user, snapshots taken before they were kept have none and
+    /// rebuild them from the orderbooks.
+    #[serde(default)]
+    pub user_orders: Option<Vec<(uuid::Uuid, OrderUuid, RestingOrder)>>,
+    /// the stop orders that have not been triggered yet.
+    pub stop_uuids: Vec<(OrderUuid, Market)>,
+    /// deadlines of good-til-date orders.
+    pub expiries: Vec<(u64, OrderUuid)>,
+    /// one-cancels-the-other groups while both of their orders are live.
+    pub oco_groups: Vec<(OcoGroupUuid, [OrderUuid; 2])>,
+    /// the group of each linked order.
+    pub oco_legs: Vec<(OrderUuid, OcoGroupUuid)>,
+    /// linked orders waiting to be cancelled.
+    pub oco_cancels: Vec<OrderUuid>,
+}
+
+/// The state of the asset book of a single market.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetBookSnapshot {
+    /// the market of the asset book.
+    pub market: Market,
+    /// the prices and quantities the market accepts.
+    pub rules: MarketRules,
+    /// the resting orders.
+    pub orderbook: OrderbookSnapshot,
+    /// the stop orders that have not been triggered yet.
+    pub stops: StopBookSnapshot,
+    /// the price of the last trade.
+    pub last_price: Option<Amount>,
+}
+
+/// The price levels of an [`Orderbook`](super::Orderbook), lowest price first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderbookSnapshot {
+    /// the price levels of the buy side.
+    pub bids: Vec<PriceLevelSnapshot>,
+    /// the price levels of the sell side.
+    pub asks: Vec<PriceLevelSnapshot>,
+}
+
+/// The orders of a price level in time priority.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PriceLevelSnapshot {
+    /// the price of the orders in the price level.
+    pub price: u64,
+    /// the sequence number of the next order added to the price level.
+    pub memo_seq: u32,
+    /// the orders, oldest first.
+    pub orders: Vec<Order>,
+}
+
+/// The stop orders of a [`StopBook`](super::StopBook).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StopBookSnapshot {
+    /// the stop orders with their stop price and sequence number.
+    pub stops: Vec<(Amount, u64, PlaceOrder)>,
+    /// the sequence number of the next stop order.
+    pub seq: u64,
+}
+
+impl AssetsSnapshot {
+    /// encode the snapshot as MessagePack.
+    pub fn encode(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    /// decode a snapshot encoded with [`AssetsSnapshot::encode`].
+    pub fn decode(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(bytes)
+    }
+}
+
+impl Assets {
+    /// copy the state of every asset book.
+    pub fn snapshot(&self) -> AssetsSnapshot {
+        AssetsSnapshot {
+            books: self
+                .books
+                .values()
+                .map(|book| AssetBookSnapshot {
+                    market: book.market,
+                    rules: book.rules,
+                    orderbook: book.orderbook.snapshot(),
+                    stops: book.stops.snapshot(),
+                    last_price: book.last_price,
+                })
+                .collect(),
+            order_uuids: self
+                .order_uuids
+                .iter()
+                .map(|(&order_uuid, &(order_index, market))| (order_uuid, order_index, market))
+                .collect(),
+            user_orders: Some(
+                self.user_orders
+                    .iter()
+                    .flat_map(|(&owner, orders)| {
+                        orders
+                            .iter()
+                            .map(move |(&order_uuid, &resting)| (owner, order_uuid, resting))
+                    })
+                    .collect(),
+            ),
+            stop_uuids: self.stop_uuids.iter().map(|(&k, &v)| (k, v)).collect(),
+            expiries: self
+                .expiries
+                .iter()
+                .map(|&Reverse(expiry)| expiry)
+                .collect(),
+            oco_groups: self.oco_groups.iter().map(|(&k, &v)| (k, v)).collect(),
+            oco_legs: self.oco_legs.iter().map(|(&k, &v)| (k, v)).collect(),
+            oco_cancels: self.oco_cancels.clone(),
+        }
+    }
+
+    /// rebuild the state of every asset book from a snapshot.
+    pub fn restore(snapshot: AssetsSnapshot) -> Self {
+        let mut assets = Self {
+            order_uuids: snapshot
+                .order_uuids
+                .into_iter()
+                .map(|(order_uuid, order_index, market)| (order_uuid, (order_index, market)))
+                .collect(),
+            user_orders: Default::default(),
+            stop_uuids: snapshot.stop_uuids.into_iter().collect(),
+            expiries: snapshot.expiries.into_iter().map(Reverse).collect(),
+            oco_groups: snapshot.oco_groups.into_iter().collect(),
+            oco_legs: snapshot.oco_legs.into_iter().collect(),
+            oco_cancels: snapshot.oco_cancels,
+            books: snapshot
+                .books
+                .into_iter()
+                .map(|book| {
+                    let asset_book = AssetBook {
+                        market: book.market,
+                        rules: book.rules,
+                        orderbook: Box::new(super::Orderbook::restore(book.orderbook)),
+                        stops: super::StopBook::restore(book.stops),
+                        last_price: book.last_price,
+                    };
+                    (book.market, asset_book)
+                })
+                .collect(),
+        };
+
+        let user_orders = match snapshot.user_orders {
+            Some(user_orders) => user_orders,
+            None => assets.resting_orders(),
+        };
+        for (owner, order_uuid, resting) in user_orders {
+            assets.track_user_order(owner, order_uuid, resting);
+        }
+
+        assets
+    }
+
+    /// the orders resting in the orderbooks with what can be told from the orderbooks alone, what
+    /// they were placed with and when is not known.
+    fn resting_orders(&self) -> Vec<(uuid::Uuid, OrderUuid, RestingOrder)> {
+        self.order_uuids
+            .iter()
+            .filter_map(|(&order_uuid, &(order_index, market))| {
+                let order = self.books.get(&market)?.orderbook().get(order_index)?;
+                let time_in_force = match order.expire_at {
+                    Some(_) => TimeInForce::GoodTilDate,
+                    None => TimeInForce::GoodTilCanceled,
+                };
+                let resting = RestingOrder {
+                    quantity: order.total_quantity(),
+                    time_in_force,
+                    created_at: None,
+                };
+                Some((order.owner, order_uuid, resting))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,135 @@
+//! Stop orders waiting for the last trade price to cross their stop price.
+
+use std::collections::BTreeMap;
+
+use super::snapshot::StopBookSnapshot;
+use super::{OrderSide, OrderUuid, PlaceOrder};
+use crate::Amount;
+
+/// The stop orders of a single market.
+///
+/// Stop orders are kept out of the [`Orderbook`](super::Orderbook) until they are triggered, at
+/// which point they are placed as the order type they stand in for (see [`OrderType::triggered`](super::OrderType::triggered)).
+#[derive(Debug, Default)]
+pub struct StopBook {
+    /// buy stops, triggered when the last trade price rises to or above their stop price.
+    buys: BTreeMap<(Amount, u64), PlaceOrder>,
+    /// sell stops, triggered when the last trade price falls to or below their stop price.
+    sells: BTreeMap<(Amount, u64), PlaceOrder>,
+    /// where each stop order is kept in `buys` or `sells`.
+    index: ahash::AHashMap<OrderUuid, (OrderSide, Amount, u64)>,
+    /// the sequence number of the next stop order, keeps stops with the same stop price in arrival order.
+    seq: u64,
+}
+
+impl StopBook {
+    /// add a stop order that triggers at `stop_price`.
+    pub fn insert(&mut self, stop_price: Amount, place_order: PlaceOrder) {
+        let seq = self.seq;
+        self.seq += 1;
+
+        let side = place_order.side;
+        self.index
+            .insert(place_order.order_uuid, (side, stop_price, seq));
+
+        match side {
+            OrderSide::Buy => self.buys.insert((stop_price, seq), place_order),
+            OrderSide::Sell => self.sells.insert((stop_price, seq), place_order),
+        };
+    }
+
+    /// get a stop order that has not been triggered yet.
+    pub fn get(&self, order_uuid: &OrderUuid) -> Option<&PlaceOrder> {
+        let &(side, stop_price, seq) = self.index.get(order_uuid)?;
+
+        match side {
+            OrderSide::Buy => self.buys.get(&(stop_price, seq)),
+            OrderSide::Sell => self.sells.get(&(stop_price, seq)),
+        }
+    }
+
+    /// remove a stop order that has not been triggered yet.
+    pub fn remove(&mut self, order_uuid: &OrderUuid) -> Option<PlaceOrder> {
+        let (side, stop_price, seq) = self.index.remove(order_uuid)?;
+
+        match side {
+            OrderSide::Buy => self.buys.remove(&(stop_price, seq)),
+            OrderSide::Sell => self.sells.remove(&(stop_price, seq)),
+        }
+    }
+
+    /// whether a last trade price of `last_price` triggers any of the stop orders.
+    pub fn is_triggered(&self, last_price: Amount) -> bool {
+        let buy = self.buys.first_key_value();
+        let sell = self.sells.last_key_value();
+
+        matches!(buy, Some((&(stop_price, _), _)) if stop_price <= last_price)
+            || matches!(sell, Some((&(stop_price, _), _)) if stop_price >= last_price)
+    }
+
+    /// remove every stop order triggered by a last trade price of `last_price`, in the order they were placed.
+    pub fn take_triggered(&mut self, last_price: Amount) -> Vec<PlaceOrder> {
+        let mut triggered = vec![];
+
+        while let Some(entry) = self.buys.first_entry() {
+            if entry.key().0 > last_price {
+                break;
+            }
+            triggered.push((entry.key().1, entry.remove()));
+        }
+
+        while let Some(entry) = self.sells.last_entry() {
+            if entry.key().0 < last_price {
+                break;
+            }
+            triggered.push((entry.key().1, entry.remove()));
+        }
+
+        triggered.sort_by_key(|&(seq, _)| seq);
+
+        triggered
+            .into_iter()
+            .map(|(_, place_order)| {
+                self.index.remove(&place_order.order_uuid);
+                place_order
+            })
+            .collect()
+    }
+}
+
+impl StopBook {
+    /// copy the stop orders, including the sequence number of the next stop order.
+    pub(super) fn snapshot(&self) -> StopBookSnapshot {
+        StopBookSnapshot {
+            stops: self
+                .buys
+                .iter()
+                .chain(self.sells.iter())
+                .map(|(&(stop_price, seq), place_order)| (stop_price, seq, place_order.clone()))
+                .collect(),
+            seq: self.seq,
+        }
+    }
+
+    /// rebuild a stop book from a snapshot.
+    pub(super) fn restore(snapshot: StopBookSnapshot) -> Self {
+        let mut stop_book = Self {
+            seq: snapshot.seq,
+            ..Default::default()
+        };
+
+        for (stop_price, seq, place_order) in snapshot.stops {
+            let side = place_order.side;
+            stop_book
+                .index
+                .insert(place_order.order_uuid, (side, stop_price, seq));
+
+            match side {
+                OrderSide::Buy => stop_book.buys.insert((stop_price, seq), place_order),
+                OrderSide::Sell => stop_book.sells.insert((stop_price, seq), place_order),
+            };
+        }
+
+        stop_book
+    }
+}
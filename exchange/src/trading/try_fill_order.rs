@@ -2,29 +2,74 @@
 
 use std::convert::Infallible;
 
-use pending_fill::MakerFill;
+use pending_fill::{MakerFill, SelfTradeCancel};
 
 use super::*;
+use crate::amount;
 
 /// An error that can occur when attempting to fill orders.
 #[derive(Debug, thiserror::Error)]
 pub enum TryFillOrdersError {}
 
+/// Limits on how much of the order book a taker's order matches against, besides its quantity.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FillBounds {
+    /// resting orders priced above this (for buys) or below it (for sells) are not matched,
+    /// limit orders are always bound by their own price.
+    pub protection_price: Option<Amount>,
+    /// the most of the quote currency a buy spends on its fills.
+    pub quote_budget: Option<u64>,
+    /// what the budget buys is rounded down to a multiple of the market's lot size.
+    pub lot_size: Option<Amount>,
+    /// the decimal places of the base asset, prices are paid for one whole base asset.
+    pub base_decimals: u32,
+}
+
 /// Attempts to fill a taker's order against the current state of the order book.
 ///
 /// This function returns a [`PendingFill`] object that encapsulates the potential outcome
 /// of the fill operation. This allows you to review the potential outcome before committing
 /// to modifying the order book.
 ///
+/// Resting orders owned by the taker are never filled against, instead `stp` decides which
+/// of the orders are cancelled (see [`SelfTradeProtection`]).
+///
 pub fn try_fill_orders<'a>(
     orderbook: &'a mut Orderbook,
     taker: Order,
     side: OrderSide,
     order_type: OrderType,
+    stp: SelfTradeProtection,
+) -> Result<PendingFill<'a>, Infallible> {
+    try_fill_orders_bounded(
+        orderbook,
+        taker,
+        side,
+        order_type,
+        stp,
+        FillBounds::default(),
+    )
+}
+
+/// Like [`try_fill_orders`], but stops matching at the given [`FillBounds`].
+pub fn try_fill_orders_bounded<'a>(
+    orderbook: &'a mut Orderbook,
+    taker: Order,
+    side: OrderSide,
+    order_type: OrderType,
+    stp: SelfTradeProtection,
+    bounds: FillBounds,
 ) -> Result<PendingFill<'a>, Infallible> {
     let mut maker_fills = vec![];
-    let mut taker_fill_outcome = FillType::None;
+    let mut self_trade_cancels = vec![];
     let mut taker_rem_q = taker.quantity.get();
+    let mut taker_self_trade_cancelled = 0;
+    let mut quote_rem = bounds.quote_budget;
+
+    let price_bound = match order_type {
+        OrderType::Limit => Some(taker.price),
+        _ => bounds.protection_price,
+    };
 
     let maker_side = match side {
         OrderSide::Buy => OrderSide::Sell,
@@ -32,14 +77,60 @@ pub fn try_fill_orders<'a>(
     };
 
     for (oix, order) in orderbook.iter_rel(maker_side) {
-        if order_type == OrderType::Limit
-            && ((side == OrderSide::Buy && order.price > taker.price)
-                || (side == OrderSide::Sell && order.price < taker.price))
-        {
-            continue; // Skip orders that don't meet the price condition for limit orders
+        if let Some(bound) = price_bound {
+            if (side == OrderSide::Buy && order.price > bound)
+                || (side == OrderSide::Sell && order.price < bound)
+            {
+                continue; // Skip orders priced beyond the limit or protection price
+            }
+        }
+
+        if order.owner == taker.owner {
+            let (cancel_amount, taker_cancel_amount) = match stp {
+                SelfTradeProtection::CancelOldest => (order.quantity.get(), 0),
+                SelfTradeProtection::CancelNewest => (0, taker_rem_q),
+                SelfTradeProtection::CancelBoth => (order.quantity.get(), taker_rem_q),
+                SelfTradeProtection::DecreaseCancel => {
+                    let amount = std::cmp::min(order.quantity.get(), taker_rem_q);
+                    (amount, amount)
+                }
+            };
+
+            if cancel_amount > 0 {
+                self_trade_cancels.push(SelfTradeCancel {
+                    oix,
+                    maker: order,
+                    cancel_amount,
+                });
+            }
+
+            taker_self_trade_cancelled += taker_cancel_amount;
+            taker_rem_q -= taker_cancel_amount;
+
+            if taker_rem_q == 0 {
+                break;
+            } else {
+                continue;
+            }
+        }
+
+        let mut fill_amount = std::cmp::min(order.quantity.get(), taker_rem_q);
+
+        if let Some(quote_rem) = &mut quote_rem {
+            // only as much as the remaining budget buys at the maker's price.
+            let mut affordable =
+                amount::quantity_for(order.price, *quote_rem, bounds.base_decimals);
+            if let Some(lot_size) = bounds.lot_size {
+                affordable -= affordable % lot_size.get();
+            }
+            fill_amount = fill_amount.min(affordable);
+
+            if fill_amount == 0 {
+                break;
+            }
+            *quote_rem -= amount::notional(order.price, fill_amount, bounds.base_decimals);
         }
 
-        let fill_amount = std::cmp::min(order.quantity.get(), taker_rem_q);
         let fill_type = if fill_amount == order.quantity.get() {
             FillType::Complete
         } else {
@@ -53,19 +144,22 @@ pub fn try_fill_orders<'a>(
             fill_amount,
         });
 
-        if taker_rem_q == fill_amount {
-            taker_fill_outcome = FillType::Complete;
-            taker_rem_q = 0;
+        taker_rem_q -= fill_amount;
+
+        if taker_rem_q == 0 {
             break;
-        } else {
-            taker_fill_outcome = FillType::Partial;
-            taker_rem_q = taker_rem_q - fill_amount;
         }
     }
 
-    if taker_rem_q == taker.quantity.get() {
-        taker_fill_outcome = FillType::None;
-    }
+    let taker_filled_q = taker.quantity.get() - taker_rem_q - taker_self_trade_cancelled;
+
+    let taker_fill_outcome = if taker_filled_q == taker.quantity.get() {
+        FillType::Complete
+    } else if taker_filled_q > 0 {
+        FillType::Partial
+    } else {
+        FillType::None
+    };
 
     let pending_fill = PendingFill::new(
         orderbook,
@@ -74,6 +168,8 @@ pub fn try_fill_orders<'a>(
         order_type,
         maker_fills,
         taker_fill_outcome,
+        self_trade_cancels,
+        taker_self_trade_cancelled,
     );
 
     Ok(pending_fill)
@@ -85,7 +181,7 @@ mod tests {
 
     macro_rules! nz {
         ($e:literal) => {
-            ::std::num::NonZeroU32::new($e).unwrap()
+            crate::Amount::new($e).unwrap()
         };
     }
 
@@ -96,15 +192,31 @@ mod tests {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            owner: uuid::Uuid::nil(),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         });
 
         let taker = Order {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            owner: uuid::Uuid::from_u128(1),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         };
-        let result =
-            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        let result = try_fill_orders(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+        )
+        .unwrap();
         assert_eq!(result.taker_fill_outcome, FillType::Complete);
         assert_eq!(result.maker_fills.len(), 1);
         assert_eq!(result.maker_fills[0].fill_type, FillType::Complete);
@@ -117,15 +229,31 @@ mod tests {
             price: nz!(100),
             quantity: nz!(30),
             memo: 0,
+            owner: uuid::Uuid::nil(),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         });
         let taker = Order {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            owner: uuid::Uuid::from_u128(1),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         };
 
-        let result =
-            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        let result = try_fill_orders(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+        )
+        .unwrap();
         assert_eq!(result.taker_fill_outcome, FillType::Partial);
         assert_eq!(result.maker_fills.len(), 1);
         assert_eq!(result.maker_fills[0].fill_type, FillType::Complete);
@@ -138,15 +266,31 @@ mod tests {
             price: nz!(150),
             quantity: nz!(50),
             memo: 0,
+            owner: uuid::Uuid::nil(),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         });
         let taker = Order {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            owner: uuid::Uuid::from_u128(1),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         };
 
-        let result =
-            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        let result = try_fill_orders(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+        )
+        .unwrap();
         assert_eq!(result.taker_fill_outcome, FillType::None);
         assert_eq!(result.maker_fills.len(), 0);
     }
@@ -158,10 +302,21 @@ mod tests {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            owner: uuid::Uuid::from_u128(1),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         };
 
-        let result =
-            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        let result = try_fill_orders(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+        )
+        .unwrap();
         assert_eq!(result.taker_fill_outcome, FillType::None);
         assert_eq!(result.maker_fills.len(), 0);
     }
@@ -173,15 +328,31 @@ mod tests {
             price: nz!(150),
             quantity: nz!(50),
             memo: 0,
+            owner: uuid::Uuid::nil(),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         });
         let taker = Order {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            owner: uuid::Uuid::from_u128(1),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         };
 
-        let result =
-            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        let result = try_fill_orders(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+        )
+        .unwrap();
         assert_eq!(result.taker_fill_outcome, FillType::None);
         assert_eq!(result.maker_fills.len(), 0);
     }
@@ -195,26 +366,52 @@ mod tests {
             price: nz!(100),
             quantity: nz!(30),
             memo: 1,
+            owner: uuid::Uuid::nil(),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         });
         orderbook.push_ask(Order {
             price: nz!(105),
             quantity: nz!(20),
             memo: 2,
+            owner: uuid::Uuid::nil(),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         });
         orderbook.push_ask(Order {
             price: nz!(110),
             quantity: nz!(50),
             memo: 3,
+            owner: uuid::Uuid::nil(),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         });
 
         let taker = Order {
             price: nz!(110),   // Taker is willing to buy up to this price
             quantity: nz!(75), // Taker wants a total of 75 units
             memo: 4,
+            owner: uuid::Uuid::from_u128(1),
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
         };
 
-        let result =
-            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        let result = try_fill_orders(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+        )
+        .unwrap();
 
         // Assertions on overall outcome
         assert_eq!(result.taker_fill_outcome, FillType::Complete);
@@ -243,7 +440,7 @@ mod tests {
             .maker_fills
             .iter()
             .map(|fill| fill.fill_amount)
-            .sum::<u32>();
+            .sum::<u64>();
         assert_eq!(
             total_filled_quantity, 75,
             "Total filled quantity should match the taker's required quantity."
@@ -256,4 +453,106 @@ mod tests {
         assert_eq!(result.order_type, OrderType::Limit);
         assert_eq!(result.taker_fill_outcome, FillType::Complete);
     }
+
+    fn self_trade_fixture(stp: SelfTradeProtection) -> (Orderbook, CommittedFill) {
+        let alice = uuid::Uuid::from_u128(1);
+        let bob = uuid::Uuid::from_u128(2);
+
+        let mut orderbook = Orderbook::new();
+        orderbook.push_ask(Order {
+            price: nz!(100),
+            quantity: nz!(20),
+            memo: 0,
+            owner: alice,
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
+        });
+        orderbook.push_ask(Order {
+            price: nz!(100),
+            quantity: nz!(20),
+            memo: 0,
+            owner: bob,
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
+        });
+
+        let taker = Order {
+            price: nz!(100),
+            quantity: nz!(30),
+            memo: 0,
+            owner: alice,
+            expire_at: None,
+            order_uuid: OrderUuid(uuid::Uuid::nil()),
+            display_quantity: None,
+            hidden_quantity: 0,
+        };
+
+        let committed =
+            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit, stp)
+                .unwrap()
+                .commit()
+                .unwrap();
+
+        (orderbook, committed)
+    }
+
+    #[test]
+    fn test_self_trade_cancel_oldest() {
+        let (orderbook, committed) = self_trade_fixture(SelfTradeProtection::CancelOldest);
+
+        // alice's resting ask is cancelled and the taker fills against bob instead.
+        assert_eq!(committed.taker_fill_outcome, FillType::Partial);
+        assert_eq!(committed.self_trade_cancels.len(), 1);
+        assert_eq!(committed.self_trade_cancels[0].cancel_amount, 20);
+        assert_eq!(committed.maker_fills.len(), 1);
+        assert_eq!(committed.maker_fills[0].fill_amount, 20);
+        assert_eq!(committed.taker_remaining.unwrap().quantity, nz!(10));
+        assert_eq!(orderbook.iter_rel(OrderSide::Sell).count(), 0);
+    }
+
+    #[test]
+    fn test_self_trade_cancel_newest() {
+        let (orderbook, committed) = self_trade_fixture(SelfTradeProtection::CancelNewest);
+
+        // the taker is cancelled before it can reach bob's ask.
+        assert_eq!(committed.taker_fill_outcome, FillType::None);
+        assert!(committed.self_trade_cancels.is_empty());
+        assert!(committed.maker_fills.is_empty());
+        assert!(committed.taker_remaining.is_none());
+        assert_eq!(orderbook.iter_rel(OrderSide::Sell).count(), 2);
+    }
+
+    #[test]
+    fn test_self_trade_cancel_both() {
+        let (orderbook, committed) = self_trade_fixture(SelfTradeProtection::CancelBoth);
+
+        assert_eq!(committed.taker_fill_outcome, FillType::None);
+        assert_eq!(committed.self_trade_cancels.len(), 1);
+        assert!(committed.maker_fills.is_empty());
+        assert!(committed.taker_remaining.is_none());
+
+        let asks = orderbook.iter_rel(OrderSide::Sell).collect::<Vec<_>>();
+        assert_eq!(asks.len(), 1);
+        assert_eq!(asks[0].1.owner, uuid::Uuid::from_u128(2));
+    }
+
+    #[test]
+    fn test_self_trade_decrease_cancel() {
+        let (orderbook, committed) = self_trade_fixture(SelfTradeProtection::DecreaseCancel);
+
+        // alice's smaller ask is cancelled, the taker is decreased by 20 and fills the remaining 10 against bob.
+        assert_eq!(committed.taker_fill_outcome, FillType::Partial);
+        assert_eq!(committed.self_trade_cancels[0].cancel_amount, 20);
+        assert_eq!(committed.maker_fills.len(), 1);
+        assert_eq!(committed.maker_fills[0].fill_amount, 10);
+        assert!(committed.taker_remaining.is_none());
+
+        let asks = orderbook.iter_rel(OrderSide::Sell).collect::<Vec<_>>();
+        assert_eq!(asks.len(), 1);
+        assert_eq!(asks[0].1.quantity, nz!(10));
+    }
 }
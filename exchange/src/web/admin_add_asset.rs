@@ -0,0 +1,55 @@
+use axum::extract::{Json, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::app_cx::ListAssetError;
+use crate::asset::Symbol;
+
+/// The request body for the `admin_add_asset` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminAddAsset {
+    /// The ticker symbol of the asset, e.g. `SOL`.
+    pub symbol: Symbol,
+    /// The number of decimal places of the asset, amounts are kept in its smallest fraction.
+    pub decimals: u8,
+}
+
+/// List a new asset, markets of it can be listed afterwards.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Json(body): Json<AdminAddAsset>,
+) -> Response {
+    match state.is_admin(user_uuid).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::warn!(?user_uuid, "non-admin tried to list an asset");
+            return (StatusCode::FORBIDDEN, "admin only").into_response();
+        }
+        Err(err) => {
+            tracing::error!(?err, "failed to fetch user role");
+            return super::internal_server_error("failed to list asset");
+        }
+    }
+
+    match state.list_asset(body.symbol, body.decimals).await {
+        Ok(asset) => {
+            tracing::info!(%asset, "asset listed");
+            Json(asset).into_response()
+        }
+        Err(err @ ListAssetError::AlreadyListed(_)) => {
+            (StatusCode::CONFLICT, err.to_string()).into_response()
+        }
+        Err(err @ ListAssetError::TooManyDecimals) => {
+            (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+        Err(err) => {
+            tracing::warn!(?err, "failed to list asset");
+            super::internal_server_error("failed to list asset")
+        }
+    }
+}
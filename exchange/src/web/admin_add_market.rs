@@ -0,0 +1,114 @@
+use axum::extract::{Json, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::amount::{AmountError, Decimal};
+use crate::app_cx::ListMarketError;
+use crate::asset::MarketListing;
+use crate::trading::MarketRules;
+use crate::Market;
+
+/// The request body for the `admin_add_market` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminAddMarket {
+    /// The market to list, e.g. `BTC-ETH`.
+    pub symbol: String,
+    /// Prices must be a multiple of this amount of the quote asset, e.g. `0.01`.
+    pub tick_size: Option<Decimal>,
+    /// Quantities must be a multiple of this amount of the base asset.
+    pub lot_size: Option<Decimal>,
+    /// The smallest quantity of an order in the base asset.
+    pub min_quantity: Option<Decimal>,
+    /// The largest quantity of an order in the base asset.
+    pub max_quantity: Option<Decimal>,
+    /// The smallest value of an order in the quote asset.
+    pub min_notional: Option<Decimal>,
+}
+
+impl AdminAddMarket {
+    /// the rules of `market` in the smallest units of its assets, the defaults accept any price
+    /// and quantity.
+    fn rules(&self, market: Market) -> Result<MarketRules, AmountError> {
+        let (base, quote) = (market.base.decimals(), market.quote.decimals());
+        let amount = |decimal: &Option<Decimal>, decimals| {
+            decimal
+                .as_ref()
+                .map(|decimal| decimal.to_amount(decimals))
+                .transpose()
+        };
+
+        let default = MarketRules::default();
+        Ok(MarketRules {
+            tick_size: amount(&self.tick_size, quote)?.unwrap_or(default.tick_size),
+            lot_size: amount(&self.lot_size, base)?.unwrap_or(default.lot_size),
+            min_quantity: amount(&self.min_quantity, base)?.unwrap_or(default.min_quantity),
+            max_quantity: amount(&self.max_quantity, base)?,
+            min_notional: match amount(&self.min_notional, quote) {
+                Ok(min_notional) => min_notional.map_or(default.min_notional, |m| m.get()),
+                Err(AmountError::Zero) => 0,
+                Err(err) => return Err(err),
+            },
+        })
+    }
+}
+
+/// The response body for the `admin_add_market` endpoint.
+#[derive(Debug, Serialize)]
+pub struct AdminAddMarketResponse {
+    symbol: String,
+    #[serde(flatten)]
+    listing: MarketListing,
+}
+
+/// List a new market, the trading engine starts accepting orders for it without a restart.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Json(body): Json<AdminAddMarket>,
+) -> Response {
+    match state.is_admin(user_uuid).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::warn!(?user_uuid, "non-admin tried to list a market");
+            return (StatusCode::FORBIDDEN, "admin only").into_response();
+        }
+        Err(err) => {
+            tracing::error!(?err, "failed to fetch user role");
+            return super::internal_server_error("failed to list market");
+        }
+    }
+
+    let Some(market) = state.market(&body.symbol) else {
+        return (StatusCode::BAD_REQUEST, "invalid market").into_response();
+    };
+
+    let rules = match body.rules(market) {
+        Ok(rules) => rules,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+
+    match state.list_market(market, rules).await {
+        Ok(listing) => {
+            tracing::info!(%market, "market listed");
+            Json(AdminAddMarketResponse {
+                symbol: market.to_string(),
+                listing,
+            })
+            .into_response()
+        }
+        Err(err @ ListMarketError::AlreadyListed(_)) => {
+            (StatusCode::CONFLICT, err.to_string()).into_response()
+        }
+        Err(err @ (ListMarketError::SameAsset | ListMarketError::InvalidRules)) => {
+            (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+        Err(err) => {
+            tracing::warn!(?err, "failed to list market");
+            super::internal_server_error("failed to list market")
+        }
+    }
+}
@@ -5,7 +5,6 @@ use axum::{Extension, Form, Json};
 use serde::Deserialize;
 
 use crate::bitcoin::proto::GetNewAddressRequest;
-use crate::Asset;
 
 use super::middleware::auth::UserUuid;
 use super::InternalApiState;
@@ -55,13 +54,9 @@ pub async fn f(
     //     state.create_user_account(user_id, asset).await?;
     // } 
 
-    let asset = match params.asset.as_str() {
-        "btc" | "BTC" => Asset::Bitcoin,
-        "eth" | "ETH" => Asset::Ether,
-        _ => {
-            tracing::warn!(?params.asset, "invalid asset");
-            return Err(CreateDepositAddressError::InvalidAsset);
-        }
+    let Some(asset) = state.asset(&params.asset) else {
+        tracing::warn!(?params.asset, "invalid asset");
+        return Err(CreateDepositAddressError::InvalidAsset);
     };
 
     let addrs = state.list_deposit_addrs(user_id).await?;
@@ -72,8 +67,8 @@ pub async fn f(
         return Err(CreateDepositAddressError::AlreadyExists);
     }
 
-    let address_text: String = match asset {
-        Asset::Bitcoin => {
+    let address_text: String = match asset.symbol().as_str() {
+        "BTC" => {
             state
                 .bitcoind_rpc
                 .get_new_address(GetNewAddressRequest {
@@ -85,7 +80,10 @@ pub async fn f(
                 .into_inner()
                 .address
         }
-        Asset::Ether => todo!(),
+        _ => {
+            tracing::warn!(%asset, "deposits of asset are not supported");
+            return Err(CreateDepositAddressError::InvalidAsset);
+        }
     };
 
     let rec = sqlx::query!(
@@ -25,10 +25,14 @@ use tower_http::{LatencyUnit, ServiceBuilderExt};
 
 mod middleware;
 
+mod trade_add_oco;
 mod trade_add_order;
 pub use trade_add_order::TradeAddOrder;
+mod trade_cancel_all;
 mod trade_cancel_order;
 mod trade_edit_order;
+mod trade_list_fills;
+mod trade_list_orders;
 
 mod user_balance;
 mod user_create;
@@ -49,8 +53,17 @@ mod withdraw_list_addrs;
 mod withdraw_status;
 mod withdraw_transfer;
 
+mod public_book;
+mod public_candles;
+mod public_market_data;
+mod public_markets;
+mod public_ticker;
+mod public_ticker_feed;
 mod public_time;
 
+mod admin_add_asset;
+mod admin_add_market;
+
 mod html_home;
 mod html_index;
 
@@ -72,6 +85,22 @@ fn internal_server_error(message: &str) -> Response {
         .into_response()
 }
 
+/// parse the `:market` path segment of a trade route, e.g. `btc-usd`, responding with a 404 if
+/// the market is not traded. a lone asset like `btc` is its market quoted in USD.
+fn traded_market(state: &InternalApiState, market: &str) -> Result<crate::Market, Response> {
+    let Some(market) = state.market(market) else {
+        tracing::warn!(?market, "invalid market");
+        return Err((axum::http::StatusCode::NOT_FOUND, "invalid market").into_response());
+    };
+
+    if !state.is_market_traded(market) {
+        tracing::warn!(%market, "market not traded");
+        return Err((axum::http::StatusCode::NOT_FOUND, "market not traded").into_response());
+    }
+
+    Ok(market)
+}
+
 type InternalApiState = crate::app_cx::AppCx;
 
 /// Router for the /trade path
@@ -82,7 +111,10 @@ pub fn trade_routes(state: InternalApiState) -> Router {
         .put(trade_edit_order::f);
 
     Router::new()
-        .route("/trade/:asset/order", trade_order)
+        .route("/trade/:market/order", trade_order)
+        .route("/trade/:market/oco", post(trade_add_oco::f))
+        .route("/trade/fills", get(trade_list_fills::f))
+        .route("/trade/orders", get(trade_list_orders::f).delete(trade_cancel_all::f))
         .route_layer(axum::middleware::from_fn_with_state(
             state.clone(),
             middleware::validate_session_token,
@@ -162,8 +194,29 @@ pub fn session_routes(state: InternalApiState) -> Router {
 }
 
 /// Router for the /public path
-pub fn public_routes() -> Router {
-    Router::new().route("/public/time", get(public_time::f))
+pub fn public_routes(state: InternalApiState) -> Router {
+    Router::new()
+        .route("/public/time", get(public_time::f))
+        .route("/public/markets", get(public_markets::f))
+        .route("/public/book/:market", get(public_book::f))
+        .route("/public/candles/:market", get(public_candles::f))
+        .route("/public/ticker", get(public_ticker::f))
+        .route("/public/ws/ticker", get(public_ticker_feed::f))
+        .route("/public/ws/:market", get(public_market_data::f))
+        .with_state(state)
+}
+
+/// Router for the /admin path, handlers check that the user is an admin
+#[track_caller]
+pub fn admin_routes(state: InternalApiState) -> Router {
+    Router::new()
+        .route("/admin/assets", post(admin_add_asset::f))
+        .route("/admin/markets", post(admin_add_market::f))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::validate_session_token,
+        ))
+        .with_state(state)
 }
 
 fn api_router(state: InternalApiState) -> Router {
@@ -172,7 +225,8 @@ fn api_router(state: InternalApiState) -> Router {
         .merge(session_routes(state.clone()))
         .merge(withdrawal_routes(state.clone()))
         .merge(deposit_routes(state.clone()))
-        .merge(public_routes());
+        .merge(admin_routes(state.clone()))
+        .merge(public_routes(state));
 
     Router::new().nest("/api", router)
 }
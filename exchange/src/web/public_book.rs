@@ -0,0 +1,145 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+
+use super::InternalApiState;
+use crate::amount::format_decimal;
+use crate::trading::BookOrder;
+use crate::Amount;
+
+/// the number of price levels returned when no depth is asked for.
+const DEFAULT_DEPTH: usize = 50;
+
+/// the most price levels returned.
+const MAX_DEPTH: usize = 1000;
+
+/// The query parameters of the `public_book` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct PublicBookParams {
+    /// the number of price levels of each side, 50 by default and at most 1000.
+    depth: Option<usize>,
+    /// 2 for aggregated price levels, 3 for individual orders.
+    #[serde(default = "default_level")]
+    level: u8,
+}
+
+fn default_level() -> u8 {
+    2
+}
+
+/// The response body for the `public_book` endpoint with aggregated price levels.
+#[derive(Debug, Serialize)]
+pub struct PublicBookL2 {
+    symbol: String,
+    /// `[price, quantity]` pairs, highest price first.
+    bids: Vec<[String; 2]>,
+    /// `[price, quantity]` pairs, lowest price first.
+    asks: Vec<[String; 2]>,
+}
+
+/// The response body for the `public_book` endpoint with individual orders.
+#[derive(Debug, Serialize)]
+pub struct PublicBookL3 {
+    symbol: String,
+    /// the bids in the order they are matched.
+    bids: Vec<PublicBookOrder>,
+    /// the asks in the order they are matched.
+    asks: Vec<PublicBookOrder>,
+}
+
+/// An order of the [`PublicBookL3`] response, neither the owner nor the order uuid are shown so
+/// orders can not be followed across responses.
+#[derive(Debug, Serialize)]
+pub struct PublicBookOrder {
+    /// the place of the order in its price level, 0 for the first one matched.
+    position: usize,
+    price: String,
+    quantity: String,
+}
+
+/// The orderbook of `market`, aggregated into price levels or as individual orders.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Path(market): Path<String>,
+    Query(params): Query<PublicBookParams>,
+) -> Response {
+    let market = match super::traded_market(&state, &market) {
+        Ok(market) => market,
+        Err(response) => return response,
+    };
+
+    let depth = match params.depth {
+        Some(depth @ 1..=MAX_DEPTH) => depth,
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("depth must be between 1 and {MAX_DEPTH}"),
+            )
+                .into_response()
+        }
+        None => DEFAULT_DEPTH,
+    };
+
+    if !matches!(params.level, 2 | 3) {
+        return (StatusCode::BAD_REQUEST, "level must be 2 or 3").into_response();
+    }
+
+    let Some(book) = state.book(market, depth).await else {
+        tracing::warn!(%market, "trading engine did not send the orderbook");
+        return super::internal_server_error("trading engine is unresponsive");
+    };
+
+    let symbol = market.to_string();
+
+    if params.level == 3 {
+        let orders = |orders: Vec<BookOrder>| {
+            let mut level = None;
+            let mut position = 0;
+
+            orders
+                .into_iter()
+                .map(|order| {
+                    if level != Some(order.price) {
+                        level = Some(order.price);
+                        position = 0;
+                    }
+                    position += 1;
+
+                    PublicBookOrder {
+                        position: position - 1,
+                        price: format_decimal(order.price.get(), market.quote.decimals()),
+                        quantity: format_decimal(order.quantity.get(), market.base.decimals()),
+                    }
+                })
+                .collect()
+        };
+
+        return Json(PublicBookL3 {
+            symbol,
+            bids: orders(book.bids),
+            asks: orders(book.asks),
+        })
+        .into_response();
+    }
+
+    let depth = book.depth();
+    let levels = |levels: Vec<(Amount, u64)>| {
+        levels
+            .into_iter()
+            .map(|(price, quantity)| {
+                [
+                    format_decimal(price.get(), market.quote.decimals()),
+                    format_decimal(quantity, market.base.decimals()),
+                ]
+            })
+            .collect()
+    };
+
+    Json(PublicBookL2 {
+        symbol,
+        bids: levels(depth.bids),
+        asks: levels(depth.asks),
+    })
+    .into_response()
+}
@@ -0,0 +1,104 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+
+use super::InternalApiState;
+use crate::amount::format_decimal;
+use crate::candles::{self, CandleInterval};
+
+/// the number of candles returned when no limit is asked for.
+const DEFAULT_LIMIT: i64 = 500;
+
+/// the most candles returned.
+const MAX_LIMIT: i64 = 1000;
+
+/// The query parameters of the `public_candles` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct PublicCandlesParams {
+    /// the length of the period of each candle, `1m`, `5m`, `1h` or `1d`.
+    interval: CandleInterval,
+    /// the unix timestamp (in seconds) of the earliest period, the beginning of time by default.
+    start: Option<i64>,
+    /// the unix timestamp (in seconds) periods must start before, now by default.
+    end: Option<i64>,
+    /// the most candles returned, the newest ones are kept. 500 by default and at most 1000.
+    limit: Option<i64>,
+}
+
+/// A candle of the `public_candles` response, periods without trades are left out.
+#[derive(Debug, Serialize)]
+pub struct PublicCandle {
+    /// the unix timestamp (in seconds) the period starts at.
+    time: i64,
+    open: String,
+    high: String,
+    low: String,
+    close: String,
+    volume: String,
+    trades: u64,
+}
+
+/// The OHLCV candles of `market`, oldest first.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Path(market): Path<String>,
+    Query(params): Query<PublicCandlesParams>,
+) -> Response {
+    let market = match super::traded_market(&state, &market) {
+        Ok(market) => market,
+        Err(response) => return response,
+    };
+
+    let limit = match params.limit {
+        Some(limit @ 1..=MAX_LIMIT) => limit,
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("limit must be between 1 and {MAX_LIMIT}"),
+            )
+                .into_response()
+        }
+        None => DEFAULT_LIMIT,
+    };
+
+    let start = params.start.unwrap_or(0);
+    let end = params.end.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(i64::MAX, |d| d.as_secs() as i64 + 1)
+    });
+
+    if start > end {
+        return (StatusCode::BAD_REQUEST, "start must not be after end").into_response();
+    }
+
+    let candles =
+        match candles::fetch_candles(&state.db(), market, params.interval, start, end, limit).await
+        {
+            Ok(candles) => candles,
+            Err(err) => {
+                tracing::error!(?err, %market, "failed to fetch candles");
+                return super::internal_server_error("failed to fetch candles");
+            }
+        };
+
+    let price = |price: u64| format_decimal(price, market.quote.decimals());
+
+    let candles: Vec<_> = candles
+        .into_iter()
+        .map(|candle| PublicCandle {
+            time: candle.open_time,
+            open: price(candle.open),
+            high: price(candle.high),
+            low: price(candle.low),
+            close: price(candle.close),
+            volume: format_decimal(candle.volume, market.base.decimals()),
+            trades: candle.trades,
+        })
+        .collect();
+
+    Json(candles).into_response()
+}
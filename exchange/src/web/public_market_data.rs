@@ -0,0 +1,66 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::response::Response;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::InternalApiState;
+use crate::market_data::MarketDataMsg;
+use crate::Market;
+
+/// Stream the market data of `market` over a WebSocket.
+///
+/// A snapshot of every price level is sent first, followed by each change to a level and each
+/// trade. Messages are numbered per market, whenever a number is skipped the client missed a
+/// message and should reconnect. A client that falls behind is sent a fresh snapshot instead.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Path(market): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let market = match super::traded_market(&state, &market) {
+        Ok(market) => market,
+        Err(response) => return response,
+    };
+
+    ws.on_upgrade(move |socket| feed(state, market, socket))
+}
+
+async fn feed(state: InternalApiState, market: Market, mut socket: WebSocket) {
+    let (snapshot, mut rx) = state.market_data().subscribe(market);
+    let mut seq = snapshot.seq;
+
+    if send(&mut socket, &snapshot).await.is_err() {
+        return;
+    }
+
+    loop {
+        let msg = tokio::select! {
+            msg = rx.recv() => msg,
+            incoming = socket.recv() => match incoming {
+                // clients only listen, anything but closing the socket is ignored.
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let msg = match msg {
+            Ok(msg) if msg.market == market && msg.seq > seq => msg,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(missed)) => {
+                tracing::debug!(%market, missed, "market data subscriber lagged, resending snapshot");
+                state.market_data().snapshot(market)
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        seq = msg.seq;
+        if send(&mut socket, &msg).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, msg: &MarketDataMsg) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(msg).expect("market data is always serializable");
+    socket.send(Message::Text(text)).await
+}
@@ -0,0 +1,27 @@
+use axum::extract::{Json, State};
+use serde::Serialize;
+
+use super::InternalApiState;
+use crate::asset::MarketListing;
+
+/// An entry of the `public_markets` response.
+#[derive(Debug, Serialize)]
+pub struct PublicMarket {
+    symbol: String,
+    #[serde(flatten)]
+    listing: MarketListing,
+}
+
+/// List every market, including halted ones.
+pub async fn f(State(state): State<InternalApiState>) -> Json<Vec<PublicMarket>> {
+    let markets = state
+        .markets()
+        .into_iter()
+        .map(|listing| PublicMarket {
+            symbol: listing.market.to_string(),
+            listing,
+        })
+        .collect();
+
+    Json(markets)
+}
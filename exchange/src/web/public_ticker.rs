@@ -0,0 +1,16 @@
+use axum::extract::{Json, State};
+
+use super::InternalApiState;
+use crate::market_data::Ticker;
+
+/// The 24 hour ticker of every traded market.
+pub async fn f(State(state): State<InternalApiState>) -> Json<Vec<Ticker>> {
+    let tickers = state
+        .markets()
+        .into_iter()
+        .filter(|listing| state.is_market_traded(listing.market))
+        .map(|listing| state.market_data().ticker(listing.market))
+        .collect();
+
+    Json(tickers)
+}
@@ -0,0 +1,63 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::InternalApiState;
+use crate::market_data::Ticker;
+
+/// Stream the 24 hour tickers of every traded market over a WebSocket.
+///
+/// The ticker of every market is sent first, followed by each ticker that changed because of a
+/// trade or a new best bid or ask. A client that falls behind is sent every ticker again.
+pub async fn f(State(state): State<InternalApiState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| feed(state, socket))
+}
+
+async fn feed(state: InternalApiState, mut socket: WebSocket) {
+    let mut rx = state.market_data().subscribe_tickers();
+
+    if send_all(&state, &mut socket).await.is_err() {
+        return;
+    }
+
+    loop {
+        let ticker = tokio::select! {
+            ticker = rx.recv() => ticker,
+            incoming = socket.recv() => match incoming {
+                // clients only listen, anything but closing the socket is ignored.
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let sent = match ticker {
+            Ok(ticker) if state.is_market_traded(ticker.market) => send(&mut socket, &ticker).await,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(missed)) => {
+                tracing::debug!(missed, "ticker subscriber lagged, resending every ticker");
+                send_all(&state, &mut socket).await
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        if sent.is_err() {
+            break;
+        }
+    }
+}
+
+async fn send_all(state: &InternalApiState, socket: &mut WebSocket) -> Result<(), axum::Error> {
+    for listing in state.markets() {
+        if state.is_market_traded(listing.market) {
+            send(socket, &state.market_data().ticker(listing.market)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(socket: &mut WebSocket, ticker: &Ticker) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(ticker).expect("tickers are always serializable");
+    socket.send(Message::Text(text)).await
+}
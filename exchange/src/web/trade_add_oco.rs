@@ -0,0 +1,111 @@
+use axum::extract::{Json, Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::{InternalApiState, TradeAddOrder};
+use crate::app_cx::PlaceOrderError;
+use crate::trading::{
+    PlaceOcoResult, PlaceOrderError as TradePlaceOrderError, TradingEngineError as TErr,
+};
+
+/// The request body for the `trade_add_oco` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeAddOco {
+    /// The linked orders, e.g. a take-profit limit order and a stop-loss order. The second order
+    /// is only placed if the first one rests without trading.
+    pub legs: [TradeAddOrder; 2],
+}
+
+/// The response body for the `trade_add_oco` endpoint.
+#[derive(Debug, Serialize)]
+pub struct TradeAddOcoResponse {
+    group_uuid: uuid::Uuid,
+    order_uuids: [uuid::Uuid; 2],
+}
+
+/// Place two linked orders in `market`, when either one fills or is cancelled the other one is cancelled.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Path(market): Path<String>,
+    Json(body): Json<TradeAddOco>,
+) -> Response {
+    let market = match super::traded_market(&state, &market) {
+        Ok(market) => market,
+        Err(response) => return response,
+    };
+
+    tracing::info!(%market, "placing linked orders for market");
+
+    let (response, reserved_funds) = match state.place_oco(market, user_uuid, body.legs).await {
+        Ok(r) => r,
+        Err(
+            err @ (PlaceOrderError::InvalidExpireAt
+            | PlaceOrderError::InvalidStopPrice
+            | PlaceOrderError::InvalidPostOnly
+            | PlaceOrderError::InvalidDisplayQuantity
+            | PlaceOrderError::InvalidQuoteAmount
+            | PlaceOrderError::InvalidProtection
+            | PlaceOrderError::InvalidAmount(_)
+            | PlaceOrderError::MarketRule(_)),
+        ) => {
+            tracing::warn!(?err, "rejected linked orders");
+            return (axum::http::StatusCode::BAD_REQUEST, err.to_string()).into_response();
+        }
+        Err(err) => {
+            tracing::warn!(?err, "failed to place linked orders");
+            return super::internal_server_error("failed to place linked orders");
+        }
+    };
+
+    let _deferred_reverts = reserved_funds
+        .map(|reserve| reserve.defer_revert(tokio::runtime::Handle::current(), state.db()));
+
+    let result = response.wait().await;
+
+    if matches!(result, Some(Ok(_))) {
+        for deferred_revert in _deferred_reverts {
+            deferred_revert.cancel();
+        }
+    }
+
+    match result {
+        Some(Ok(PlaceOcoResult {
+            group_uuid,
+            order_uuids,
+            ..
+        })) => {
+            tracing::info!(?group_uuid, "linked orders placed");
+            Json(TradeAddOcoResponse {
+                group_uuid: group_uuid.0,
+                order_uuids: order_uuids.map(|order_uuid| order_uuid.0),
+            })
+            .into_response()
+        }
+        Some(Err(err)) => match err {
+            TErr::UnserializableInput => super::internal_server_error(
+                "this input was considered problematic and could not be processed",
+            ),
+            TErr::PlaceOrder(
+                err @ (TradePlaceOrderError::FillOrKillFailed
+                | TradePlaceOrderError::InsufficientLiquidity
+                | TradePlaceOrderError::PostOnlyWouldTake
+                | TradePlaceOrderError::ExceedsReservation
+                | TradePlaceOrderError::MarketRule(_)),
+            ) => {
+                tracing::info!(?err, "rejected linked orders");
+                (axum::http::StatusCode::BAD_REQUEST, err.to_string()).into_response()
+            }
+            err => {
+                tracing::warn!(?err, "failed to place linked orders");
+                super::internal_server_error("failed to place linked orders")
+            }
+        },
+        None => {
+            tracing::warn!("trading engine unresponsive");
+            super::internal_server_error("trading engine unresponsive")
+        }
+    }
+}
@@ -7,12 +7,12 @@ use serde::{Deserialize, Serialize};
 
 use super::middleware::auth::UserUuid;
 use super::InternalApiState;
-use crate::asset::ContainsAsset as _;
+use crate::amount::Decimal;
+use crate::app_cx::PlaceOrderError;
 use crate::trading::{
-    OrderSide, OrderType, PlaceOrderResult, SelfTradeProtection, TimeInForce,
-    TradingEngineError as TErr,
+    OrderSide, OrderType, PlaceOrderError as TradePlaceOrderError, PlaceOrderResult, PostOnly,
+    SelfTradeProtection, TimeInForce, TradingEngineError as TErr,
 };
-use crate::Asset;
 
 /// The request body for the `trade_add_order` endpoint.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,16 +21,38 @@ pub struct TradeAddOrder {
     pub side: OrderSide,
     /// The type of the order.
     pub order_type: OrderType,
-    /// The quantity of the order.
-    pub quantity: NonZeroU32,
-    /// The price of the order.
-    pub price: NonZeroU32,
+    /// The quantity of the order in the base asset, a decimal string like `"0.0015"`.
+    pub quantity: Decimal,
+    /// The price of the order in the quote asset for one whole base asset, a decimal string.
+    pub price: Decimal,
     /// The time in force of the order.
     #[serde(default)]
     pub time_in_force: TimeInForce,
     /// The self-trade protection of the order.
     #[serde(default)]
     pub stp: SelfTradeProtection,
+    /// The unix timestamp (in seconds) a good-til-date order expires at, required for and only allowed on good-til-date orders.
+    #[serde(default)]
+    pub expire_at: Option<u64>,
+    /// The last trade price that triggers a stop order, required for and only allowed on stop orders.
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+    /// Makes the order post-only, it is rejected or repriced instead of taking liquidity.
+    #[serde(default)]
+    pub post_only: Option<PostOnly>,
+    /// Makes the order an iceberg order that only displays this much of its quantity at a time.
+    #[serde(default)]
+    pub display_quantity: Option<Decimal>,
+    /// Makes an immediate-or-cancel market buy spend at most this much of the quote currency,
+    /// `quantity` then only caps how much is bought. Only what is spent is kept from the reservation.
+    #[serde(default)]
+    pub quote_amount: Option<Decimal>,
+    /// The worst price a (stop-)market order matches at, the highest for buys and the lowest for sells.
+    #[serde(default)]
+    pub protection_price: Option<Decimal>,
+    /// How far, in basis points, a (stop-)market order may match from the best opposite price.
+    #[serde(default)]
+    pub max_slippage_bps: Option<NonZeroU32>,
 }
 
 /// The response body for the `trade_add_order` endpoint.
@@ -39,34 +61,35 @@ pub struct TradeAddOrderResponse {
     order_uuid: uuid::Uuid,
 }
 
-/// Place an order for `asset`
+/// Place an order in `market`
 pub async fn f(
     State(state): State<InternalApiState>,
     Extension(UserUuid(user_uuid)): Extension<UserUuid>,
-    Path(asset): Path<String>,
+    Path(market): Path<String>,
     Json(body): Json<TradeAddOrder>,
 ) -> Response {
-    let asset = match asset.as_str() {
-        "btc" | "BTC" => Asset::Bitcoin,
-        "eth" | "ETH" => Asset::Ether,
-        _ => {
-            tracing::warn!(?asset, "invalid asset");
-            return (axum::http::StatusCode::NOT_FOUND, "invalid asset").into_response();
-        }
+    let market = match super::traded_market(&state, &market) {
+        Ok(market) => market,
+        Err(response) => return response,
     };
 
-    if !state
-        .assets
-        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
-    {
-        tracing::warn!(?asset, "asset not enabled");
-        return (axum::http::StatusCode::NOT_FOUND, "asset not enabled").into_response();
-    } else {
-        tracing::info!(?asset, "placing order for asset");
-    }
+    tracing::info!(%market, "placing order for market");
 
-    let (response, reserved_funds) = match state.place_order(asset, user_uuid, body).await {
+    let (response, reserved_funds) = match state.place_order(market, user_uuid, body).await {
         Ok(r) => r,
+        Err(
+            err @ (PlaceOrderError::InvalidExpireAt
+            | PlaceOrderError::InvalidStopPrice
+            | PlaceOrderError::InvalidPostOnly
+            | PlaceOrderError::InvalidDisplayQuantity
+            | PlaceOrderError::InvalidQuoteAmount
+            | PlaceOrderError::InvalidProtection
+            | PlaceOrderError::InvalidAmount(_)
+            | PlaceOrderError::MarketRule(_)),
+        ) => {
+            tracing::warn!(?err, "rejected order");
+            return (axum::http::StatusCode::BAD_REQUEST, err.to_string()).into_response();
+        }
         Err(err) => {
             tracing::warn!(?err, "failed to place order");
             return super::internal_server_error("failed to place order");
@@ -94,6 +117,14 @@ pub async fn f(
             TErr::UnserializableInput => super::internal_server_error(
                 "this input was considered problematic and could not be processed",
             ),
+            TErr::PlaceOrder(
+                err @ (TradePlaceOrderError::PostOnlyWouldTake
+                | TradePlaceOrderError::ExceedsReservation
+                | TradePlaceOrderError::MarketRule(_)),
+            ) => {
+                tracing::info!(?err, "rejected order");
+                (axum::http::StatusCode::BAD_REQUEST, err.to_string()).into_response()
+            }
             err => {
                 tracing::warn!(?err, "failed to place order");
                 super::internal_server_error("failed to place order")
@@ -0,0 +1,69 @@
+use axum::extract::{Json, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::trading::{OrderSide, TradingEngineError};
+
+/// The query parameters of the `trade_cancel_all` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct TradeCancelAllParams {
+    /// only cancel the orders of this market, e.g. `btc-usd`.
+    market: Option<String>,
+    /// only cancel the orders of this side, `buy` or `sell`.
+    side: Option<OrderSide>,
+}
+
+/// The response body for the `trade_cancel_all` endpoint.
+#[derive(Debug, Serialize)]
+pub struct TradeCancelAllResponse {
+    /// the orders that were cancelled.
+    cancelled: Vec<uuid::Uuid>,
+}
+
+/// Cancel every order of the user, resting orders and stop orders alike.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Query(params): Query<TradeCancelAllParams>,
+) -> Response {
+    let market = match params.market {
+        Some(market) => match super::traded_market(&state, &market) {
+            Ok(market) => Some(market),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    let Ok(wait_response) = state.cancel_all(user_uuid, market, params.side).await else {
+        tracing::warn!("failed to cancel orders, trade engine is suspended");
+        return super::internal_server_error("trading engine is suspended");
+    };
+
+    let Some(res) = wait_response.wait().await else {
+        tracing::warn!("wait_response did not return a result");
+        return super::internal_server_error("trading engine is unresponsive");
+    };
+
+    match res {
+        Ok(cancelled) => {
+            tracing::info!(count = cancelled.len(), "orders cancelled");
+            let cancelled = cancelled
+                .iter()
+                .map(|cancelled| cancelled.order.order_uuid().0)
+                .collect();
+            Json(TradeCancelAllResponse { cancelled }).into_response()
+        }
+        Err(err @ TradingEngineError::UnknownMarket(..)) => {
+            tracing::warn!(?err, "failed to cancel orders");
+            (StatusCode::NOT_FOUND, "market not traded").into_response()
+        }
+        Err(err) => {
+            tracing::warn!(?err, "failed to cancel orders");
+            super::internal_server_error("failed to cancel orders")
+        }
+    }
+}
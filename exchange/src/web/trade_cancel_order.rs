@@ -5,8 +5,7 @@ use serde::{Deserialize, Serialize};
 
 use super::middleware::auth::UserUuid;
 use super::InternalApiState;
-use crate::asset::ContainsAsset as _;
-use crate::Asset;
+use crate::trading::TradingEngineError;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradeCancelOrder {
@@ -16,31 +15,19 @@ pub struct TradeCancelOrder {
 #[derive(Debug, Serialize)]
 pub struct TradeCancelOrderResponse {}
 
-/// Place an order for `asset`
+/// Cancel one of the user's orders in `market`
 pub async fn f(
     State(state): State<InternalApiState>,
     Extension(UserUuid(user_uuid)): Extension<UserUuid>,
-    Path(asset): Path<String>,
+    Path(market): Path<String>,
     Json(body): Json<TradeCancelOrder>,
 ) -> Response {
-    let asset = match asset.as_str() {
-        "btc" | "BTC" => Asset::Bitcoin,
-        "eth" | "ETH" => Asset::Ether,
-        _ => {
-            tracing::warn!(?asset, "invalid asset");
-            return (axum::http::StatusCode::NOT_FOUND, "invalid asset").into_response();
-        }
+    let market = match super::traded_market(&state, &market) {
+        Ok(market) => market,
+        Err(response) => return response,
     };
 
-    if !state
-        .assets
-        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
-    {
-        tracing::warn!(?asset, "asset not enabled");
-        return (axum::http::StatusCode::NOT_FOUND, "asset not enabled").into_response();
-    } else {
-        tracing::info!(?asset, "placing order for asset");
-    }
+    tracing::info!(%market, order_uuid = %body.order_uuid, "cancelling order in market");
 
     let Ok(wait_response) = state.cancel_order(user_uuid, body.order_uuid).await else {
         tracing::warn!("failed to cancel order, trade engine is suspended");
@@ -53,10 +40,14 @@ pub async fn f(
     };
 
     match res {
-        Ok(()) => {
+        Ok(_) => {
             tracing::info!("order cancelled");
             (axum::http::StatusCode::OK, "order cancelled").into_response()
         }
+        Err(err @ TradingEngineError::OrderNotFound(..)) => {
+            tracing::warn!(?err, "failed to cancel order");
+            (axum::http::StatusCode::NOT_FOUND, "order not found").into_response()
+        }
         Err(err) => {
             tracing::warn!(?err, "failed to cancel order");
             super::internal_server_error("failed to cancel order")
@@ -1 +1,99 @@
-pub async fn f() {}
+use axum::extract::{Json, Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::amount::Decimal;
+use crate::app_cx::AmendOrderError;
+use crate::trading::{AmendOrderResult, TradingEngineError as TErr};
+
+/// The request body for the `trade_edit_order` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeEditOrder {
+    /// The order to amend.
+    pub order_uuid: uuid::Uuid,
+    /// The new price of the order, a decimal string.
+    pub price: Decimal,
+    /// The new quantity of the order, a decimal string.
+    pub quantity: Decimal,
+}
+
+/// The response body for the `trade_edit_order` endpoint.
+#[derive(Debug, Serialize)]
+pub struct TradeEditOrderResponse {
+    order_uuid: uuid::Uuid,
+    price: String,
+    quantity: String,
+    kept_priority: bool,
+}
+
+/// Amend a resting order in `market`
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Path(market): Path<String>,
+    Json(body): Json<TradeEditOrder>,
+) -> Response {
+    let market = match super::traded_market(&state, &market) {
+        Ok(market) => market,
+        Err(response) => return response,
+    };
+
+    tracing::info!(%market, "amending order for market");
+
+    let TradeEditOrder {
+        order_uuid,
+        price,
+        quantity,
+    } = body;
+
+    let wait_response = match state
+        .amend_order(user_uuid, market, order_uuid, price, quantity)
+        .await
+    {
+        Ok(wait_response) => wait_response,
+        Err(err @ AmendOrderError::InvalidAmount(_)) => {
+            return (axum::http::StatusCode::BAD_REQUEST, err.to_string()).into_response();
+        }
+        Err(AmendOrderError::TradingEngineUnresponsive) => {
+            tracing::warn!("failed to amend order, trade engine is not running");
+            return super::internal_server_error("trading engine is not running");
+        }
+    };
+
+    let Some(res) = wait_response.wait().await else {
+        tracing::warn!("wait_response did not return a result");
+        return super::internal_server_error("trading engine is unresponsive");
+    };
+
+    match res {
+        Ok(AmendOrderResult {
+            order,
+            kept_priority,
+            ..
+        }) => {
+            tracing::info!(?order_uuid, kept_priority, "order amended");
+            Json(TradeEditOrderResponse {
+                order_uuid,
+                price: order.price().to_decimal(market.quote.decimals()),
+                quantity: order.quantity().to_decimal(market.base.decimals()),
+                kept_priority,
+            })
+            .into_response()
+        }
+        Err(err @ TErr::OrderNotFound(..)) => {
+            tracing::warn!(?err, "failed to amend order");
+            (axum::http::StatusCode::NOT_FOUND, "order not found").into_response()
+        }
+        Err(TErr::AmendOrder(err)) => {
+            tracing::warn!(?err, "rejected order amendment");
+            (axum::http::StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+        Err(err) => {
+            tracing::warn!(?err, "failed to amend order");
+            super::internal_server_error("failed to amend order")
+        }
+    }
+}
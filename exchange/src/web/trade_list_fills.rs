@@ -0,0 +1,163 @@
+use axum::extract::{Json, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::amount::format_decimal;
+use crate::app_cx::TradeFilter;
+use crate::trading::OrderSide;
+
+/// the number of fills returned when no limit is asked for.
+const DEFAULT_LIMIT: i64 = 100;
+
+/// the most fills returned.
+const MAX_LIMIT: i64 = 1000;
+
+/// The query parameters of the `trade_list_fills` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct TradeListFillsParams {
+    /// only list fills of this market, e.g. `btc-usd`.
+    market: Option<String>,
+    /// the earliest unix timestamp (in seconds).
+    start: Option<i64>,
+    /// the unix timestamp (in seconds) fills must be before.
+    end: Option<i64>,
+    /// only list fills older than this trade, the `next` of the previous page.
+    before: Option<i64>,
+    /// the most fills returned, 100 by default and at most 1000.
+    limit: Option<i64>,
+}
+
+/// Whether the user's order was resting in the orderbook or took liquidity from it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+/// A fill of one of the user's orders.
+#[derive(Debug, Serialize)]
+pub struct TradeFill {
+    trade_id: i64,
+    symbol: String,
+    order_uuid: uuid::Uuid,
+    side: OrderSide,
+    liquidity: Liquidity,
+    price: String,
+    quantity: String,
+    /// charged in the asset received, the base asset for buys and the quote asset for sells.
+    fee: String,
+    fee_asset: String,
+    /// the unix timestamp (in seconds) of the trade.
+    time: i64,
+}
+
+/// The response body for the `trade_list_fills` endpoint.
+#[derive(Debug, Serialize)]
+pub struct TradeListFillsResponse {
+    /// newest first.
+    fills: Vec<TradeFill>,
+    /// pass as `before` to get the next page, missing on the last page.
+    next: Option<i64>,
+}
+
+/// List the fills of the user's orders, newest first.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Query(params): Query<TradeListFillsParams>,
+) -> Response {
+    let market = match params.market {
+        Some(market) => match state.market(&market) {
+            Some(market) => Some(market),
+            None => return (StatusCode::BAD_REQUEST, "invalid market").into_response(),
+        },
+        None => None,
+    };
+
+    let limit = match params.limit {
+        Some(limit @ 1..=MAX_LIMIT) => limit,
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("limit must be between 1 and {MAX_LIMIT}"),
+            )
+                .into_response()
+        }
+        None => DEFAULT_LIMIT,
+    };
+
+    let filter = TradeFilter {
+        market,
+        start: params.start,
+        end: params.end,
+        before: params.before,
+        limit,
+    };
+
+    let trades = match state.user_trades(user_uuid, filter).await {
+        Ok(trades) => trades,
+        Err(err) => {
+            tracing::error!(?err, "failed to list fills");
+            return super::internal_server_error("failed to list fills");
+        }
+    };
+
+    let next = match trades.last() {
+        Some(last) if trades.len() as i64 == limit => Some(last.id),
+        _ => None,
+    };
+
+    let fills = trades
+        .into_iter()
+        .map(|trade| {
+            let market = trade.market;
+
+            // a self-trade is listed as the taker's fill.
+            let (order_uuid, side, liquidity, fee) = if trade.taker_user_uuid == user_uuid {
+                let side = trade.taker_side;
+                (
+                    trade.taker_order_uuid,
+                    side,
+                    Liquidity::Taker,
+                    trade.taker_fee,
+                )
+            } else {
+                let side = match trade.taker_side {
+                    OrderSide::Buy => OrderSide::Sell,
+                    OrderSide::Sell => OrderSide::Buy,
+                };
+                (
+                    trade.maker_order_uuid,
+                    side,
+                    Liquidity::Maker,
+                    trade.maker_fee,
+                )
+            };
+
+            let fee_asset = match side {
+                OrderSide::Buy => market.base,
+                OrderSide::Sell => market.quote,
+            };
+
+            TradeFill {
+                trade_id: trade.id,
+                symbol: market.to_string(),
+                order_uuid: order_uuid.0,
+                side,
+                liquidity,
+                price: format_decimal(trade.price.get(), market.quote.decimals()),
+                quantity: format_decimal(trade.quantity, market.base.decimals()),
+                fee: format_decimal(fee, fee_asset.decimals()),
+                fee_asset: fee_asset.to_string(),
+                time: trade.timestamp,
+            }
+        })
+        .collect();
+
+    Json(TradeListFillsResponse { fills, next }).into_response()
+}
@@ -0,0 +1,71 @@
+use axum::extract::{Json, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::amount::format_decimal;
+use crate::trading::{OrderSide, TimeInForce};
+
+/// The query parameters of the `trade_list_orders` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct TradeListOrdersParams {
+    /// which orders to list, only `open` (the default) is supported.
+    status: Option<String>,
+}
+
+/// A resting order of the user.
+#[derive(Debug, Serialize)]
+pub struct TradeOrder {
+    order_uuid: uuid::Uuid,
+    symbol: String,
+    side: OrderSide,
+    price: String,
+    /// the quantity the order was placed with, what it was amended to plus what had been filled.
+    quantity: String,
+    /// the quantity that is left to fill.
+    remaining: String,
+    time_in_force: TimeInForce,
+    /// the unix timestamp (in seconds) the order was created at or last lost its time priority
+    /// by an amendment, if it is known.
+    created_at: Option<u64>,
+}
+
+/// List the orders of the user that rest in an orderbook, oldest first.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Query(params): Query<TradeListOrdersParams>,
+) -> Response {
+    if !matches!(params.status.as_deref(), None | Some("open")) {
+        return (StatusCode::BAD_REQUEST, "status must be open").into_response();
+    }
+
+    let Some(orders) = state.open_orders(user_uuid).await else {
+        tracing::warn!("trading engine did not send the open orders");
+        return super::internal_server_error("trading engine is unresponsive");
+    };
+
+    let orders: Vec<_> = orders
+        .into_iter()
+        .map(|order| {
+            let market = order.market;
+            let quantity = |quantity: u64| format_decimal(quantity, market.base.decimals());
+
+            TradeOrder {
+                order_uuid: order.order_uuid.0,
+                symbol: market.to_string(),
+                side: order.side,
+                price: format_decimal(order.price.get(), market.quote.decimals()),
+                quantity: quantity(order.quantity.get()),
+                remaining: quantity(order.remaining.get()),
+                time_in_force: order.time_in_force,
+                created_at: order.created_at,
+            }
+        })
+        .collect();
+
+    Json(orders).into_response()
+}
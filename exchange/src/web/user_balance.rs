@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::Asset;
+use crate::amount::format_decimal;
 
 use super::middleware::auth::UserUuid;
 use super::InternalApiState;
@@ -42,18 +42,31 @@ pub async fn f(
 
         details
             .into_iter()
-            .map(|(k, v)| format!("<div id='balance-{k}'>{v}</div>"))
+            .map(|(k, v)| format!("<div id='balance-{k}'>{}</div>", format_balance(&state, &k, v)))
             .collect::<Vec<_>>()
             .join("\n")
     } else {
         let currency = currency.to_ascii_uppercase();
 
         let balance = match state.calculate_balance_from_accounting(user_id, &currency).await {
-            Ok(t) => t.map(|b| b.get()).unwrap_or(0),
+            Ok(t) => t.map(|b| b.get() as i64).unwrap_or(0),
             Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
         };
-        format!("<div id='balance-{currency}'>{balance}</div>")
+        format!("<div id='balance-{currency}'>{}</div>", format_balance(&state, &currency, balance))
     };
 
     Html(st).into_response()
 }
+
+/// format a balance in the smallest unit of `currency` as a decimal, unknown currencies as is.
+fn format_balance(state: &InternalApiState, currency: &str, balance: i64) -> String {
+    let Some(asset) = state.asset(currency) else {
+        return balance.to_string();
+    };
+
+    let units = format_decimal(balance.unsigned_abs(), asset.decimals());
+    match balance {
+        ..=-1 => format!("-{units}"),
+        _ => units,
+    }
+}
@@ -8,7 +8,6 @@ use axum_htmx::HX_TRIGGER;
 use serde::Deserialize;
 
 use crate::bitcoin::proto::GetNewAddressRequest;
-use crate::Asset;
 
 use super::middleware::auth::UserUuid;
 use super::InternalApiState;
@@ -52,13 +51,9 @@ pub async fn f(
 ) -> Result<Response, CreateWithdrawalAddressError> {
     let db = state.db();
 
-    let asset = match params.asset.as_str() {
-        "btc" | "BTC" => Asset::Bitcoin,
-        "eth" | "ETH" => Asset::Ether,
-        _ => {
-            tracing::warn!(?params.asset, "invalid asset");
-            return Err(CreateWithdrawalAddressError::InvalidAsset);
-        }
+    let Some(asset) = state.asset(&params.asset) else {
+        tracing::warn!(?params.asset, "invalid asset");
+        return Err(CreateWithdrawalAddressError::InvalidAsset);
     };
 
     let addrs = state.list_withdrawal_addrs(user_id).await?;
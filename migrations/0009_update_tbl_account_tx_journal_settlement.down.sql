@@ -0,0 +1,8 @@
+BEGIN;
+
+DELETE FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency IN ('BTC', 'ETH');
+
+ALTER TABLE account_tx_journal
+ALTER COLUMN txid SET NOT NULL;
+
+COMMIT;
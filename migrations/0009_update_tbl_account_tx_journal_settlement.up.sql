@@ -0,0 +1,11 @@
+BEGIN;
+
+-- internal transfers (reservations, trade settlement) have no on-chain txid
+ALTER TABLE account_tx_journal
+ALTER COLUMN txid DROP NOT NULL;
+
+-- the exchange holds reserved funds for every tradeable currency, not only USD
+INSERT INTO accounts (currency, source_type, source_id) VALUES ('BTC', 'fiat', 'exchange');
+INSERT INTO accounts (currency, source_type, source_id) VALUES ('ETH', 'fiat', 'exchange');
+
+COMMIT;
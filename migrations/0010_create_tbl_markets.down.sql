@@ -0,0 +1,2 @@
+DROP TABLE markets;
+DROP TYPE IF EXISTS market_status;
@@ -0,0 +1,24 @@
+-- Create enum type for market statuses
+DO $$ BEGIN
+    CREATE TYPE market_status AS ENUM ('active', 'halted');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+-- markets table, every market the trading engine keeps an orderbook for
+CREATE TABLE IF NOT EXISTS markets (
+    symbol VARCHAR(32) PRIMARY KEY,
+    base VARCHAR(16) NOT NULL,
+    quote VARCHAR(16) NOT NULL,
+    status market_status NOT NULL DEFAULT 'active',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(base, quote)
+);
+
+SELECT manage_updated_at('markets');
+
+INSERT INTO markets (symbol, base, quote) VALUES
+    ('BTC-USD', 'BTC', 'USD'),
+    ('ETH-USD', 'ETH', 'USD'),
+    ('ETH-BTC', 'ETH', 'BTC');
@@ -0,0 +1,6 @@
+ALTER TABLE markets
+    DROP COLUMN tick_size,
+    DROP COLUMN lot_size,
+    DROP COLUMN min_quantity,
+    DROP COLUMN max_quantity,
+    DROP COLUMN min_notional;
@@ -0,0 +1,7 @@
+-- the prices and quantities each market accepts, the defaults accept anything
+ALTER TABLE markets
+    ADD COLUMN tick_size BIGINT NOT NULL DEFAULT 1 CHECK (tick_size > 0),
+    ADD COLUMN lot_size BIGINT NOT NULL DEFAULT 1 CHECK (lot_size > 0),
+    ADD COLUMN min_quantity BIGINT NOT NULL DEFAULT 1 CHECK (min_quantity > 0),
+    ADD COLUMN max_quantity BIGINT CHECK (max_quantity >= min_quantity),
+    ADD COLUMN min_notional BIGINT NOT NULL DEFAULT 0 CHECK (min_notional >= 0);
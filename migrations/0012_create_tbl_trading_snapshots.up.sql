@@ -0,0 +1,11 @@
+-- snapshots of the trading engine state, each taken right after the event `last_event_id`
+-- of trading_event_source was applied. startup restores the newest one and replays the
+-- events after it.
+CREATE TABLE IF NOT EXISTS trading_snapshots (
+    id BIGSERIAL PRIMARY KEY,
+    last_event_id BIGINT NOT NULL,
+    snapshot BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_trading_snapshots_last_event_id ON trading_snapshots(last_event_id);
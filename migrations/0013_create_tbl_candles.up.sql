@@ -0,0 +1,21 @@
+-- OHLCV candles of the trades of each market, there is a row for every period of an interval
+-- with at least one trade. prices are in the smallest unit of the quote asset, volumes in the
+-- smallest unit of the base asset.
+CREATE TABLE IF NOT EXISTS candles (
+    market VARCHAR(32) NOT NULL REFERENCES markets(symbol),
+    -- the length of the period, one of 1m, 5m, 1h or 1d
+    period VARCHAR(4) NOT NULL CHECK (period IN ('1m', '5m', '1h', '1d')),
+    -- the unix timestamp (in seconds) the period starts at
+    open_time BIGINT NOT NULL,
+    open BIGINT NOT NULL,
+    high BIGINT NOT NULL,
+    low BIGINT NOT NULL,
+    close BIGINT NOT NULL,
+    volume BIGINT NOT NULL,
+    -- the number of trades in the period
+    trades BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (market, period, open_time)
+);
+
+SELECT manage_updated_at('candles');
@@ -0,0 +1,25 @@
+-- trades table, every match of a taker order against a resting maker order
+--
+-- rows are written in the same transaction as the ledger entries settling the match. the price
+-- is in the smallest unit of the quote asset per whole base unit, the quantity in the smallest
+-- unit of the base asset.
+CREATE TABLE IF NOT EXISTS trades (
+    id BIGSERIAL PRIMARY KEY,
+    market VARCHAR(32) NOT NULL REFERENCES markets(symbol),
+    price BIGINT NOT NULL CHECK (price > 0),
+    quantity BIGINT NOT NULL CHECK (quantity > 0),
+    -- the side of the taker order, the maker order is on the other side
+    taker_side VARCHAR(4) NOT NULL CHECK (taker_side IN ('buy', 'sell')),
+    maker_order_uuid UUID NOT NULL,
+    maker_user_uuid UUID NOT NULL,
+    taker_order_uuid UUID NOT NULL,
+    taker_user_uuid UUID NOT NULL,
+    -- fees are charged in the asset each side receives, the base asset for the buyer and the
+    -- quote asset for the seller. no fees are charged yet.
+    maker_fee BIGINT NOT NULL DEFAULT 0 CHECK (maker_fee >= 0),
+    taker_fee BIGINT NOT NULL DEFAULT 0 CHECK (taker_fee >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS trades_maker_user_uuid_idx ON trades (maker_user_uuid, id);
+CREATE INDEX IF NOT EXISTS trades_taker_user_uuid_idx ON trades (taker_user_uuid, id);
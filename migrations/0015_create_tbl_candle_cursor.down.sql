@@ -0,0 +1 @@
+DROP TABLE candle_cursor;
@@ -0,0 +1,14 @@
+-- how far the trades table has been aggregated into candles, a single row. the trades after
+-- last_trade_id are aggregated next, in the same transaction that moves the cursor, so trades are
+-- neither lost nor counted twice across restarts.
+CREATE TABLE IF NOT EXISTS candle_cursor (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    last_trade_id BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+SELECT manage_updated_at('candle_cursor');
+
+-- the trades so far are in the candles already, only later ones are aggregated. to rebuild the
+-- candles from the whole trades table, delete them and set last_trade_id to 0 in one transaction.
+INSERT INTO candle_cursor (last_trade_id) SELECT COALESCE(MAX(id), 0) FROM trades;
@@ -0,0 +1 @@
+ALTER TABLE trading_event_source DROP COLUMN fixed_point;
@@ -0,0 +1,10 @@
+-- events logged before prices and quantities became fixed-point amounts in the smallest unit of
+-- an asset hold unscaled whole numbers that would be replayed at the wrong scale. the rows already
+-- in the table are marked as such and the trading engine refuses to bootstrap from them, rows
+-- logged from now on are fixed-point.
+--
+-- cut-over: stop the exchange, cancel or settle every open order, archive the rows of
+-- trading_event_source (e.g. with \copy) and delete them together with trading_snapshots, then
+-- run this migration and start the new version with an empty event log.
+ALTER TABLE trading_event_source ADD COLUMN fixed_point BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE trading_event_source ALTER COLUMN fixed_point SET DEFAULT TRUE;
@@ -0,0 +1,5 @@
+ALTER TABLE markets
+    DROP CONSTRAINT IF EXISTS markets_base_fkey,
+    DROP CONSTRAINT IF EXISTS markets_quote_fkey;
+
+DROP TABLE IF EXISTS assets;
@@ -0,0 +1,20 @@
+-- assets table, every asset the exchange keeps balances of and trades
+CREATE TABLE IF NOT EXISTS assets (
+    symbol VARCHAR(16) PRIMARY KEY CHECK (symbol ~ '^[A-Z]{3,}$'),
+    -- amounts are kept in units of the asset's smallest fraction, e.g. satoshis for BTC
+    decimals SMALLINT NOT NULL CHECK (decimals BETWEEN 0 AND 18),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+SELECT manage_updated_at('assets');
+
+-- ether is kept in gwei rather than wei so realistic amounts fit the ledger's BIGINT columns
+INSERT INTO assets (symbol, decimals) VALUES
+    ('BTC', 8),
+    ('ETH', 9),
+    ('USD', 2);
+
+ALTER TABLE markets
+    ADD CONSTRAINT markets_base_fkey FOREIGN KEY (base) REFERENCES assets(symbol),
+    ADD CONSTRAINT markets_quote_fkey FOREIGN KEY (quote) REFERENCES assets(symbol);